@@ -0,0 +1,40 @@
+//! Records the git commit and build time for the health endpoint. Both can be set with
+//! the `GIT_SHA` and `BUILD_TIME` env vars instead, e.g. in Docker builds where `.git`
+//! isn't available; a missing commit is reported as "unknown" rather than failing.
+
+use std::process::Command;
+
+use chrono::{SecondsFormat, Utc};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=BUILD_TIME");
+
+    // Rerun when HEAD moves, so the commit (and with it the build time) stays current
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/refs/heads", git_dir);
+    }
+
+    let commit = env("GIT_SHA")
+        .or_else(|| git(&["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    let built_at =
+        env("BUILD_TIME").unwrap_or_else(|| Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+
+    println!("cargo:rustc-env=GIT_SHA={}", commit);
+    println!("cargo:rustc-env=BUILD_TIME={}", built_at);
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string()).filter(|value| !value.is_empty())
+}
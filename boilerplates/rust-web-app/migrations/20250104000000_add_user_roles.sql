@@ -0,0 +1,5 @@
+-- Create user role type
+CREATE TYPE user_role AS ENUM ('user', 'admin');
+
+-- Add role to users, defaulting existing and new accounts to regular users
+ALTER TABLE users ADD COLUMN role user_role NOT NULL DEFAULT 'user';
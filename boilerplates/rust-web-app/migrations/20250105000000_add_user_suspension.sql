@@ -0,0 +1,3 @@
+-- Allow admins to temporarily suspend accounts; expiry is enforced by comparing against NOW()
+ALTER TABLE users ADD COLUMN suspended_until TIMESTAMP WITH TIME ZONE;
+ALTER TABLE users ADD COLUMN suspension_reason TEXT;
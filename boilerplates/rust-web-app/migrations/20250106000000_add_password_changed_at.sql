@@ -0,0 +1,2 @@
+-- Record password changes so tokens issued before the change can be rejected
+ALTER TABLE users ADD COLUMN password_changed_at TIMESTAMP WITH TIME ZONE;
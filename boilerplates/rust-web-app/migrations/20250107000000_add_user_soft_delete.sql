@@ -0,0 +1,6 @@
+-- Soft-delete users instead of removing their rows
+ALTER TABLE users ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE;
+
+-- Emails only need to be unique among active accounts so a deleted user can register again
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+CREATE UNIQUE INDEX users_email_active_key ON users(email) WHERE deleted_at IS NULL;
@@ -0,0 +1,2 @@
+-- Support keyset pagination over active users ordered by (created_at, id)
+CREATE INDEX idx_users_created_at_id ON users(created_at DESC, id DESC) WHERE deleted_at IS NULL;
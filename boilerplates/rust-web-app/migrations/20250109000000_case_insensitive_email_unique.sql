@@ -0,0 +1,3 @@
+-- Treat emails that differ only by case as the same address
+DROP INDEX users_email_active_key;
+CREATE UNIQUE INDEX users_email_lower_active_key ON users(lower(email)) WHERE deleted_at IS NULL;
@@ -0,0 +1,17 @@
+-- Public handles, stored normalized (lowercase) so a plain unique index enforces uniqueness
+ALTER TABLE users ADD COLUMN handle VARCHAR(30);
+ALTER TABLE users ADD COLUMN handle_changed_at TIMESTAMP WITH TIME ZONE;
+
+CREATE UNIQUE INDEX users_handle_active_key ON users(handle) WHERE deleted_at IS NULL;
+
+-- Handles released by renames or deleted accounts; they stay quarantined for a while
+-- so nobody can immediately impersonate the previous owner
+CREATE TABLE IF NOT EXISTS handle_history (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    handle VARCHAR(30) NOT NULL,
+    released_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+-- Create index for quarantine lookups by handle
+CREATE INDEX idx_handle_history_handle ON handle_history(handle, released_at);
@@ -0,0 +1,5 @@
+-- Users that haven't been soft-deleted. Queries read and update users through this view
+-- so none of them can forget the deleted_at filter and let a deleted account back in.
+-- `*` is expanded when the view is created: migrations that add columns to users must
+-- recreate it with CREATE OR REPLACE VIEW.
+CREATE VIEW active_users AS SELECT * FROM users WHERE deleted_at IS NULL;
@@ -0,0 +1,49 @@
+use clap::{Parser, Subcommand};
+use reqwest::Url;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Rust web app server and admin tasks")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Start the HTTP server (the default when no command is given)
+    Serve {
+        /// Don't apply pending migrations before starting, e.g. when a separate job runs them
+        #[arg(long)]
+        skip_migrations: bool,
+    },
+    /// Apply pending database migrations and exit
+    Migrate {
+        /// List pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Create a user with the admin role
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        password: String,
+        #[arg(long, default_value = "Admin")]
+        name: String,
+    },
+    /// Run a scripted end-to-end flow against a running instance and print a JSON report
+    SmokeTest {
+        /// Defaults to this config's `server.host` and `server.port`
+        #[arg(long)]
+        base_url: Option<Url>,
+        /// Seconds each request may take before its step fails
+        #[arg(long, default_value_t = 5)]
+        timeout_secs: u64,
+        /// Don't update or delete the disposable account
+        #[arg(long)]
+        skip_destructive: bool,
+        /// Required when `application.environment` is `production`
+        #[arg(long)]
+        allow_production: bool,
+    },
+}
@@ -1,51 +1,1008 @@
+use axum::http::{HeaderName, HeaderValue, Method};
 use config::{Config, ConfigError, Environment, File};
+use ipnet::IpNet;
 use serde::Deserialize;
+use sqlx::postgres::PgConnectOptions;
+use std::{
+    collections::HashMap,
+    net::{AddrParseError, IpAddr},
+    time::Duration,
+};
+
+use crate::utils::{auth::check_jwt_keys, two_factor::SecretCipher};
+
+/// Longest `cache.auth_ttl_secs` allowed. A failed Redis invalidation leaves a stale
+/// suspension or revocation check in place for up to this long.
+const MAX_AUTH_CACHE_TTL_SECS: u64 = 60;
+
+/// Minimum `jwt_secret` length enforced when running in production.
+const MIN_PRODUCTION_SECRET_LEN: usize = 32;
+
+/// Upper bounds for the Argon2 cost settings. Every hash runs on its own blocking
+/// thread, so runaway values would exhaust memory under a burst of logins.
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 20;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+/// Work factors the bcrypt crate accepts.
+const MIN_BCRYPT_COST: u32 = 4;
+const MAX_BCRYPT_COST: u32 = 31;
+
+/// Bounds for `password_policy.min_length`. Below the minimum passwords are too easy to
+/// brute-force; above the maximum the setting is almost certainly a typo.
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Accepted values for `application.environment`.
+const ENVIRONMENTS: &[&str] = &["development", "test", "staging", "production"];
+
+/// Example secrets from the docs and sample configs, refused in production.
+const PLACEHOLDER_SECRETS: &[&str] = &[
+    "secret",
+    "changeme",
+    "change-me",
+    "your-super-secret-jwt-key-change-this-in-production",
+];
+
+/// Settings checked by [`Settings::validate`], whose origin is recorded for error messages.
+const VALIDATED_KEYS: &[&str] = &[
+    "server.host",
+    "server.port",
+    "server.request_timeout_secs",
+    "server.max_body_bytes",
+    "server.trusted_proxies",
+    "rate_limit.requests_per_minute",
+    "rate_limit.burst",
+    "database.url",
+    "database.replica_url",
+    "database.max_connections",
+    "database.min_connections",
+    "database.acquire_timeout_secs",
+    "application.jwt_secret",
+    "application.jwt_secrets",
+    "application.jwt_expiration",
+    "application.environment",
+    "application.argon2_memory_kib",
+    "application.argon2_iterations",
+    "application.argon2_parallelism",
+    "application.bcrypt_cost",
+    "application.password_policy",
+    "cors.allowed_origins",
+    "cors.allowed_methods",
+    "cors.allowed_headers",
+    "cors.allow_credentials",
+    "sentry.dsn",
+    "two_factor.encryption_key",
+    "oauth.google",
+    "oauth.github",
+    "telemetry.otlp_endpoint",
+    "metrics.host",
+    "metrics.port",
+    "cache.auth_ttl_secs",
+];
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub server: ServerSettings,
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub rate_limit: RateLimitSettings,
+    pub handles: HandleSettings,
+    pub logging: LoggingSettings,
+    pub telemetry: TelemetrySettings,
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub redis: RedisSettings,
+    pub cache: CacheSettings,
+    pub smtp: SmtpSettings,
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub security: SecuritySettings,
+    #[serde(default)]
+    pub auth: AuthSettings,
+    #[serde(default)]
+    pub sentry: SentrySettings,
+    pub two_factor: TwoFactorSettings,
+    pub oauth: OAuthSettings,
+    /// Where each validated setting came from, e.g. `env var APP__SERVER__PORT`.
+    #[serde(skip)]
+    sources: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerSettings {
+    /// IPv4 or IPv6 address to listen on, e.g. `127.0.0.1` for a local-only server.
     pub host: String,
     pub port: u16,
+    pub shutdown_timeout_secs: u64,
+    /// Requests still running after this long are answered with 504; health checks are exempt.
+    pub request_timeout_secs: u64,
+    /// Larger request bodies are rejected with 413.
+    pub max_body_bytes: usize,
+    /// Serve HTTPS directly when set; otherwise plain HTTP, e.g. behind a proxy.
+    pub tls: Option<TlsSettings>,
+    /// Addresses or CIDR ranges of the reverse proxies in front of the app. Only
+    /// connections from these have their `X-Forwarded-For` header honoured.
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSettings {
+    /// PEM certificate chain.
+    pub cert_path: String,
+    /// PEM private key.
+    pub key_path: String,
+    /// Also listen for plain HTTP on this port and redirect it to HTTPS.
+    pub redirect_http_port: Option<u16>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DatabaseSettings {
     pub url: String,
+    /// Read replica for queries that tolerate replication lag; reads use the primary
+    /// when unset.
+    pub replica_url: Option<String>,
     pub max_connections: u32,
+    /// Connections kept open even when idle.
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing.
+    pub acquire_timeout_secs: u64,
+    /// Idle connections above `min_connections` are closed after this long.
+    pub idle_timeout_secs: u64,
+    /// Connections are recycled after this long, so a pooler or failover in front of the
+    /// database doesn't keep the app on stale connections.
+    pub max_lifetime_secs: u64,
+    /// How long startup keeps retrying an unreachable database before giving up.
+    pub connect_retry_secs: u64,
+    /// How long `/health/ready` waits for the database before reporting not ready.
+    pub readiness_timeout_ms: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ApplicationSettings {
     pub jwt_secret: String,
+    /// File to read the JWT secret from (e.g. a mounted Kubernetes secret); takes
+    /// precedence over `jwt_secret`.
+    pub jwt_secret_file: Option<String>,
+    /// Secrets for rotation, newest first: the first signs new tokens and every entry
+    /// verifies them. Replaces `jwt_secret` when non-empty.
+    #[serde(default)]
+    pub jwt_secrets: Vec<String>,
     pub jwt_expiration: i64,
+    /// `HS256` signs with the shared secret; `RS256` and `ES256` sign with the private
+    /// key so other services can verify tokens with just the public key.
+    #[serde(default)]
+    pub jwt_algorithm: JwtAlgorithm,
+    /// PEM (PKCS#8) private key for `RS256`/`ES256`.
+    pub jwt_private_key_path: Option<String>,
+    /// PEM public key for `RS256`/`ES256`, published at `/api/auth/jwks.json`.
+    pub jwt_public_key_path: Option<String>,
+    /// Contents of `jwt_private_key_path`, read at startup.
+    #[serde(skip)]
+    pub jwt_private_key: Option<String>,
+    /// Contents of `jwt_public_key_path`, read at startup.
+    #[serde(skip)]
+    pub jwt_public_key: Option<String>,
+    /// Expected `iss` claim; unset skips the check (single-service deployments).
+    pub jwt_issuer: Option<String>,
+    /// Expected `aud` claim; unset skips the check.
+    pub jwt_audience: Option<String>,
+    /// Clock skew tolerated when checking `exp`, in seconds.
+    pub jwt_leeway_secs: u64,
+    pub password_reset_expiration: i64,
+    pub email_verification_expiration: i64,
+    pub require_email_verification: bool,
+    /// Scheme new password hashes use. Hashes in the other scheme still verify and are
+    /// upgraded on the next login, as are hashes with outdated cost settings.
+    pub password_algorithm: PasswordAlgorithm,
+    /// Argon2id memory cost; at least 8 KiB per lane and at most 1 GiB.
+    pub argon2_memory_kib: u32,
+    /// Argon2id passes over memory, 1 to 20.
+    pub argon2_iterations: u32,
+    /// Argon2id lanes, 1 to 16.
+    pub argon2_parallelism: u32,
+    /// bcrypt work factor, 4 to 31.
+    pub bcrypt_cost: u32,
     pub environment: String,
+    /// Shape of error response bodies.
+    pub error_format: ErrorFormat,
+    /// Rules new passwords must meet at registration, reset and change.
+    pub password_policy: PasswordPolicySettings,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    pub require_letter: bool,
+    pub require_digit: bool,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    /// Anything other than a letter or digit counts as a symbol.
+    pub require_symbol: bool,
+    /// Reject passwords containing the local part of the account's email.
+    pub reject_email: bool,
+    /// Reject passwords on the built-in list of the most common ones.
+    pub reject_common: bool,
+    /// Minimum zxcvbn strength score, 0 (weakest) to 4; unset skips the estimate.
+    pub min_score: Option<u8>,
+}
+
+impl ApplicationSettings {
+    /// Secrets tokens may be signed with, the one new tokens are signed with first.
+    pub fn jwt_keys(&self) -> Vec<&str> {
+        if self.jwt_secrets.is_empty() {
+            vec![self.jwt_secret.as_str()]
+        } else {
+            self.jwt_secrets.iter().map(String::as_str).collect()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    /// `{"error", "message", ...}` as `application/json`.
+    #[default]
+    Simple,
+    /// RFC 7807 problem details as `application/problem+json`.
+    Problem,
+    /// The `ApiResponse` envelope with `status: "error"`, so successes and failures share
+    /// one shape.
+    Envelope,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordAlgorithm {
+    /// Argon2id with the `argon2_*` cost settings, the OWASP recommendation.
+    #[default]
+    Argon2id,
+    /// bcrypt with `bcrypt_cost`, for compatibility with hashes from other systems.
+    Bcrypt,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    #[default]
+    HS256,
+    RS256,
+    ES256,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Failed logins for an email from one client IP before lockout.
+    pub login_max_attempts: u32,
+    /// Failed logins for an email from any IP before lockout, against distributed guessing.
+    pub account_max_attempts: u32,
+    pub login_window_secs: u64,
+    pub login_cooldown_secs: u64,
+    /// Sustained rate of requests per client IP to the auth endpoints.
+    pub requests_per_minute: u32,
+    /// Requests a client IP may send to the auth endpoints at once.
+    pub burst: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HandleSettings {
+    /// Minimum time between handle changes.
+    pub change_cooldown_secs: i64,
+    /// How long a released handle stays unavailable to other users.
+    pub quarantine_secs: i64,
+    /// Extra reserved handles on top of the built-in list.
+    pub reserved: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RedisSettings {
+    /// Redis backing the cache; without it each instance caches in memory.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheSettings {
+    /// How long cached profiles are kept.
+    pub ttl_secs: u64,
+    /// How long the suspension and token-revocation state is kept. Only cached in Redis,
+    /// and kept short so a lost invalidation can't keep a revoked token working for long.
+    pub auth_ttl_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CorsSettings {
+    /// Origins allowed to call the API, e.g. `https://app.example.com`; `*` allows any.
+    /// Empty disables cross-origin requests.
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests; `*` allows any.
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests; `*` allows any.
+    pub allowed_headers: Vec<String>,
+    /// Let browsers send cookies and HTTP auth. With `*` entries, the request's own
+    /// origin, method and headers are echoed back instead, since browsers reject `*` here.
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response.
+    pub max_age_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SecuritySettings {
+    /// Send `Strict-Transport-Security` with this max age in seconds. Only enable it when
+    /// the app is reached over HTTPS: browsers then refuse plain HTTP to the host.
+    pub hsts_max_age: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthSettings {
+    /// Set the token as an HttpOnly `access_token` cookie on login and registration, and
+    /// accept it from requests without an `Authorization` header.
+    pub cookie_enabled: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SentrySettings {
+    /// Sentry project DSN; unexpected errors and panics are reported when set.
+    pub dsn: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TwoFactorSettings {
+    /// Hex-encoded 32-byte key TOTP secrets are encrypted with at rest. Two-factor
+    /// endpoints answer 503 while it is unset.
+    pub encryption_key: Option<String>,
+    /// Name authenticator apps show next to the account.
+    pub issuer: String,
+    /// How long a login that passed the password check waits for its code.
+    pub challenge_ttl_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OAuthSettings {
+    /// Sign in with Google; `/api/auth/oauth/google/*` answers 404 while unset.
+    pub google: Option<OAuthProviderSettings>,
+    /// Sign in with GitHub; `/api/auth/oauth/github/*` answers 404 while unset.
+    pub github: Option<OAuthProviderSettings>,
+    /// How long a login sent to the provider may take to come back.
+    pub state_ttl_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct OAuthProviderSettings {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Callback URL registered with the provider, i.e. this app's
+    /// `/api/auth/oauth/{provider}/callback` as clients reach it.
+    pub redirect_url: String,
+    /// Override the provider's endpoints, e.g. for GitHub Enterprise or a test double.
+    pub authorize_url: Option<String>,
+    pub token_url: Option<String>,
+    /// Base URL of the API the profile is fetched from.
+    pub api_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SmtpSettings {
+    /// SMTP relay for outgoing email; without it emails are only logged.
+    pub host: Option<String>,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// `From` address, e.g. `Example <no-reply@example.com>`.
+    pub from: String,
+    pub tls: SmtpTls,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS, usually on port 587.
+    Starttls,
+    /// TLS from the first byte, usually on port 465.
+    Implicit,
+    /// Plain text, for local relays such as Mailpit.
+    None,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum LogFormat {
+    /// Human-readable output for local development.
+    Pretty,
+    /// One JSON object per line for log shippers.
+    Json,
+}
+
+impl ServerSettings {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs)
+    }
+
+    /// The trusted proxies as networks; a bare address is a single-host network. Errs
+    /// with the first entry that is neither.
+    pub fn trusted_proxies(&self) -> Result<Vec<IpNet>, String> {
+        self.trusted_proxies
+            .iter()
+            .map(|proxy| {
+                let proxy = proxy.trim();
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| proxy.to_string())
+            })
+            .collect()
+    }
+
+    /// The configured host as an IP address. IPv6 addresses may be written with or
+    /// without brackets.
+    pub fn ip(&self) -> Result<IpAddr, AddrParseError> {
+        parse_host(&self.host)
+    }
+}
+
+impl DatabaseSettings {
+    pub fn readiness_timeout(&self) -> Duration {
+        Duration::from_millis(self.readiness_timeout_ms)
+    }
+}
+
+impl CacheSettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+
+    pub fn auth_ttl(&self) -> Duration {
+        Duration::from_secs(self.auth_ttl_secs)
+    }
+}
+
+impl TryFrom<String> for LogFormat {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!(
+                "invalid logging.format {:?}: expected \"pretty\" or \"json\"",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LoggingSettings {
+    pub format: LogFormat,
+    /// `EnvFilter` directives overriding `RUST_LOG`, e.g. `info,sqlx=warn`.
+    pub level: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TelemetrySettings {
+    /// Base URL of an OTLP/HTTP collector such as Jaeger or Tempo, e.g.
+    /// `http://localhost:4318`. Spans are only exported when set.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` the exported spans are reported under.
+    pub service_name: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct MetricsSettings {
+    /// Address of the internal listener serving `/metrics`. Keep it off public interfaces:
+    /// the metrics describe traffic per route.
+    pub host: String,
+    pub port: u16,
+}
+
+impl MetricsSettings {
+    pub fn ip(&self) -> Result<IpAddr, AddrParseError> {
+        parse_host(&self.host)
+    }
+}
+
+/// Parses a listen address, with or without the brackets around an IPv6 address.
+fn parse_host(host: &str) -> Result<IpAddr, AddrParseError> {
+    let host = host.trim();
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+        .parse()
+}
+
+/// Reads the file the `key` setting points at; an unreadable file fails startup.
+fn read_key_file(key: &str, path: &str) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| ConfigError::Message(format!("{} {:?} could not be read: {}", key, path, e)))
+}
+
+fn is_http_url(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
 }
 
 impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
         let run_mode = std::env::var("RUN_MODE").unwrap_or_else(|_| "development".into());
 
-        let s = Config::builder()
+        let builder = Config::builder()
             // Start with default values
             .set_default("server.host", "0.0.0.0")?
             .set_default("server.port", 8080)?
+            .set_default("server.shutdown_timeout_secs", 30)?
+            .set_default("server.request_timeout_secs", 30)?
+            .set_default("server.max_body_bytes", 1_048_576)?
+            .set_default("server.trusted_proxies", Vec::<String>::new())?
             .set_default("database.max_connections", 5)?
+            .set_default("database.min_connections", 0)?
+            .set_default("database.acquire_timeout_secs", 30)?
+            .set_default("database.idle_timeout_secs", 600)?
+            .set_default("database.max_lifetime_secs", 1800)?
+            .set_default("database.connect_retry_secs", 30)?
+            .set_default("database.readiness_timeout_ms", 500)?
+            .set_default("application.jwt_secret", "")?
             .set_default("application.jwt_expiration", 3600)?
+            .set_default("application.jwt_leeway_secs", 60)?
+            .set_default("application.password_reset_expiration", 1800)?
+            .set_default("application.email_verification_expiration", 86400)?
+            .set_default("application.require_email_verification", false)?
+            .set_default("application.argon2_memory_kib", 19456)?
+            .set_default("application.argon2_iterations", 2)?
+            .set_default("application.argon2_parallelism", 1)?
+            .set_default("application.password_algorithm", "argon2id")?
+            .set_default("application.bcrypt_cost", 12)?
             .set_default("application.environment", "development")?
+            .set_default("application.error_format", "simple")?
+            .set_default("application.password_policy.min_length", 8)?
+            .set_default("application.password_policy.require_letter", true)?
+            .set_default("application.password_policy.require_digit", true)?
+            .set_default("application.password_policy.require_lowercase", false)?
+            .set_default("application.password_policy.require_uppercase", false)?
+            .set_default("application.password_policy.require_symbol", false)?
+            .set_default("application.password_policy.reject_email", true)?
+            .set_default("application.password_policy.reject_common", true)?
+            .set_default("rate_limit.login_max_attempts", 5)?
+            .set_default("rate_limit.account_max_attempts", 20)?
+            .set_default("rate_limit.login_window_secs", 900)?
+            .set_default("rate_limit.login_cooldown_secs", 900)?
+            .set_default("rate_limit.requests_per_minute", 30)?
+            .set_default("rate_limit.burst", 10)?
+            .set_default("handles.change_cooldown_secs", 2_592_000)?
+            .set_default("handles.quarantine_secs", 7_776_000)?
+            .set_default("handles.reserved", Vec::<String>::new())?
+            .set_default("cache.ttl_secs", 300)?
+            .set_default("cache.auth_ttl_secs", 5)?
+            .set_default("telemetry.service_name", "rust-web-app")?
+            .set_default("metrics.host", "127.0.0.1")?
+            .set_default("metrics.port", 9090)?
+            .set_default("smtp.port", 587)?
+            .set_default("smtp.from", "no-reply@localhost")?
+            .set_default("smtp.tls", "starttls")?
+            .set_default("cors.allowed_origins", Vec::<String>::new())?
+            .set_default(
+                "cors.allowed_methods",
+                vec!["GET", "POST", "PUT", "PATCH", "DELETE"],
+            )?
+            .set_default(
+                "cors.allowed_headers",
+                vec![
+                    "authorization",
+                    "content-type",
+                    "x-request-id",
+                    "x-csrf-token",
+                ],
+            )?
+            .set_default("cors.allow_credentials", false)?
+            .set_default("cors.max_age_secs", 3600)?
+            .set_default("two_factor.issuer", "rust-web-app")?
+            .set_default("two_factor.challenge_ttl_secs", 300)?
+            .set_default("oauth.state_ttl_secs", 600)?
             // Load configuration from file (if exists)
             .add_source(File::with_name("config/default").required(false))
             .add_source(File::with_name(&format!("config/{}", run_mode)).required(false))
             // Override with environment variables
-            .add_source(Environment::with_prefix("APP").separator("__"))
+            .add_source(
+                Environment::with_prefix("APP")
+                    .separator("__")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("application.jwt_secrets")
+                    .with_list_parse_key("server.trusted_proxies")
+                    .with_list_parse_key("handles.reserved")
+                    .with_list_parse_key("cors.allowed_origins")
+                    .with_list_parse_key("cors.allowed_methods")
+                    .with_list_parse_key("cors.allowed_headers"),
+            );
+
+        // Log format defaults to JSON in production and pretty output elsewhere
+        let environment = builder
+            .build_cloned()?
+            .get_string("application.environment")?;
+        let default_log_format = if environment == "production" {
+            "json"
+        } else {
+            "pretty"
+        };
+        let s = builder
+            .set_default("logging.format", default_log_format)?
             .build()?;
 
-        s.try_deserialize()
+        let sources = VALIDATED_KEYS
+            .iter()
+            .map(|key| {
+                // Values deserialized through `get` lose their origin, tables keep it
+                let (section, field) = key.split_once('.').unwrap_or_default();
+                let value = s.get_table(section).ok().and_then(|mut t| t.remove(field));
+                let origin = value.and_then(|value| {
+                    value.origin().map(|origin| match origin {
+                        // The label the config crate gives environment values
+                        "the environment" => {
+                            format!("env var APP__{}", key.to_uppercase().replace('.', "__"))
+                        }
+                        file => format!("file {}", file),
+                    })
+                });
+                (
+                    key.to_string(),
+                    origin.unwrap_or_else(|| "default".to_string()),
+                )
+            })
+            .collect();
+
+        let mut settings: Settings = s.try_deserialize()?;
+        settings.sources = sources;
+
+        if let Some(path) = &settings.application.jwt_secret_file {
+            let secret = read_key_file("application.jwt_secret_file", path)?;
+            settings.application.jwt_secret = secret.trim_end_matches(['\r', '\n']).to_string();
+            settings.sources.insert(
+                "application.jwt_secret".to_string(),
+                format!("application.jwt_secret_file {}", path),
+            );
+        }
+
+        let app = &mut settings.application;
+        if let Some(path) = &app.jwt_private_key_path {
+            app.jwt_private_key = Some(read_key_file("application.jwt_private_key_path", path)?);
+        }
+        if let Some(path) = &app.jwt_public_key_path {
+            app.jwt_public_key = Some(read_key_file("application.jwt_public_key_path", path)?);
+        }
+
+        Ok(settings)
+    }
+
+    /// Checks semantic constraints that deserialization can't express, naming the
+    /// offending setting and where it was set in the error.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, problem: String| {
+            Err(ConfigError::Message(format!(
+                "{} (from {}) {}",
+                key,
+                self.source(key),
+                problem
+            )))
+        };
+        let app = &self.application;
+
+        if let Err(e) = self.server.ip() {
+            return invalid(
+                "server.host",
+                format!("must be an IPv4 or IPv6 address ({})", e),
+            );
+        }
+
+        if self.server.port == 0 {
+            return invalid("server.port", "must be non-zero".into());
+        }
+
+        if let Err(e) = self.metrics.ip() {
+            return invalid(
+                "metrics.host",
+                format!("must be an IPv4 or IPv6 address ({})", e),
+            );
+        }
+
+        if self.metrics.port == 0 || self.metrics.port == self.server.port {
+            return invalid(
+                "metrics.port",
+                "must be non-zero and differ from server.port".into(),
+            );
+        }
+
+        if self.cache.auth_ttl_secs > MAX_AUTH_CACHE_TTL_SECS {
+            return invalid(
+                "cache.auth_ttl_secs",
+                format!("must be at most {}", MAX_AUTH_CACHE_TTL_SECS),
+            );
+        }
+
+        if self.server.request_timeout_secs == 0 {
+            return invalid("server.request_timeout_secs", "must be non-zero".into());
+        }
+
+        if self.rate_limit.requests_per_minute == 0 {
+            return invalid("rate_limit.requests_per_minute", "must be non-zero".into());
+        }
+
+        if self.rate_limit.burst == 0 {
+            return invalid("rate_limit.burst", "must be non-zero".into());
+        }
+
+        if self.server.max_body_bytes == 0 {
+            return invalid("server.max_body_bytes", "must be non-zero".into());
+        }
+
+        if let Err(proxy) = self.server.trusted_proxies() {
+            return invalid(
+                "server.trusted_proxies",
+                format!("contains {:?}, expected an IP address or CIDR range", proxy),
+            );
+        }
+
+        if let Some(redirect_port) = self.server.tls.as_ref().and_then(|t| t.redirect_http_port) {
+            if redirect_port == self.server.port {
+                return Err(ConfigError::Message(format!(
+                    "server.tls.redirect_http_port must differ from server.port ({})",
+                    self.server.port
+                )));
+            }
+        }
+
+        // Don't echo the URLs themselves, they usually contain a password
+        let urls = std::iter::once(("database.url", &self.database.url)).chain(
+            self.database
+                .replica_url
+                .as_ref()
+                .map(|url| ("database.replica_url", url)),
+        );
+        for (key, url) in urls {
+            if !url.starts_with("postgres://") && !url.starts_with("postgresql://") {
+                return invalid(key, "must start with postgres:// or postgresql://".into());
+            }
+            if let Err(e) = url.parse::<PgConnectOptions>() {
+                return invalid(key, format!("is not a valid Postgres URL: {}", e));
+            }
+        }
+
+        if self.database.max_connections < 1 {
+            return invalid("database.max_connections", "must be at least 1".into());
+        }
+
+        if self.database.min_connections > self.database.max_connections {
+            return invalid(
+                "database.min_connections",
+                format!(
+                    "must not exceed database.max_connections ({})",
+                    self.database.max_connections
+                ),
+            );
+        }
+
+        if self.database.acquire_timeout_secs == 0 {
+            return invalid("database.acquire_timeout_secs", "must be non-zero".into());
+        }
+
+        if !(1..=MAX_ARGON2_PARALLELISM).contains(&app.argon2_parallelism) {
+            return invalid(
+                "application.argon2_parallelism",
+                format!("must be between 1 and {}", MAX_ARGON2_PARALLELISM),
+            );
+        }
+
+        if !(1..=MAX_ARGON2_ITERATIONS).contains(&app.argon2_iterations) {
+            return invalid(
+                "application.argon2_iterations",
+                format!("must be between 1 and {}", MAX_ARGON2_ITERATIONS),
+            );
+        }
+
+        let min_memory_kib = 8 * app.argon2_parallelism;
+        if !(min_memory_kib..=MAX_ARGON2_MEMORY_KIB).contains(&app.argon2_memory_kib) {
+            return invalid(
+                "application.argon2_memory_kib",
+                format!(
+                    "must be between {} (8 per lane) and {}",
+                    min_memory_kib, MAX_ARGON2_MEMORY_KIB
+                ),
+            );
+        }
+
+        if !(MIN_BCRYPT_COST..=MAX_BCRYPT_COST).contains(&app.bcrypt_cost) {
+            return invalid(
+                "application.bcrypt_cost",
+                format!(
+                    "must be between {} and {}",
+                    MIN_BCRYPT_COST, MAX_BCRYPT_COST
+                ),
+            );
+        }
+
+        let policy = &app.password_policy;
+        if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&policy.min_length) {
+            return invalid(
+                "application.password_policy",
+                format!(
+                    "min_length must be between {} and {}",
+                    MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+                ),
+            );
+        }
+
+        if policy.min_score.is_some_and(|score| score > 4) {
+            return invalid(
+                "application.password_policy",
+                "min_score must be between 0 and 4".into(),
+            );
+        }
+
+        if !ENVIRONMENTS.contains(&app.environment.as_str()) {
+            return invalid(
+                "application.environment",
+                format!(
+                    "is {:?}, expected one of {:?}",
+                    app.environment, ENVIRONMENTS
+                ),
+            );
+        }
+
+        let cors = &self.cors;
+        for origin in cors.allowed_origins.iter().filter(|o| *o != "*") {
+            let valid = origin.parse::<HeaderValue>().is_ok()
+                && (origin.starts_with("http://") || origin.starts_with("https://"))
+                && !origin.ends_with('/');
+            if !valid {
+                return invalid(
+                    "cors.allowed_origins",
+                    format!(
+                        "contains {:?}, expected \"*\" or an origin such as \"https://app.example.com\"",
+                        origin
+                    ),
+                );
+            }
+        }
+        if let Some(method) = cors
+            .allowed_methods
+            .iter()
+            .find(|m| *m != "*" && m.parse::<Method>().is_err())
+        {
+            return invalid(
+                "cors.allowed_methods",
+                format!("contains invalid method {:?}", method),
+            );
+        }
+        if let Some(header) = cors
+            .allowed_headers
+            .iter()
+            .find(|h| *h != "*" && h.parse::<HeaderName>().is_err())
+        {
+            return invalid(
+                "cors.allowed_headers",
+                format!("contains invalid header name {:?}", header),
+            );
+        }
+
+        // Each rotation secret is held to the same rules as a single jwt_secret
+        let secrets_key = if app.jwt_secrets.is_empty() {
+            "application.jwt_secret"
+        } else {
+            "application.jwt_secrets"
+        };
+        let uses_secret = app.jwt_algorithm == JwtAlgorithm::HS256;
+        if uses_secret && app.jwt_keys().iter().any(|secret| secret.trim().is_empty()) {
+            return invalid(secrets_key, "must not be empty".into());
+        }
+
+        // Parse the key pair now so bad key material fails startup, not the first login
+        if !uses_secret {
+            for (key, pem) in [
+                ("application.jwt_private_key_path", &app.jwt_private_key),
+                ("application.jwt_public_key_path", &app.jwt_public_key),
+            ] {
+                if pem.is_none() {
+                    return invalid(
+                        key,
+                        format!("must be set when jwt_algorithm is {:?}", app.jwt_algorithm),
+                    );
+                }
+            }
+            if let Err(e) = check_jwt_keys(app) {
+                return invalid("application.jwt_private_key_path", e.to_string());
+            }
+        }
+
+        if app.jwt_expiration <= 0 {
+            return invalid(
+                "application.jwt_expiration",
+                "must be greater than 0".into(),
+            );
+        }
+
+        // Don't echo the DSN, it contains the project's key
+        if let Some(dsn) = &self.sentry.dsn {
+            if let Err(e) = dsn.parse::<sentry::types::Dsn>() {
+                return invalid("sentry.dsn", format!("is not a valid Sentry DSN: {}", e));
+            }
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            if !is_http_url(endpoint) {
+                return invalid(
+                    "telemetry.otlp_endpoint",
+                    "must be an absolute http(s) URL".into(),
+                );
+            }
+        }
+
+        if let Some(key) = &self.two_factor.encryption_key {
+            if let Err(e) = SecretCipher::new(key) {
+                return invalid("two_factor.encryption_key", e);
+            }
+        }
+
+        for (key, provider) in [
+            ("oauth.google", &self.oauth.google),
+            ("oauth.github", &self.oauth.github),
+        ] {
+            let Some(provider) = provider else { continue };
+            if provider.client_id.trim().is_empty() || provider.client_secret.trim().is_empty() {
+                return invalid(key, "needs a client_id and client_secret".into());
+            }
+            let urls = [
+                Some(&provider.redirect_url),
+                provider.authorize_url.as_ref(),
+                provider.token_url.as_ref(),
+                provider.api_url.as_ref(),
+            ];
+            if urls.into_iter().flatten().any(|url| !is_http_url(url)) {
+                return invalid(key, "URLs must be absolute http(s) URLs".into());
+            }
+        }
+
+        if self.is_production() {
+            if uses_secret {
+                let keys = app.jwt_keys();
+                if keys.iter().any(|secret| {
+                    PLACEHOLDER_SECRETS.contains(&secret.trim().to_lowercase().as_str())
+                }) {
+                    return invalid(
+                        secrets_key,
+                        "is a placeholder value and must be replaced in production".into(),
+                    );
+                }
+
+                if keys
+                    .iter()
+                    .any(|secret| secret.len() < MIN_PRODUCTION_SECRET_LEN)
+                {
+                    return invalid(
+                        secrets_key,
+                        format!(
+                            "must be at least {} characters in production",
+                            MIN_PRODUCTION_SECRET_LEN
+                        ),
+                    );
+                }
+            }
+
+            // Browsers ignore credentialed responses to wildcard origins, so this is
+            // always a mistake; outside production the request origin is echoed instead
+            if cors.allow_credentials && cors.allowed_origins.iter().any(|o| o == "*") {
+                return invalid(
+                    "cors.allow_credentials",
+                    "cannot be combined with a \"*\" in cors.allowed_origins in production".into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describes where `key` was set, for error messages.
+    fn source(&self, key: &str) -> &str {
+        self.sources.get(key).map_or("default", String::as_str)
+    }
+
+    pub fn is_production(&self) -> bool {
+        self.application.environment == "production"
     }
 
     pub fn database_url(&self) -> String {
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use sqlx::{
+    migrate::{Migration, Migrator},
+    postgres::{PgConnectOptions, PgPoolOptions},
+    Connection, PgConnection, PgPool,
+};
+
+use crate::config::DatabaseSettings;
+
+/// Migrations embedded in the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Delay before the first connection retry; it doubles up to [`CONNECT_RETRY_MAX_DELAY`].
+const CONNECT_RETRY_INITIAL_DELAY: Duration = Duration::from_millis(250);
+const CONNECT_RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// Pool options from settings, shared by the server, the CLI commands and the tests.
+pub fn pool_options(settings: &DatabaseSettings) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(settings.max_connections)
+        .min_connections(settings.min_connections)
+        .acquire_timeout(Duration::from_secs(settings.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(settings.idle_timeout_secs))
+        .max_lifetime(Duration::from_secs(settings.max_lifetime_secs))
+}
+
+/// The primary pool plus an optional read replica. Queries that can tolerate replication
+/// lag go through [`Db::read`]; writes, and reads that must see a write just made, use
+/// [`Db::write`].
+#[derive(Clone)]
+pub struct Db {
+    primary: PgPool,
+    replica: Option<PgPool>,
+}
+
+impl Db {
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        Self { primary, replica }
+    }
+
+    /// The replica when one is configured, otherwise the primary.
+    pub fn read(&self) -> &PgPool {
+        self.replica.as_ref().unwrap_or(&self.primary)
+    }
+
+    pub fn write(&self) -> &PgPool {
+        &self.primary
+    }
+
+    pub fn replica(&self) -> Option<&PgPool> {
+        self.replica.as_ref()
+    }
+
+    pub async fn close(&self) {
+        self.primary.close().await;
+        if let Some(replica) = &self.replica {
+            replica.close().await;
+        }
+    }
+}
+
+/// Connects to the primary database, retrying with exponential backoff while it's unreachable
+/// (e.g. still starting up under docker-compose) for up to `connect_retry_secs`. Errors
+/// retrying can't fix, such as bad credentials, are returned immediately.
+pub async fn connect(settings: &DatabaseSettings) -> sqlx::Result<PgPool> {
+    connect_to(&settings.url, settings).await
+}
+
+/// Connects to `replica_url`, if set, with the same pool settings and retries as the
+/// primary.
+pub async fn connect_replica(settings: &DatabaseSettings) -> sqlx::Result<Option<PgPool>> {
+    match &settings.replica_url {
+        Some(url) => connect_to(url, settings).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+async fn connect_to(url: &str, settings: &DatabaseSettings) -> sqlx::Result<PgPool> {
+    let options: PgConnectOptions = url.parse()?;
+    let budget = Duration::from_secs(settings.connect_retry_secs);
+    let acquire_timeout = Duration::from_secs(settings.acquire_timeout_secs);
+    let started = Instant::now();
+    let mut delay = CONNECT_RETRY_INITIAL_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        // Probe with a single connection: the pool retries internally until its acquire
+        // timeout, which would hide the attempts and overshoot the budget
+        let probe = PgConnection::connect_with(&options);
+        let error = match tokio::time::timeout(acquire_timeout, probe).await {
+            Ok(Ok(connection)) => {
+                let _ = connection.close().await;
+                return pool_options(settings).connect_with(options).await;
+            }
+            Ok(Err(e)) if is_transient(&e) => e,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => sqlx::Error::PoolTimedOut,
+        };
+
+        // Jitter keeps replicas that started together from retrying in lockstep
+        let wait = delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        if started.elapsed() + wait > budget {
+            return Err(error);
+        }
+
+        tracing::warn!(
+            attempt,
+            "Database unavailable, retrying in {}ms: {}",
+            wait.as_millis(),
+            error
+        );
+        tokio::time::sleep(wait).await;
+        delay = (delay * 2).min(CONNECT_RETRY_MAX_DELAY);
+        attempt += 1;
+    }
+}
+
+/// Whether a connection error may go away on its own.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // cannot_connect_now: the server is starting up or shutting down
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("57P03"),
+        _ => false,
+    }
+}
+
+/// Embedded migrations, split by whether the database has applied them.
+#[derive(Default)]
+pub struct MigrationStatus {
+    pub applied: Vec<&'static Migration>,
+    pub pending: Vec<&'static Migration>,
+}
+
+/// Compares the embedded migrations with the database's bookkeeping table. Only reads,
+/// so it's safe for dry runs and health checks.
+pub async fn migration_status(pool: &PgPool) -> sqlx::Result<MigrationStatus> {
+    let table_exists =
+        sqlx::query_scalar::<_, bool>("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    let applied_versions: HashSet<i64> = if table_exists {
+        sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    let (applied, pending) = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .partition(|m| applied_versions.contains(&m.version));
+
+    Ok(MigrationStatus { applied, pending })
+}
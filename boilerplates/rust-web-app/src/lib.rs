@@ -0,0 +1,115 @@
+pub mod config;
+pub mod db;
+pub mod middleware;
+pub mod models;
+pub mod repositories;
+pub mod routes;
+pub mod smoke;
+pub mod telemetry;
+pub mod utils;
+
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    Router,
+};
+use sentry::integrations::tower::{NewSentryLayer, SentryHttpLayer};
+use tower_http::{
+    catch_panic::CatchPanicLayer, compression::CompressionLayer, limit::RequestBodyLimitLayer,
+    timeout::TimeoutLayer, trace::TraceLayer,
+};
+
+use crate::{
+    config::Settings,
+    middleware::{
+        catch_panic,
+        client_ip::resolve_client_ip,
+        cors::cors_layer,
+        error_context::{error_context, ErrorContext},
+        fallback,
+        metrics::track_metrics,
+        request_id,
+        security_headers::{hsts_header, security_headers},
+        trace::{make_span, RecordResponse},
+    },
+    repositories::{ApiKeyRepository, OAuthRepository, TwoFactorRepository, UserRepository},
+    utils::{auth::JwtKeys, cache::Cache, mailer::Mailer, rate_limit::LoginRateLimiter},
+};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: db::Db,
+    pub users: Arc<dyn UserRepository>,
+    pub two_factor: TwoFactorRepository,
+    pub api_keys: ApiKeyRepository,
+    pub oauth: OAuthRepository,
+    pub config: Settings,
+    /// The JWT keys, parsed from `config` at startup.
+    pub jwt: Arc<JwtKeys>,
+    pub mailer: Arc<dyn Mailer>,
+    pub login_limiter: Arc<LoginRateLimiter>,
+    pub cache: Arc<dyn Cache>,
+    /// When the process started serving, for the uptime reported by `/health`.
+    pub started_at: Instant,
+}
+
+/// Builds the application router with all routes and middleware. Shared by `main` and
+/// the integration tests; the metrics route is mounted separately by the caller.
+pub fn build_app(state: AppState) -> Router {
+    let server = state.config.server.clone();
+    let cors = cors_layer(&state.config.cors);
+    let hsts = hsts_header(&state.config.security);
+    // Checked by `Settings::validate`
+    let trusted_proxies = Arc::new(server.trusted_proxies().unwrap_or_default());
+    let error_context_state = ErrorContext {
+        expose_details: !state.config.is_production(),
+        format: state.config.application.error_format,
+        path: String::new(),
+    };
+
+    let routes = Router::new()
+        .nest("/api", routes::api_routes(&state.config))
+        .merge(routes::docs_routes())
+        // Added before the health routes so slow probes report the real problem
+        .layer(TimeoutLayer::new(server.request_timeout()))
+        .nest("/health", routes::health_routes())
+        .fallback(fallback::not_found)
+        .layer(axum::middleware::from_fn(track_metrics))
+        .with_state(state);
+
+    // Layers on a router only wrap its individual routes, and axum adds the `Allow`
+    // header to 405s outside of them. Wrapping the finished router lets the 405 handler
+    // see that header.
+    Router::new()
+        .fallback_service(routes)
+        // Replaces axum's own 2 MB JSON limit with the configured one
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(server.max_body_bytes))
+        .layer(axum::middleware::from_fn(fallback::json_errors))
+        // Inside the trace and request id layers so panics are logged as a 500 with an id
+        .layer(CatchPanicLayer::custom(catch_panic::handle_panic))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_span)
+                .on_response(RecordResponse::default()),
+        )
+        .layer(axum::middleware::from_fn_with_state(hsts, security_headers))
+        .layer(CompressionLayer::new())
+        .layer(cors)
+        // Records the method, URL and headers on a per-request Sentry hub, so scope
+        // changes such as the user stay with the request
+        .layer(SentryHttpLayer::new())
+        .layer(NewSentryLayer::<Request>::new_from_top())
+        // Wraps everything that can render an error, including panics and rejections
+        .layer(axum::middleware::from_fn_with_state(
+            error_context_state,
+            error_context,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            trusted_proxies,
+            resolve_client_ip,
+        ))
+        // Outermost so the id is assigned before the trace span is created
+        .layer(axum::middleware::from_fn(request_id::request_id))
+}
@@ -1,84 +1,514 @@
-mod config;
-mod middleware;
-mod models;
-mod routes;
-mod utils;
-
-use anyhow::Result;
-use axum::Router;
-use sqlx::postgres::PgPoolOptions;
-use std::net::SocketAddr;
-use tower_http::{
-    compression::CompressionLayer,
-    cors::CorsLayer,
-    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+mod cli;
+
+use anyhow::Context;
+use axum::{extract::Host, http::Uri, response::Redirect, Router};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use clap::Parser;
+use reqwest::Url;
+use sqlx::PgPool;
+use std::{
+    future::{Future, IntoFuture},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{signal, sync::watch};
+use validator::ValidateArgs;
+
+use rust_web_app::{
+    build_app,
+    config::{ServerSettings, Settings},
+    db::{self, Db},
+    models::{CreateUserRequest, UserRole},
+    repositories::{
+        ApiKeyRepository, NewUser, OAuthRepository, PgUserRepository, TwoFactorRepository,
+        UserRepository,
+    },
+    routes,
+    smoke::{self, SmokeTestOptions},
+    telemetry,
+    utils::{
+        auth::{hash_password, JwtKeys},
+        cache::{Cache, MemoryCache, RedisCache},
+        mailer::{Mailer, NoopMailer, SmtpMailer},
+        password_policy::PasswordContext,
+        rate_limit::LoginRateLimiter,
+    },
+    AppState,
 };
-use tracing::Level;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::config::Settings;
+use crate::cli::{Cli, Command};
+
+// Exit codes follow sysexits(3) so CI jobs and init containers can tell failures apart.
+// Invalid command-line usage exits with 2 (from clap).
+const EXIT_FAILURE: u8 = 1;
+const EXIT_DATA_ERROR: u8 = 65;
+const EXIT_UNAVAILABLE: u8 = 69;
+const EXIT_CONFIG: u8 = 78;
+
+/// An error paired with the process exit code it should produce.
+struct Failure {
+    code: u8,
+    error: anyhow::Error,
+}
+
+impl<E: Into<anyhow::Error>> From<E> for Failure {
+    fn from(error: E) -> Self {
+        Failure {
+            code: EXIT_FAILURE,
+            error: error.into(),
+        }
+    }
+}
+
+trait ExitCodeExt<T> {
+    fn exit_code(self, code: u8) -> Result<T, Failure>;
+}
 
-#[derive(Clone)]
-pub struct AppState {
-    pub db: sqlx::PgPool,
-    pub config: Settings,
+impl<T, E: Into<anyhow::Error>> ExitCodeExt<T> for Result<T, E> {
+    fn exit_code(self, code: u8) -> Result<T, Failure> {
+        self.map_err(|e| Failure {
+            code,
+            error: e.into(),
+        })
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rust_web_app=debug,tower_http=debug".into()),
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure { code, error }) => {
+            eprintln!("Error: {:#}", error);
+            ExitCode::from(code)
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Failure> {
+    // Load configuration; tracing depends on it, so this happens first
+    let settings = Settings::new().exit_code(EXIT_CONFIG)?;
+    let command = cli.command.unwrap_or(Command::Serve {
+        skip_migrations: false,
+    });
+
+    // Only a client of the target, and its report is all it writes to stdout
+    if let Command::SmokeTest {
+        base_url,
+        timeout_secs,
+        skip_destructive,
+        allow_production,
+    } = command
+    {
+        return smoke_test(
+            settings,
+            base_url,
+            Duration::from_secs(timeout_secs),
+            skip_destructive,
+            allow_production,
         )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+        .await;
+    }
 
-    // Load configuration
-    let settings = Settings::new()?;
+    // Initialize tracing. Spans still buffered for the collector are flushed when the
+    // guard is dropped at the end of the run.
+    let tracer = telemetry::init_tracer(&settings.telemetry).exit_code(EXIT_CONFIG)?;
+    telemetry::init(&settings.logging, tracer.as_ref()).exit_code(EXIT_CONFIG)?;
     tracing::info!("Configuration loaded successfully");
 
-    // Setup database connection pool
-    let db_pool = PgPoolOptions::new()
-        .max_connections(settings.database.max_connections)
-        .connect(&settings.database_url())
-        .await?;
+    match command {
+        Command::Serve { skip_migrations } => serve(settings, skip_migrations).await,
+        Command::Migrate { dry_run } => migrate(settings, dry_run).await,
+        Command::CreateAdmin {
+            email,
+            password,
+            name,
+        } => create_admin(settings, email, password, name).await,
+        Command::SmokeTest { .. } => unreachable!("handled before tracing is initialized"),
+    }
+}
+
+async fn connect(settings: &Settings) -> Result<PgPool, Failure> {
+    let db_pool = db::connect(&settings.database)
+        .await
+        .context("Failed to connect to the database")
+        .exit_code(EXIT_UNAVAILABLE)?;
 
     tracing::info!("Database connection established");
+    Ok(db_pool)
+}
+
+async fn migrate(settings: Settings, dry_run: bool) -> Result<(), Failure> {
+    let db_pool = connect(&settings).await?;
+
+    if dry_run {
+        // Read the bookkeeping table directly so a dry run never writes anything
+        let pending = db::migration_status(&db_pool).await?.pending;
+
+        if pending.is_empty() {
+            println!("No pending migrations");
+        }
+        for migration in pending {
+            println!("Pending: {} {}", migration.version, migration.description);
+        }
+    } else {
+        db::MIGRATOR.run(&db_pool).await?;
+        tracing::info!("Database migrations completed");
+    }
+
+    db_pool.close().await;
+    Ok(())
+}
+
+async fn create_admin(
+    settings: Settings,
+    email: String,
+    password: String,
+    name: String,
+) -> Result<(), Failure> {
+    // Apply the same rules as self-service registration
+    let request = CreateUserRequest {
+        email,
+        password,
+        name,
+    };
+    request
+        .validate_with_args(&PasswordContext {
+            policy: &settings.application.password_policy,
+            email: &request.email,
+        })
+        .exit_code(EXIT_DATA_ERROR)?;
+
+    let db_pool = connect(&settings).await?;
+    let users = PgUserRepository::new(Db::new(db_pool.clone(), None));
+
+    if users.email_in_use(&request.email, None).await? {
+        return Err(anyhow::anyhow!(
+            "A user with email {} already exists",
+            request.email
+        ))
+        .exit_code(EXIT_DATA_ERROR);
+    }
+
+    let password_hash = hash_password(&request.password, &settings.application).await?;
+
+    let (user, verification_token) = users
+        .create(
+            NewUser {
+                email: request.email,
+                password_hash,
+                name: request.name,
+            },
+            settings.application.email_verification_expiration,
+        )
+        .await?;
+    users.set_role(user.id, UserRole::Admin).await?;
+    // Created by an operator, so the address counts as verified
+    users.mark_email_verified(&verification_token).await?;
+
+    println!("Created admin {} ({})", user.email, user.id);
+
+    db_pool.close().await;
+    Ok(())
+}
+
+async fn smoke_test(
+    settings: Settings,
+    base_url: Option<Url>,
+    timeout: Duration,
+    skip_destructive: bool,
+    allow_production: bool,
+) -> Result<(), Failure> {
+    if settings.is_production() && !allow_production {
+        return Err(anyhow::anyhow!(
+            "application.environment is production; pass --allow-production to run the smoke test anyway"
+        ))
+        .exit_code(EXIT_CONFIG);
+    }
+
+    let base_url = match base_url {
+        Some(base_url) => base_url,
+        None => local_url(&settings.server).exit_code(EXIT_CONFIG)?,
+    };
+    let report = smoke::run(&SmokeTestOptions {
+        base_url,
+        timeout,
+        skip_destructive,
+    })
+    .await;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    match report.failed_step() {
+        Some(step) => Err(anyhow::anyhow!("Smoke test failed at step {}", step.name).into()),
+        None => Ok(()),
+    }
+}
+
+/// This config's own server, reached over loopback when it listens on all interfaces.
+fn local_url(server: &ServerSettings) -> anyhow::Result<Url> {
+    let ip = match server.host.parse::<IpAddr>()? {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    let scheme = if server.tls.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+    Ok(format!("{}://{}", scheme, SocketAddr::new(ip, server.port)).parse()?)
+}
+
+async fn serve(settings: Settings, skip_migrations: bool) -> Result<(), Failure> {
+    settings.validate().exit_code(EXIT_CONFIG)?;
+    let _sentry = telemetry::init_sentry(&settings).exit_code(EXIT_CONFIG)?;
+    let metrics_handle = telemetry::init_metrics()?;
+
+    let db_pool = connect(&settings).await?;
 
     // Run migrations
-    sqlx::migrate!("./migrations").run(&db_pool).await?;
-    tracing::info!("Database migrations completed");
+    if skip_migrations {
+        tracing::info!("Skipping database migrations");
+    } else {
+        db::MIGRATOR.run(&db_pool).await?;
+        tracing::info!("Database migrations completed");
+    }
+
+    // Migrations only run on the primary; the replica picks them up through replication
+    let replica = db::connect_replica(&settings.database)
+        .await
+        .context("Failed to connect to the read replica")
+        .exit_code(EXIT_UNAVAILABLE)?;
+    if replica.is_some() {
+        tracing::info!("Read replica connection established");
+    }
+    let db = Db::new(db_pool, replica);
+
+    // Redis is optional; an unreachable server only disables caching until it returns
+    let cache: Arc<dyn Cache> = match &settings.redis.url {
+        Some(url) => Arc::new(
+            RedisCache::new(url)
+                .context("Invalid redis.url")
+                .exit_code(EXIT_CONFIG)?,
+        ),
+        None => Arc::new(MemoryCache::new()),
+    };
+
+    // Without an SMTP relay, emails (and the tokens in them) only go to the log
+    let mailer: Arc<dyn Mailer> = match &settings.smtp.host {
+        Some(host) => Arc::new(
+            SmtpMailer::new(host, &settings.smtp)
+                .context("Invalid smtp settings")
+                .exit_code(EXIT_CONFIG)?,
+        ),
+        None => Arc::new(NoopMailer),
+    };
+
+    let jwt = JwtKeys::new(&settings.application)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid JWT keys")
+        .exit_code(EXIT_CONFIG)?;
 
     // Create application state
     let state = AppState {
-        db: db_pool,
+        users: Arc::new(PgUserRepository::new(db.clone())),
+        two_factor: TwoFactorRepository::new(db.clone()),
+        api_keys: ApiKeyRepository::new(db.clone()),
+        oauth: OAuthRepository::new(db.clone()),
+        db: db.clone(),
         config: settings.clone(),
+        jwt: Arc::new(jwt),
+        mailer,
+        login_limiter: Arc::new(LoginRateLimiter::new(&settings.rate_limit)),
+        cache,
+        started_at: Instant::now(),
     };
 
-    // Build application router
-    let app = Router::new()
-        .nest("/api", routes::api_routes())
-        .nest("/health", routes::health_routes())
-        .layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
-                .on_response(DefaultOnResponse::new().level(Level::INFO)),
-        )
-        .layer(CompressionLayer::new())
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+    // Metrics get their own internal listener, outside the API middleware stack
+    let metrics_app = routes::metrics_routes(metrics_handle).with_state(state.clone());
+    let app = build_app(state);
 
     // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], settings.server.port));
-    tracing::info!("Starting server on {}", addr);
+    let ip = settings
+        .server
+        .ip()
+        .with_context(|| format!("Invalid server.host {:?}", settings.server.host))
+        .exit_code(EXIT_CONFIG)?;
+    let addr = SocketAddr::new(ip, settings.server.port);
+
+    // Load certificates before binding so a bad path fails fast
+    let tls_config = match &settings.server.tls {
+        Some(tls) => Some(
+            RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to load TLS certificate {:?} / key {:?}",
+                        tls.cert_path, tls.key_path
+                    )
+                })
+                .exit_code(EXIT_CONFIG)?,
+        ),
+        None => None,
+    };
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", addr))
+        .exit_code(EXIT_UNAVAILABLE)?;
+
+    // Stop accepting connections on SIGINT/SIGTERM and let in-flight requests finish,
+    // but never wait longer than the configured shutdown timeout
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down gracefully");
+        let _ = shutdown_tx.send(true);
+    });
+
+    let metrics_ip = settings
+        .metrics
+        .ip()
+        .with_context(|| format!("Invalid metrics.host {:?}", settings.metrics.host))
+        .exit_code(EXIT_CONFIG)?;
+    let metrics_addr = SocketAddr::new(metrics_ip, settings.metrics.port);
+    let metrics_listener = tokio::net::TcpListener::bind(metrics_addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", metrics_addr))
+        .exit_code(EXIT_UNAVAILABLE)?;
+    tracing::info!("Serving metrics on {}", metrics_addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let metrics = axum::serve(metrics_listener, metrics_app)
+        .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()));
+    tokio::spawn(async move {
+        if let Err(e) = metrics.await {
+            tracing::error!("Metrics listener failed: {}", e);
+        }
+    });
+
+    if let Some(redirect_port) = settings
+        .server
+        .tls
+        .as_ref()
+        .and_then(|t| t.redirect_http_port)
+    {
+        let redirect_addr = SocketAddr::new(ip, redirect_port);
+        let redirect_listener = tokio::net::TcpListener::bind(redirect_addr)
+            .await
+            .with_context(|| format!("Failed to bind {}", redirect_addr))
+            .exit_code(EXIT_UNAVAILABLE)?;
+        tracing::info!("Redirecting HTTP on {} to HTTPS", redirect_addr);
+
+        let redirect = axum::serve(redirect_listener, redirect_to_https(settings.server.port))
+            .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()));
+        tokio::spawn(async move {
+            if let Err(e) = redirect.await {
+                tracing::error!("HTTP redirect listener failed: {}", e);
+            }
+        });
+    }
+
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let server: Pin<Box<dyn Future<Output = io::Result<()>> + Send>> = match tls_config {
+        Some(tls_config) => {
+            tracing::info!("Starting HTTPS server on {}", addr);
+            let handle = Handle::new();
+            let shutdown_handle = handle.clone();
+            let shutdown = shutdown_requested(shutdown_rx.clone());
+            tokio::spawn(async move {
+                shutdown.await;
+                // No deadline here; the timeout below applies to both listeners
+                shutdown_handle.graceful_shutdown(None);
+            });
+
+            Box::pin(
+                axum_server::from_tcp_rustls(listener.into_std()?, tls_config)
+                    .handle(handle)
+                    .serve(service),
+            )
+        }
+        None => {
+            tracing::info!("Starting server on {}", addr);
+            Box::pin(
+                axum::serve(listener, service)
+                    .with_graceful_shutdown(shutdown_requested(shutdown_rx.clone()))
+                    .into_future(),
+            )
+        }
+    };
+
+    let shutdown_timeout = Duration::from_secs(settings.server.shutdown_timeout_secs);
+    tokio::select! {
+        result = server => result.exit_code(EXIT_FAILURE)?,
+        _ = async {
+            shutdown_requested(shutdown_rx).await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            tracing::warn!(
+                "Graceful shutdown timed out after {}s, dropping remaining connections",
+                shutdown_timeout.as_secs()
+            );
+        }
+    }
+
+    db.close().await;
+    tracing::info!("Database connections closed");
 
     Ok(())
 }
+
+/// Resolves once shutdown has been signalled on `rx`.
+async fn shutdown_requested(mut rx: watch::Receiver<bool>) {
+    let _ = rx.wait_for(|stopping| *stopping).await;
+}
+
+/// Redirects every plain HTTP request to the same host and path over HTTPS.
+fn redirect_to_https(https_port: u16) -> Router {
+    Router::new().fallback(move |Host(host): Host, uri: Uri| async move {
+        // Drop any port from the Host header; IPv6 literals end in `]`
+        let host = host
+            .rsplit_once(':')
+            .filter(|(_, port)| port.chars().all(|c| c.is_ascii_digit()))
+            .map_or(host.as_str(), |(host, _)| host);
+        let port = if https_port == 443 {
+            String::new()
+        } else {
+            format!(":{}", https_port)
+        };
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        Redirect::permanent(&format!("https://{}{}{}", host, port, path))
+    })
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
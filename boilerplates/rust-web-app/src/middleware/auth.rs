@@ -1,47 +1,312 @@
 use axum::{
     async_trait,
     extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    http::{header, request::Parts, HeaderMap, HeaderValue},
 };
+use chrono::Utc;
 use uuid::Uuid;
 
-use crate::utils::{auth::verify_jwt, error::AppError};
+use crate::{
+    models::UserRole,
+    repositories::AuthState,
+    utils::{
+        api_key::{api_key_prefix, API_KEY_HEADER, LAST_USED_UPDATE_INTERVAL},
+        auth::{constant_time_eq, hash_token},
+        cache::{api_key_used_key, auth_key, session_seen_key},
+        error::AppError,
+    },
+    AppState,
+};
+
+/// Cookie holding the token for browser clients when `auth.cookie_enabled` is set.
+pub const ACCESS_TOKEN_COOKIE: &str = "access_token";
 
 pub struct AuthUser {
     pub user_id: Uuid,
+    /// Session the token was issued for; `None` for API keys and tokens issued before
+    /// sessions were tracked.
+    pub session_id: Option<Uuid>,
 }
 
-#[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
-where
-    S: Send + Sync,
-{
-    type Rejection = AppError;
+/// `Set-Cookie` value carrying `token`. It is HttpOnly so scripts can't read it, and
+/// SameSite=Strict so other sites can't make requests with it.
+pub fn access_token_cookie(token: &str, max_age_secs: i64) -> HeaderValue {
+    let cookie = format!(
+        "{}={}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=Strict",
+        ACCESS_TOKEN_COOKIE, token, max_age_secs
+    );
+    HeaderValue::from_str(&cookie).expect("JWTs are valid header values")
+}
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        // Extract the authorization header
-        let auth_header = parts
-            .headers
-            .get("Authorization")
-            .and_then(|h| h.to_str().ok())
-            .ok_or_else(|| AppError::Unauthorized("Missing authorization header".to_string()))?;
+/// `Set-Cookie` value that removes the `name` cookie from the browser.
+pub fn expired_cookie(name: &str) -> HeaderValue {
+    let cookie = format!("{}=; Max-Age=0; Path=/; Secure; SameSite=Strict", name);
+    HeaderValue::from_str(&cookie).expect("cookie names are valid header values")
+}
+
+/// Value of the `name` cookie sent with the request, if any.
+pub fn request_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
 
+/// What a request authenticates with.
+enum Credential<'a> {
+    Jwt(&'a str),
+    ApiKey(&'a str),
+}
+
+/// The credential a request authenticates with: the `Authorization` header, then the
+/// `X-Api-Key` header, then the `access_token` cookie when cookies are enabled. `None`
+/// when the request carries none of them.
+fn request_credential<'a>(
+    parts: &'a Parts,
+    state: &AppState,
+) -> Result<Option<Credential<'a>>, AppError> {
+    if let Some(auth_header) = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+    {
         // Extract the token from "Bearer <token>"
         let token = auth_header
             .strip_prefix("Bearer ")
             .ok_or_else(|| AppError::Unauthorized("Invalid authorization format".to_string()))?;
+        return Ok(Some(Credential::Jwt(token)));
+    }
+
+    if let Some(key) = parts.headers.get(API_KEY_HEADER) {
+        let key = key
+            .to_str()
+            .map_err(|_| AppError::Unauthorized("Invalid API key".to_string()))?;
+        return Ok(Some(Credential::ApiKey(key)));
+    }
+
+    if !state.config.auth.cookie_enabled {
+        return Ok(None);
+    }
+
+    Ok(request_cookie(&parts.headers, ACCESS_TOKEN_COOKIE).map(Credential::Jwt))
+}
+
+/// Checked on every authenticated request, so served from the cache when possible;
+/// every change to these columns invalidates the entry. Only a shared cache is used: an
+/// in-process one would miss suspensions and revocations made through other instances.
+async fn auth_state(state: &AppState, user_id: Uuid) -> Result<AuthState, AppError> {
+    let key = auth_key(user_id);
+    let cached = state.cache.is_shared();
+    if cached {
+        if let Some(auth_state) = state.cache.get_json(&key).await {
+            return Ok(auth_state);
+        }
+    }
+
+    let auth_state = state
+        .users
+        .auth_state(user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    if cached {
+        state
+            .cache
+            .set_json(&key, &auth_state, state.config.cache.auth_ttl())
+            .await;
+    }
+    Ok(auth_state)
+}
+
+/// Resolves an API key to its owner. Keys are looked up by their indexed prefix and
+/// then matched on the full hash.
+async fn api_key_user(state: &AppState, key: &str) -> Result<Uuid, AppError> {
+    let invalid = || AppError::Unauthorized("Invalid API key".to_string());
+    let prefix = api_key_prefix(key).ok_or_else(invalid)?;
+    let key_hash = hash_token(key);
+
+    let api_key = state
+        .api_keys
+        .find_by_prefix(prefix)
+        .await?
+        .into_iter()
+        .find(|candidate| constant_time_eq(candidate.key_hash.as_bytes(), key_hash.as_bytes()))
+        .ok_or_else(invalid)?;
+
+    if api_key
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(AppError::Unauthorized("API key has expired".to_string()));
+    }
+
+    record_api_key_use(state, api_key.id).await;
+    Ok(api_key.user_id)
+}
+
+/// Updates `last_used_at` in the background, at most once per
+/// [`LAST_USED_UPDATE_INTERVAL`] per key.
+async fn record_api_key_use(state: &AppState, key_id: Uuid) {
+    let marker = api_key_used_key(key_id);
+    if state.cache.get_json::<bool>(&marker).await.is_some() {
+        return;
+    }
+    state
+        .cache
+        .set_json(&marker, &true, LAST_USED_UPDATE_INTERVAL)
+        .await;
+
+    let api_keys = state.api_keys.clone();
+    tokio::spawn(async move {
+        if let Err(e) = api_keys.touch(key_id).await {
+            tracing::warn!(%key_id, "Failed to record API key use: {}", e);
+        }
+    });
+}
+
+/// Rejects tokens whose session was revoked, and bumps the session's `last_seen_at`.
+/// An active session is only checked against the database once per
+/// [`LAST_USED_UPDATE_INTERVAL`]; revoking it drops the marker.
+async fn check_session(state: &AppState, user_id: Uuid, session_id: Uuid) -> Result<(), AppError> {
+    let marker = session_seen_key(session_id);
+    if state.cache.get_json::<bool>(&marker).await.is_some() {
+        return Ok(());
+    }
+
+    if !state.users.touch_session(user_id, session_id).await? {
+        return Err(AppError::Unauthorized(
+            "Session has been revoked".to_string(),
+        ));
+    }
+
+    state
+        .cache
+        .set_json(&marker, &true, LAST_USED_UPDATE_INTERVAL)
+        .await;
+    Ok(())
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let credential = request_credential(parts, state)?
+            .ok_or_else(|| AppError::Unauthorized("Missing authorization header".to_string()))?;
+
+        // When the token was issued; API keys aren't tied to the password, so `None`
+        let (user_id, issued_at, session_id) = match credential {
+            Credential::Jwt(token) => {
+                // Verify the token with the configured secret (inline or from jwt_secret_file)
+                let claims = state.jwt.verify_jwt(token)?;
+
+                // Parse user ID from claims
+                let user_id = Uuid::parse_str(&claims.sub)
+                    .map_err(|_| AppError::Unauthorized("Invalid user ID in token".to_string()))?;
+                (user_id, Some(claims.iat), claims.sid)
+            }
+            Credential::ApiKey(key) => (api_key_user(state, key).await?, None, None),
+        };
+
+        // Attributes errors reported for the rest of the request to this user
+        sentry::configure_scope(|scope| {
+            scope.set_user(Some(sentry::User {
+                id: Some(user_id.to_string()),
+                ..Default::default()
+            }))
+        });
+
+        let auth_state = auth_state(state, user_id).await?;
+
+        // Reject tokens issued before the last password change. `iat` only has second
+        // precision, so compare against the start of the second the password changed in.
+        if let (Some(changed_at), Some(issued_at)) = (auth_state.password_changed_at, issued_at) {
+            if issued_at < changed_at.timestamp() {
+                return Err(AppError::Unauthorized("Token has been revoked".to_string()));
+            }
+        }
+
+        // Reject suspended accounts; suspensions lapse on their own once the expiry passes
+        if let Some(until) = auth_state
+            .suspended_until
+            .filter(|until| *until > Utc::now())
+        {
+            return Err(AppError::AccountSuspended(until));
+        }
+
+        // Tokens issued at registration stay unusable until the address is confirmed
+        if state.config.application.require_email_verification
+            && auth_state.email_verified_at.is_none()
+        {
+            return Err(AppError::EmailNotVerified);
+        }
+
+        if let Some(session_id) = session_id {
+            check_session(state, user_id, session_id).await?;
+        }
+
+        Ok(AuthUser {
+            user_id,
+            session_id,
+        })
+    }
+}
+
+/// The authenticated user, or `None` for anonymous requests. Requests without a token
+/// are let through, but a credential that fails [`AuthUser`]'s checks is still rejected
+/// rather than treated as anonymous.
+pub struct OptionalAuthUser(pub Option<AuthUser>);
+
+#[async_trait]
+impl FromRequestParts<AppState> for OptionalAuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if request_credential(parts, state)?.is_none() {
+            return Ok(OptionalAuthUser(None));
+        }
+
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        Ok(OptionalAuthUser(Some(user)))
+    }
+}
+
+/// An authenticated user holding the admin role. Non-admins are rejected with 403.
+pub struct AdminUser {
+    pub user_id: Uuid,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
 
-        // Get JWT secret from environment
-        let jwt_secret =
-            std::env::var("APP__APPLICATION__JWT_SECRET").unwrap_or_else(|_| "secret".to_string());
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser { user_id, .. } = AuthUser::from_request_parts(parts, state).await?;
 
-        // Verify the token
-        let claims = verify_jwt(token, &jwt_secret)?;
+        // Look up the role on every request so demotions take effect immediately
+        let role = state
+            .users
+            .role(user_id)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
 
-        // Parse user ID from claims
-        let user_id = Uuid::parse_str(&claims.sub)
-            .map_err(|_| AppError::Unauthorized("Invalid user ID in token".to_string()))?;
+        if role != UserRole::Admin {
+            return Err(AppError::Forbidden("Admin access required".to_string()));
+        }
 
-        Ok(AuthUser { user_id })
+        Ok(AdminUser { user_id })
     }
 }
@@ -0,0 +1,17 @@
+use std::any::Any;
+
+use axum::response::{IntoResponse, Response};
+
+use crate::utils::error::AppError;
+
+/// Response for `CatchPanicLayer`: returns the standard 500 body instead of dropping the
+/// connection. The panic message is logged and reported like any other internal error.
+pub fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("non-string panic payload");
+
+    AppError::InternalError(format!("Request handler panicked: {}", message)).into_response()
+}
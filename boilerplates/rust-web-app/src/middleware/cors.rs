@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+
+use crate::config::CorsSettings;
+
+/// Builds the CORS layer from settings. Entries are checked by `Settings::validate`,
+/// so invalid ones are skipped here.
+pub fn cors_layer(settings: &CorsSettings) -> CorsLayer {
+    let is_wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+    let credentials = settings.allow_credentials;
+
+    // tower-http refuses `*` together with credentials, so those echo the request instead
+    let origins = if !is_wildcard(&settings.allowed_origins) {
+        AllowOrigin::list(parse_all::<HeaderValue>(&settings.allowed_origins))
+    } else if credentials {
+        AllowOrigin::mirror_request()
+    } else {
+        AllowOrigin::any()
+    };
+
+    let methods = if !is_wildcard(&settings.allowed_methods) {
+        AllowMethods::list(parse_all::<Method>(&settings.allowed_methods))
+    } else if credentials {
+        AllowMethods::mirror_request()
+    } else {
+        AllowMethods::any()
+    };
+
+    let headers = if !is_wildcard(&settings.allowed_headers) {
+        AllowHeaders::list(parse_all::<HeaderName>(&settings.allowed_headers))
+    } else if credentials {
+        AllowHeaders::mirror_request()
+    } else {
+        AllowHeaders::any()
+    };
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(credentials)
+        .max_age(Duration::from_secs(settings.max_age_secs))
+}
+
+fn parse_all<T: std::str::FromStr>(values: &[String]) -> Vec<T> {
+    values
+        .iter()
+        .filter_map(|value| value.parse().ok())
+        .collect()
+}
@@ -0,0 +1,56 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+
+use super::auth::{request_cookie, ACCESS_TOKEN_COOKIE};
+use crate::utils::{api_key::API_KEY_HEADER, auth::constant_time_eq, error::AppError};
+
+/// Cookie holding the CSRF token. Unlike the access token cookie, scripts on the page can
+/// read it, so they can echo it back in [`CSRF_HEADER`].
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+/// Header state-changing requests authenticated by cookie must repeat the token in.
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// `Set-Cookie` value carrying the CSRF `token`.
+pub fn csrf_cookie(token: &str, max_age_secs: i64) -> HeaderValue {
+    let cookie = format!(
+        "{}={}; Max-Age={}; Path=/; Secure; SameSite=Strict",
+        CSRF_COOKIE, token, max_age_secs
+    );
+    HeaderValue::from_str(&cookie).expect("CSRF tokens are valid header values")
+}
+
+/// Double-submit CSRF check for cookie authentication. A state-changing request carrying
+/// the access token cookie, and no header credential, must send the `csrf_token` cookie's
+/// value in `X-CSRF-Token`. Another site can make the browser send the cookies, but it
+/// can't read them to set the header. Requests authenticated by header aren't affected,
+/// since browsers never add those on their own.
+pub async fn require_csrf_token(request: Request, next: Next) -> Result<Response, AppError> {
+    let headers = request.headers();
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let cookie_authenticated = request_cookie(headers, ACCESS_TOKEN_COOKIE).is_some()
+        && !headers.contains_key(header::AUTHORIZATION)
+        && !headers.contains_key(API_KEY_HEADER);
+
+    if !safe && cookie_authenticated {
+        let expected = request_cookie(headers, CSRF_COOKIE).unwrap_or_default();
+        let submitted = headers
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if expected.is_empty() || !constant_time_eq(expected.as_bytes(), submitted.as_bytes()) {
+            return Err(AppError::Forbidden(
+                "Missing or invalid CSRF token".to_string(),
+            ));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
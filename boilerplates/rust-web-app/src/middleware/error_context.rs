@@ -0,0 +1,41 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::ErrorFormat;
+
+tokio::task_local! {
+    static ERROR_CONTEXT: ErrorContext;
+}
+
+/// How errors rendered while handling the current request are presented.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    /// Whether 5xx responses may carry the underlying error.
+    pub expose_details: bool,
+    pub format: ErrorFormat,
+    /// Path of the request, reported as a problem's `instance`.
+    pub path: String,
+}
+
+/// The context of the request being handled. Outside of a request it's the default,
+/// so errors rendered elsewhere are redacted and use the simple format.
+pub fn current_error_context() -> ErrorContext {
+    ERROR_CONTEXT.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Makes `context`, as decided from the settings by `build_app`, and the request path
+/// available to errors rendered while handling the request.
+pub async fn error_context(
+    State(context): State<ErrorContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let context = ErrorContext {
+        path: request.uri().path().to_string(),
+        ..context
+    };
+    ERROR_CONTEXT.scope(context, next.run(request)).await
+}
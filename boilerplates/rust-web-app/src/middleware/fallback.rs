@@ -0,0 +1,47 @@
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::utils::error::AppError;
+
+/// Router fallback for paths that match no route.
+pub async fn not_found() -> AppError {
+    AppError::NotFound("Route not found".to_string())
+}
+
+/// Replaces the empty or plain-text responses produced by axum and tower-http for a
+/// wrong method (405), a timeout and an oversized body (413) with the JSON error body.
+/// For 405 the methods the route accepts (taken from the `Allow` header axum sets) are
+/// listed in `details.allowed_methods`. `TimeoutLayer` can only answer 408, which blames
+/// the client, so its responses become a 504 instead.
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| {
+            content_type == "application/json" || content_type == "application/problem+json"
+        });
+    if is_json {
+        return response;
+    }
+
+    match response.status() {
+        StatusCode::METHOD_NOT_ALLOWED => {
+            let allowed = response
+                .headers()
+                .get(header::ALLOW)
+                .and_then(|allow| allow.to_str().ok())
+                .map(|allow| allow.split(',').map(|m| m.trim().to_string()).collect())
+                .unwrap_or_default();
+
+            AppError::MethodNotAllowed(allowed).into_response()
+        }
+        StatusCode::REQUEST_TIMEOUT => AppError::Timeout.into_response(),
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge.into_response(),
+        _ => response,
+    }
+}
@@ -1,3 +1,18 @@
 pub mod auth;
+pub mod catch_panic;
+pub mod client_ip;
+pub mod cors;
+pub mod csrf;
+pub mod error_context;
+pub mod fallback;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod security_headers;
+pub mod trace;
+pub mod validated_json;
 
-pub use auth::AuthUser;
+pub use auth::{AdminUser, AuthUser, OptionalAuthUser};
+pub use client_ip::ClientIp;
+pub use request_id::RequestId;
+pub use validated_json::{JsonBody, ValidatedJson};
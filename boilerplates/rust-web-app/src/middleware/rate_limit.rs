@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    middleware::ClientIp,
+    utils::{error::AppError, rate_limit::IpRateLimiter},
+};
+
+/// Throttles requests per client IP, answering 429 with `Retry-After` once the bucket is
+/// empty. Requests without a known IP are let through.
+pub async fn limit_by_ip(
+    State(limiter): State<Arc<IpRateLimiter>>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if let Some(ip) = ip {
+        limiter
+            .check(ip)
+            .map_err(|wait| AppError::TooManyRequests(wait.as_secs().max(1)))?;
+    }
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::SecuritySettings;
+
+/// `Strict-Transport-Security` value for the configured max age, or `None` when HSTS is off.
+pub fn hsts_header(settings: &SecuritySettings) -> Option<HeaderValue> {
+    let max_age = settings.hsts_max_age?;
+    HeaderValue::from_str(&format!("max-age={}", max_age)).ok()
+}
+
+/// Adds defensive headers to every response. Headers a handler already set are kept,
+/// so individual routes can relax them.
+pub async fn security_headers(
+    State(hsts): State<Option<HeaderValue>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    headers
+        .entry(header::X_CONTENT_TYPE_OPTIONS)
+        .or_insert(HeaderValue::from_static("nosniff"));
+    headers
+        .entry(header::X_FRAME_OPTIONS)
+        .or_insert(HeaderValue::from_static("DENY"));
+    headers
+        .entry(header::REFERRER_POLICY)
+        .or_insert(HeaderValue::from_static("no-referrer"));
+    if let Some(hsts) = hsts {
+        headers
+            .entry(header::STRICT_TRANSPORT_SECURITY)
+            .or_insert(hsts);
+    }
+
+    response
+}
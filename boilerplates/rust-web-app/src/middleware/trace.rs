@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use axum::{body::Body, extract::Request, response::Response};
+use tower_http::trace::{DefaultOnResponse, OnResponse};
+use tracing::{field::Empty, Level, Span};
+
+use super::request_id::RequestId;
+
+/// Span for `TraceLayer` that records the request id alongside the method and URI. The
+/// route, status and latency are filled in as the request is handled, so traces and JSON
+/// logs can be grouped by endpoint.
+pub fn make_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.as_str())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        http.method = %request.method(),
+        // Routing happens inside the span, see `track_metrics`
+        http.route = Empty,
+        http.status_code = Empty,
+        latency_ms = Empty,
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = %request_id,
+    )
+}
+
+/// Records the status and latency on the request span, then logs the response like
+/// `DefaultOnResponse` at INFO.
+#[derive(Clone)]
+pub struct RecordResponse(DefaultOnResponse);
+
+impl Default for RecordResponse {
+    fn default() -> Self {
+        Self(DefaultOnResponse::new().level(Level::INFO))
+    }
+}
+
+impl<B> OnResponse<B> for RecordResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        span.record("http.status_code", response.status().as_u16());
+        span.record("latency_ms", latency.as_millis() as u64);
+        self.0.on_response(response, latency, span);
+    }
+}
@@ -0,0 +1,61 @@
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    Json,
+};
+use serde::de::DeserializeOwned;
+use validator::Validate;
+
+use crate::utils::error::AppError;
+
+/// JSON body that has been deserialized and validated. Malformed bodies are rejected
+/// with 400 (422 when the JSON doesn't match the expected shape, 413 when it exceeds
+/// `server.max_body_bytes`), and validation failures with 422 and per-field messages.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let JsonBody(value) = JsonBody::<T>::from_request(req, state).await?;
+
+        value.validate()?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// JSON body rejected like [`ValidatedJson`] when malformed, but left for the handler
+/// to validate. For requests whose rules need a context only the handler has, such as
+/// the password policy and the account's email; see [`validator::ValidateArgs`].
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state)
+            .await
+            .map_err(|rejection| match rejection {
+                JsonRejection::JsonDataError(e) => AppError::ValidationError(e.body_text()),
+                // Bodies without a Content-Length only hit the size limit while being read
+                other if other.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                    AppError::PayloadTooLarge
+                }
+                other => AppError::BadRequest(other.body_text()),
+            })?;
+
+        Ok(JsonBody(value))
+    }
+}
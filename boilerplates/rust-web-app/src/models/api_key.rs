@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::utils::api_key::{is_valid_scope, MAX_SCOPES};
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CreateApiKeyRequest {
+    /// Label to tell keys apart, e.g. the service using it.
+    #[validate(length(min = 1, max = 100, message = "Name must be 1 to 100 characters"))]
+    pub name: String,
+    /// Labels like `reports:read`, returned with the key for clients and handlers to
+    /// check. Not enforced by the built-in endpoints.
+    #[serde(default)]
+    #[validate(custom(function = "validate_scopes"))]
+    pub scopes: Vec<String>,
+    /// When the key stops working. Keys without one last until revoked.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn validate_scopes(scopes: &[String]) -> Result<(), validator::ValidationError> {
+    if scopes.len() > MAX_SCOPES {
+        let mut error = validator::ValidationError::new("too_many_scopes");
+        error.message = Some(format!("At most {} scopes are allowed", MAX_SCOPES).into());
+        return Err(error);
+    }
+    if !scopes.iter().all(|scope| is_valid_scope(scope)) {
+        let mut error = validator::ValidationError::new("invalid_scope");
+        error.message =
+            Some("Scopes must be 1 to 64 lowercase letters, digits or one of : . _ -".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// The first characters of the key, to recognize it by.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id,
+            name: key.name,
+            prefix: key.prefix,
+            scopes: key.scopes,
+            last_used_at: key.last_used_at,
+            expires_at: key.expires_at,
+            created_at: key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKeyResponse {
+    /// The key to send in the `X-Api-Key` header. Only shown once.
+    pub key: String,
+    pub api_key: ApiKeyResponse,
+}
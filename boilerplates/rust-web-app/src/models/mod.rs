@@ -1,3 +1,17 @@
+pub mod api_key;
+pub mod session;
+pub mod two_factor;
 pub mod user;
 
-pub use user::{User, CreateUserRequest, LoginRequest, UserResponse};
+pub use api_key::{ApiKey, ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse};
+pub use session::{Session, SessionResponse};
+pub use two_factor::{
+    DisableTwoFactorRequest, EnableTwoFactorRequest, LoginResponse, TwoFactorChallengeResponse,
+    TwoFactorEnabledResponse, TwoFactorSetupResponse, VerifyTwoFactorRequest,
+};
+pub use user::{
+    AuthResponse, ChangePasswordRequest, ClaimHandleRequest, CreateUserRequest, CsrfTokenResponse,
+    ForgotPasswordRequest, ListUsersQuery, LoginRequest, PublicUserResponse,
+    ResendVerificationRequest, ResetPasswordRequest, SortOrder, SuspendUserRequest,
+    UpdateUserRequest, User, UserResponse, UserRole, VerifyEmailQuery,
+};
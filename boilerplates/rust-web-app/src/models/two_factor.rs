@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::AuthResponse;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TwoFactorSetupResponse {
+    /// Base32 secret for entering into an authenticator app by hand.
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code.
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct EnableTwoFactorRequest {
+    /// Current code from the authenticator app.
+    #[validate(length(min = 1, message = "Code is required"))]
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TwoFactorEnabledResponse {
+    /// Single-use codes that stand in for the authenticator app. Only shown once.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct DisableTwoFactorRequest {
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct VerifyTwoFactorRequest {
+    /// Token from the login response.
+    #[validate(length(min = 1, message = "Pending token is required"))]
+    pub pending_token: String,
+    /// Code from the authenticator app, or one of the recovery codes.
+    #[validate(length(min = 1, message = "Code is required"))]
+    pub code: String,
+}
+
+/// Returned by login instead of a token when the account has two-factor authentication
+/// enabled; exchange it at `/api/auth/2fa/verify`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TwoFactorChallengeResponse {
+    pub two_factor_required: bool,
+    pub pending_token: String,
+    /// Seconds until the pending token expires.
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(AuthResponse),
+    TwoFactorRequired(TwoFactorChallengeResponse),
+}
@@ -1,43 +1,219 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidateArgs, ValidationError, ValidationErrors};
+
+use crate::utils::password_policy::{validate_password_strength, PasswordContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    User,
+    Admin,
+}
 
 #[derive(Debug, Clone, FromRow, Serialize)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
+    /// Unset for accounts created through an OAuth provider until a password is set.
     #[serde(skip_serializing)]
-    pub password_hash: String,
+    pub password_hash: Option<String>,
     pub name: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub role: UserRole,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    pub password_changed_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub handle: Option<String>,
+    pub handle_changed_at: Option<DateTime<Utc>>,
+    /// Encrypted TOTP secret; set by 2FA setup, in effect once `totp_enabled_at` is set.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub totp_last_used_step: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[validate(context = "PasswordContext<'v_a>")]
 pub struct CreateUserRequest {
     #[validate(email(message = "Invalid email address"))]
     pub email: String,
-    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    #[validate(custom(function = "validate_password_strength", use_context))]
     pub password: String,
     #[validate(length(min = 2, message = "Name must be at least 2 characters"))]
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct LoginRequest {
     #[validate(email(message = "Invalid email address"))]
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct UpdateUserRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: Option<String>,
+    #[validate(length(min = 2, message = "Name must be at least 2 characters"))]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[validate(context = "PasswordContext<'v_a>")]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    #[validate(custom(function = "validate_password_strength", use_context))]
+    pub new_password: String,
+}
+
+impl ChangePasswordRequest {
+    /// Runs the field rules and reports a new password equal to the current one under
+    /// `new_password`, alongside any other errors. A schema validator would put it under `__all__`.
+    pub fn validate_change(&self, context: &PasswordContext) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate_with_args(context) {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors,
+        };
+
+        if self.new_password == self.current_password {
+            let mut error = ValidationError::new("password_unchanged");
+            error.message = Some("New password must differ from the current password".into());
+            errors.add("new_password", error);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Claims or changes the caller's handle. Normalization and the reserved-word check
+/// happen in the handler since they depend on config.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ClaimHandleRequest {
+    pub handle: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[validate(context = "PasswordContext<'v_a>")]
+pub struct ResetPasswordRequest {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+    #[validate(custom(function = "validate_password_strength", use_context))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct SuspendUserRequest {
+    /// Suspension length in seconds; mutually exclusive with `until`.
+    #[validate(range(min = 1, message = "Duration must be positive"))]
+    pub duration_secs: Option<i64>,
+    /// Absolute end of the suspension; mutually exclusive with `duration_secs`.
+    pub until: Option<DateTime<Utc>>,
+    #[validate(length(min = 1, message = "Reason is required"))]
+    pub reason: String,
+}
+
+/// Columns the admin user listing may be sorted by.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSortField {
+    #[default]
+    CreatedAt,
+    Email,
+}
+
+impl UserSortField {
+    pub fn column(self) -> &'static str {
+        match self {
+            UserSortField::CreatedAt => "created_at",
+            UserSortField::Email => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListUsersQuery {
+    #[serde(default)]
+    pub sort: UserSortField,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Case-insensitive substring match on email or name.
+    pub q: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
     pub name: String,
+    pub email_verified: bool,
+    pub role: UserRole,
+    pub handle: Option<String>,
+    #[serde(default)]
+    pub two_factor_enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suspended_until: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    /// Only included when the user is viewing their own account.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl UserResponse {
+    /// Builds the response for the account owner, including `updated_at`.
+    pub fn for_owner(user: User) -> Self {
+        Self {
+            updated_at: Some(user.updated_at),
+            ..user.into()
+        }
+    }
 }
 
 impl From<User> for UserResponse {
@@ -46,13 +222,50 @@ impl From<User> for UserResponse {
             id: user.id,
             email: user.email,
             name: user.name,
+            email_verified: user.email_verified_at.is_some(),
+            role: user.role,
+            handle: user.handle,
+            two_factor_enabled: user.totp_enabled_at.is_some(),
+            suspended_until: user.suspended_until.filter(|until| *until > Utc::now()),
             created_at: user.created_at,
+            updated_at: None,
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+/// The subset of a user's profile visible to anyone, looked up by id or handle.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PublicUserResponse {
+    pub id: Uuid,
+    pub handle: Option<String>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Only included when the request is authenticated: whether the profile is the caller's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_self: Option<bool>,
+}
+
+impl PublicUserResponse {
+    /// Builds the response for `viewer`, the authenticated caller if there is one.
+    pub fn new(user: User, viewer: Option<Uuid>) -> Self {
+        Self {
+            is_self: viewer.map(|viewer| viewer == user.id),
+            id: user.id,
+            handle: user.handle,
+            name: user.name,
+            created_at: user.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AuthResponse {
     pub token: String,
     pub user: UserResponse,
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CsrfTokenResponse {
+    /// Send this in the `X-CSRF-Token` header of state-changing requests.
+    pub csrf_token: String,
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    db::Db,
+    models::ApiKey,
+    utils::{api_key::api_key_prefix, auth::hash_token, error::AppResult},
+};
+
+/// Fields for a new API key.
+#[derive(Debug, Clone)]
+pub struct NewApiKey {
+    pub user_id: Uuid,
+    pub name: String,
+    /// The generated key in plain text; only its hash and prefix are stored.
+    pub key: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Storage for the API keys users create for service clients.
+#[derive(Clone)]
+pub struct ApiKeyRepository {
+    db: Db,
+}
+
+impl ApiKeyRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    pub async fn create(&self, key: NewApiKey) -> AppResult<ApiKey> {
+        let prefix = api_key_prefix(&key.key).expect("generated keys are well-formed");
+
+        let api_key = sqlx::query_as::<_, ApiKey>(
+            "INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        )
+        .bind(key.user_id)
+        .bind(&key.name)
+        .bind(prefix)
+        .bind(hash_token(&key.key))
+        .bind(&key.scopes)
+        .bind(key.expires_at)
+        .fetch_one(self.db.write())
+        .await?;
+        Ok(api_key)
+    }
+
+    /// The keys starting with `prefix`, for matching a presented key against their hashes.
+    pub async fn find_by_prefix(&self, prefix: &str) -> AppResult<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE prefix = $1")
+            .bind(prefix)
+            .fetch_all(self.db.write())
+            .await?;
+        Ok(keys)
+    }
+
+    /// Records that the key was just used.
+    pub async fn touch(&self, id: Uuid) -> AppResult<()> {
+        sqlx::query("UPDATE api_keys SET last_used_at = NOW() WHERE id = $1")
+            .bind(id)
+            .execute(self.db.write())
+            .await?;
+        Ok(())
+    }
+
+    /// The user's keys, newest first.
+    pub async fn list(&self, user_id: Uuid) -> AppResult<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>(
+            "SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC, id",
+        )
+        .bind(user_id)
+        .fetch_all(self.db.read())
+        .await?;
+        Ok(keys)
+    }
+
+    /// Deletes one of the user's keys. Returns whether it existed.
+    pub async fn delete(&self, user_id: Uuid, id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(self.db.write())
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -0,0 +1,9 @@
+pub mod api_keys;
+pub mod oauth;
+pub mod two_factor;
+pub mod users;
+
+pub use api_keys::{ApiKeyRepository, NewApiKey};
+pub use oauth::OAuthRepository;
+pub use two_factor::TwoFactorRepository;
+pub use users::{AuthState, NewUser, PgUserRepository, UserChanges, UserRepository};
@@ -0,0 +1,138 @@
+use crate::{
+    db::Db,
+    models::User,
+    utils::{
+        auth::hash_token,
+        error::{AppError, AppResult},
+        oauth::OAuthProfile,
+    },
+};
+
+/// Storage for OAuth logins in progress and the provider accounts linked to users.
+#[derive(Clone)]
+pub struct OAuthRepository {
+    db: Db,
+}
+
+impl OAuthRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Remembers a login started with `provider`, keyed by its `state` parameter, along
+    /// with its PKCE code verifier.
+    pub async fn create_state(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        ttl_secs: u64,
+    ) -> AppResult<()> {
+        // Drop logins that were abandoned at the provider
+        sqlx::query("DELETE FROM oauth_states WHERE expires_at < NOW()")
+            .execute(self.db.write())
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO oauth_states (state_hash, provider, code_verifier, expires_at) \
+             VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))",
+        )
+        .bind(hash_token(state))
+        .bind(provider)
+        .bind(code_verifier)
+        .bind(ttl_secs as f64)
+        .execute(self.db.write())
+        .await?;
+        Ok(())
+    }
+
+    /// Consumes the login with `state` and returns its code verifier. `None` if it is
+    /// unknown, expired or was started with another provider.
+    pub async fn take_state(&self, state: &str, provider: &str) -> AppResult<Option<String>> {
+        // Consume the state atomically so a callback can only ever be used once
+        let code_verifier = sqlx::query_scalar::<_, String>(
+            "DELETE FROM oauth_states \
+             WHERE state_hash = $1 AND provider = $2 AND expires_at > NOW() \
+             RETURNING code_verifier",
+        )
+        .bind(hash_token(state))
+        .bind(provider)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(code_verifier)
+    }
+
+    /// The user `profile` is linked to. An unlinked profile is linked to the account with
+    /// its verified email, or to a new password-less account if there is none.
+    pub async fn find_or_create_user(
+        &self,
+        provider: &str,
+        profile: OAuthProfile,
+    ) -> AppResult<User> {
+        let mut tx = self.db.write().begin().await?;
+
+        let linked = sqlx::query_as::<_, User>(
+            "SELECT u.* FROM active_users u \
+             JOIN oauth_accounts a ON a.user_id = u.id \
+             WHERE a.provider = $1 AND a.provider_user_id = $2",
+        )
+        .bind(provider)
+        .bind(&profile.provider_user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(user) = linked {
+            return Ok(user);
+        }
+
+        // Linking by an unverified address would let anyone take over an account by
+        // claiming its email at the provider
+        let email = profile.email.ok_or_else(|| {
+            AppError::BadRequest(
+                "The OAuth provider did not share a verified email address".to_string(),
+            )
+        })?;
+
+        // The provider vouches for the address, so it counts as verified here too
+        let existing = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET email_verified_at = COALESCE(email_verified_at, NOW()) \
+             WHERE lower(email) = lower($1) \
+             RETURNING *",
+        )
+        .bind(&email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let user = match existing {
+            Some(user) => user,
+            None => {
+                let name = profile
+                    .name
+                    .filter(|name| name.trim().chars().count() >= 2)
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+                sqlx::query_as::<_, User>(
+                    "INSERT INTO users (email, name, email_verified_at) \
+                     VALUES ($1, $2, NOW()) \
+                     RETURNING *",
+                )
+                .bind(&email)
+                .bind(name.trim())
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+
+        sqlx::query(
+            "INSERT INTO oauth_accounts (user_id, provider, provider_user_id, email) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(user.id)
+        .bind(provider)
+        .bind(&profile.provider_user_id)
+        .bind(&email)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(user)
+    }
+}
@@ -0,0 +1,224 @@
+use uuid::Uuid;
+
+use crate::{
+    db::Db,
+    models::User,
+    utils::{
+        auth::{generate_token, hash_token},
+        error::AppResult,
+        two_factor::normalize_recovery_code,
+    },
+};
+
+/// Wrong codes a login challenge tolerates before it is discarded and the password has
+/// to be entered again.
+const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+/// How a code submitted for a login challenge checked out against the user's secret.
+pub enum SecondFactor {
+    /// A TOTP code, with the time step it was accepted for, if it was.
+    Totp(Option<u64>),
+    /// A recovery code, accepted if it is one of the user's unused ones.
+    RecoveryCode(String),
+}
+
+/// The result of [`TwoFactorRepository::complete_challenge`].
+pub enum ChallengeOutcome {
+    /// The pending token is unknown or expired, or 2FA was disabled since it was issued.
+    Invalid,
+    /// The code was wrong; the attempt was counted.
+    Rejected,
+    /// The code was right and the challenge is used up.
+    Passed(Box<User>),
+}
+
+/// Storage for TOTP secrets, recovery codes and the pending logins waiting for a code.
+#[derive(Clone)]
+pub struct TwoFactorRepository {
+    db: Db,
+}
+
+impl TwoFactorRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+
+    /// Stores an unconfirmed secret, replacing any earlier one.
+    pub async fn set_totp_secret(&self, user_id: Uuid, encrypted_secret: &str) -> AppResult<()> {
+        sqlx::query("UPDATE active_users SET totp_secret = $1 WHERE id = $2")
+            .bind(encrypted_secret)
+            .bind(user_id)
+            .execute(self.db.write())
+            .await?;
+        Ok(())
+    }
+
+    /// Turns 2FA on with `step` as the last code used, replacing the recovery codes.
+    pub async fn enable_totp(
+        &self,
+        user_id: Uuid,
+        step: u64,
+        recovery_codes: &[String],
+    ) -> AppResult<()> {
+        let mut tx = self.db.write().begin().await?;
+
+        sqlx::query(
+            "UPDATE active_users SET totp_enabled_at = NOW(), totp_last_used_step = $1 WHERE id = $2",
+        )
+        .bind(step as i64)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM two_factor_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        for code in recovery_codes {
+            sqlx::query(
+                "INSERT INTO two_factor_recovery_codes (user_id, code_hash) VALUES ($1, $2)",
+            )
+            .bind(user_id)
+            .bind(hash_token(&normalize_recovery_code(code)))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Turns 2FA off, dropping the secret, recovery codes and pending logins.
+    pub async fn disable_totp(&self, user_id: Uuid) -> AppResult<()> {
+        let mut tx = self.db.write().begin().await?;
+
+        sqlx::query(
+            "UPDATE active_users \
+             SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL \
+             WHERE id = $1",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM two_factor_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Logins waiting for a code would otherwise still be completable with one
+        sqlx::query("DELETE FROM two_factor_challenges WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Records a pending login for `user_id` and returns its token in plain text.
+    pub async fn create_challenge(&self, user_id: Uuid, ttl_secs: u64) -> AppResult<String> {
+        let token = generate_token();
+
+        sqlx::query(
+            "INSERT INTO two_factor_challenges (user_id, token_hash, expires_at) \
+             VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+        )
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(ttl_secs as f64)
+        .execute(self.db.write())
+        .await?;
+
+        Ok(token)
+    }
+
+    /// Checks a code for the pending login `token`. `check` is given the user and their
+    /// encrypted secret and says what the code is; a TOTP step is recorded so the code
+    /// can't be replayed, and a recovery code is used up.
+    pub async fn complete_challenge<F>(&self, token: &str, check: F) -> AppResult<ChallengeOutcome>
+    where
+        F: FnOnce(&User, &str) -> AppResult<SecondFactor> + Send,
+    {
+        let mut tx = self.db.write().begin().await?;
+
+        // Lock the challenge so concurrent guesses are counted one at a time
+        let Some((challenge_id, user_id, failed_attempts)) =
+            sqlx::query_as::<_, (Uuid, Uuid, i32)>(
+                "SELECT id, user_id, failed_attempts FROM two_factor_challenges \
+                 WHERE token_hash = $1 AND expires_at > NOW() FOR UPDATE",
+            )
+            .bind(hash_token(token))
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(ChallengeOutcome::Invalid);
+        };
+
+        let Some(user) =
+            sqlx::query_as::<_, User>("SELECT * FROM active_users WHERE id = $1 FOR UPDATE")
+                .bind(user_id)
+                .fetch_optional(&mut *tx)
+                .await?
+        else {
+            return Ok(ChallengeOutcome::Invalid);
+        };
+        // 2FA may have been disabled since the challenge was issued
+        let encrypted = match (&user.totp_enabled_at, &user.totp_secret) {
+            (Some(_), Some(secret)) => secret.clone(),
+            _ => return Ok(ChallengeOutcome::Invalid),
+        };
+
+        let accepted = match check(&user, &encrypted)? {
+            SecondFactor::Totp(Some(step)) => {
+                sqlx::query("UPDATE active_users SET totp_last_used_step = $1 WHERE id = $2")
+                    .bind(step as i64)
+                    .bind(user.id)
+                    .execute(&mut *tx)
+                    .await?;
+                true
+            }
+            SecondFactor::Totp(None) => false,
+            SecondFactor::RecoveryCode(code) => {
+                sqlx::query(
+                    "UPDATE two_factor_recovery_codes SET used_at = NOW() \
+                     WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+                )
+                .bind(user.id)
+                .bind(hash_token(&normalize_recovery_code(&code)))
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    == 1
+            }
+        };
+
+        if !accepted {
+            if failed_attempts + 1 >= MAX_CHALLENGE_ATTEMPTS {
+                sqlx::query("DELETE FROM two_factor_challenges WHERE id = $1")
+                    .bind(challenge_id)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                sqlx::query(
+                    "UPDATE two_factor_challenges SET failed_attempts = failed_attempts + 1 \
+                     WHERE id = $1",
+                )
+                .bind(challenge_id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            return Ok(ChallengeOutcome::Rejected);
+        }
+
+        // Pending tokens are single-use
+        sqlx::query("DELETE FROM two_factor_challenges WHERE id = $1")
+            .bind(challenge_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(ChallengeOutcome::Passed(Box::new(user)))
+    }
+}
@@ -0,0 +1,727 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+use crate::{
+    config::HandleSettings,
+    db::Db,
+    models::{ListUsersQuery, Session, User, UserRole},
+    utils::{
+        auth::{generate_token, hash_token},
+        error::{AppError, AppResult},
+        pagination::{Cursor, Pagination},
+        transaction::with_transaction,
+    },
+};
+
+/// Fields for a new account.
+#[derive(Debug, Clone)]
+pub struct NewUser {
+    pub email: String,
+    pub password_hash: String,
+    pub name: String,
+}
+
+/// Profile fields to change; `None` leaves a field as it is.
+#[derive(Debug, Clone, Default)]
+pub struct UserChanges {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// The columns checked on every authenticated request.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuthState {
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub password_changed_at: Option<DateTime<Utc>>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+}
+
+/// Storage for user accounts, kept behind a trait so handlers can be tested against an
+/// in-memory fake. Lookups never return soft-deleted users.
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn find_by_id(&self, id: Uuid) -> AppResult<Option<User>>;
+
+    /// Compares emails case-insensitively, like registration does.
+    async fn find_by_email(&self, email: &str) -> AppResult<Option<User>>;
+
+    async fn find_by_handle(&self, handle: &str) -> AppResult<Option<User>>;
+
+    /// The suspension and revocation state of the user, read from the primary so a change
+    /// applies to the very next request.
+    async fn auth_state(&self, id: Uuid) -> AppResult<Option<AuthState>>;
+
+    /// The user's current role, read from the primary so demotions apply immediately.
+    async fn role(&self, id: Uuid) -> AppResult<Option<UserRole>>;
+
+    /// Like [`find_by_email`](Self::find_by_email), but only while the email is unverified.
+    async fn find_unverified_by_email(&self, email: &str) -> AppResult<Option<User>>;
+
+    /// One page of the users matching `query`, with the number of matches.
+    async fn list(
+        &self,
+        query: &ListUsersQuery,
+        pagination: Pagination,
+    ) -> AppResult<(Vec<User>, i64)>;
+
+    /// Up to `limit` users created before `cursor`, newest first.
+    async fn list_before(&self, cursor: Option<Cursor>, limit: i64) -> AppResult<Vec<User>>;
+
+    /// Whether an account other than `except` already uses `email`, ignoring case.
+    async fn email_in_use(&self, email: &str, except: Option<Uuid>) -> AppResult<bool>;
+
+    /// Creates the user along with an email verification token, returned in plain text.
+    /// Either both are stored or neither is.
+    async fn create(
+        &self,
+        user: NewUser,
+        verification_expires_in_secs: i64,
+    ) -> AppResult<(User, String)>;
+
+    /// Stores a new single-use email verification token and returns it in plain text.
+    async fn create_verification_token(
+        &self,
+        user_id: Uuid,
+        expires_in_secs: i64,
+    ) -> AppResult<String>;
+
+    /// Consumes the verification `token` and marks its user's email as verified, voiding
+    /// their other verification tokens. `None` if the token is unknown, used or expired.
+    async fn mark_email_verified(&self, token: &str) -> AppResult<Option<Uuid>>;
+
+    /// Applies `changes`; a new email has to be verified again.
+    async fn update(&self, id: Uuid, changes: UserChanges) -> AppResult<User>;
+
+    /// Replaces the password hash. With `revoke_tokens`, tokens issued before now stop
+    /// authenticating.
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        revoke_tokens: bool,
+    ) -> AppResult<User>;
+
+    /// Stores a new single-use password reset token and returns it in plain text.
+    async fn create_password_reset_token(
+        &self,
+        user_id: Uuid,
+        expires_in_secs: i64,
+    ) -> AppResult<String>;
+
+    /// The email of the account the usable reset `token` belongs to.
+    async fn find_email_by_reset_token(&self, token: &str) -> AppResult<Option<String>>;
+
+    /// Consumes the reset `token` and sets its user's password, revoking tokens issued
+    /// before now and voiding their other reset tokens. `None` if the token is unknown,
+    /// used or expired.
+    async fn set_password(&self, token: &str, password_hash: &str) -> AppResult<Option<Uuid>>;
+
+    /// Suspends the user until `until`. `None` if there is no such user.
+    async fn suspend(
+        &self,
+        id: Uuid,
+        until: DateTime<Utc>,
+        reason: &str,
+    ) -> AppResult<Option<User>>;
+
+    /// Lifts a suspension. `None` if there is no such user.
+    async fn unsuspend(&self, id: Uuid) -> AppResult<Option<User>>;
+
+    /// Gives the user `role`. `None` if there is no such user.
+    async fn set_role(&self, id: Uuid, role: UserRole) -> AppResult<Option<User>>;
+
+    /// Gives the user `handle`, enforcing the change cooldown and the quarantine on
+    /// handles recently released by others.
+    async fn change_handle(
+        &self,
+        id: Uuid,
+        handle: &str,
+        settings: &HandleSettings,
+    ) -> AppResult<User>;
+
+    /// Soft-deletes the user, quarantines their handle and voids their outstanding
+    /// single-use tokens.
+    async fn soft_delete(&self, id: Uuid) -> AppResult<()>;
+
+    /// Records a new session for `user_id`, lasting as long as the token issued for it,
+    /// and returns its id.
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+        expires_in_secs: i64,
+    ) -> AppResult<Uuid>;
+
+    /// The user's unexpired sessions, most recently used first.
+    async fn list_sessions(&self, user_id: Uuid) -> AppResult<Vec<Session>>;
+
+    /// Bumps the session's `last_seen_at`. Returns whether it is still active, i.e. not
+    /// revoked or expired.
+    async fn touch_session(&self, user_id: Uuid, session_id: Uuid) -> AppResult<bool>;
+
+    /// Deletes one of the user's sessions. Returns whether it existed.
+    async fn delete_session(&self, user_id: Uuid, session_id: Uuid) -> AppResult<bool>;
+
+    /// Deletes every session of the user except `keep`, returning the deleted ids.
+    async fn delete_sessions(&self, user_id: Uuid, keep: Option<Uuid>) -> AppResult<Vec<Uuid>>;
+}
+
+/// [`UserRepository`] backed by Postgres. Lookups read from the replica when one is
+/// configured; checks that guard a write use the primary.
+#[derive(Clone)]
+pub struct PgUserRepository {
+    db: Db,
+}
+
+impl PgUserRepository {
+    pub fn new(db: Db) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgUserRepository {
+    async fn find_by_id(&self, id: Uuid) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM active_users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(self.db.read())
+            .await?;
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        let user =
+            sqlx::query_as::<_, User>("SELECT * FROM active_users WHERE lower(email) = lower($1)")
+                .bind(email)
+                .fetch_optional(self.db.read())
+                .await?;
+        Ok(user)
+    }
+
+    async fn find_by_handle(&self, handle: &str) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM active_users WHERE handle = $1")
+            .bind(handle)
+            .fetch_optional(self.db.read())
+            .await?;
+        Ok(user)
+    }
+
+    async fn auth_state(&self, id: Uuid) -> AppResult<Option<AuthState>> {
+        let auth_state = sqlx::query_as::<_, AuthState>(
+            "SELECT suspended_until, password_changed_at, email_verified_at \
+             FROM active_users WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(auth_state)
+    }
+
+    async fn role(&self, id: Uuid) -> AppResult<Option<UserRole>> {
+        let role = sqlx::query_scalar::<_, UserRole>("SELECT role FROM active_users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(self.db.write())
+            .await?;
+        Ok(role)
+    }
+
+    async fn find_unverified_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        // From the primary, so a resend right after registering finds the account
+        let user = sqlx::query_as::<_, User>(
+            "SELECT * FROM active_users \
+             WHERE lower(email) = lower($1) AND email_verified_at IS NULL",
+        )
+        .bind(email)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn list(
+        &self,
+        query: &ListUsersQuery,
+        pagination: Pagination,
+    ) -> AppResult<(Vec<User>, i64)> {
+        let pattern = query
+            .q
+            .as_deref()
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+            .map(|q| format!("%{}%", escape_like(q)));
+
+        // Sort column and direction come from whitelisted enums, never from raw input
+        let sql = format!(
+            "SELECT * FROM active_users \
+             WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1) \
+             ORDER BY {column} {order}, id {order} LIMIT $2 OFFSET $3",
+            column = query.sort.column(),
+            order = query.order.as_sql(),
+        );
+
+        let users = sqlx::query_as::<_, User>(&sql)
+            .bind(&pattern)
+            .bind(pagination.limit())
+            .bind(pagination.offset())
+            .fetch_all(self.db.read())
+            .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM active_users \
+             WHERE $1::text IS NULL OR email ILIKE $1 OR name ILIKE $1",
+        )
+        .bind(&pattern)
+        .fetch_one(self.db.read())
+        .await?;
+
+        Ok((users, total))
+    }
+
+    async fn list_before(&self, cursor: Option<Cursor>, limit: i64) -> AppResult<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            "SELECT * FROM active_users \
+             WHERE ($1::timestamptz IS NULL OR (created_at, id) < ($1, $2)) \
+             ORDER BY created_at DESC, id DESC LIMIT $3",
+        )
+        .bind(cursor.map(|c| c.created_at))
+        .bind(cursor.map(|c| c.id))
+        .bind(limit)
+        .fetch_all(self.db.read())
+        .await?;
+        Ok(users)
+    }
+
+    async fn email_in_use(&self, email: &str, except: Option<Uuid>) -> AppResult<bool> {
+        let taken = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM active_users \
+             WHERE lower(email) = lower($1) AND id IS DISTINCT FROM $2)",
+        )
+        .bind(email)
+        .bind(except)
+        .fetch_one(self.db.write())
+        .await?;
+        Ok(taken)
+    }
+
+    async fn create(
+        &self,
+        user: NewUser,
+        verification_expires_in_secs: i64,
+    ) -> AppResult<(User, String)> {
+        with_transaction(self.db.write(), move |tx| {
+            Box::pin(async move {
+                let user = sqlx::query_as::<_, User>(
+                    "INSERT INTO users (email, password_hash, name) \
+                     VALUES ($1, $2, $3) RETURNING *",
+                )
+                .bind(&user.email)
+                .bind(&user.password_hash)
+                .bind(&user.name)
+                .fetch_one(&mut **tx)
+                .await?;
+
+                let token =
+                    insert_verification_token(tx, user.id, verification_expires_in_secs).await?;
+                Ok((user, token))
+            })
+        })
+        .await
+    }
+
+    async fn create_verification_token(
+        &self,
+        user_id: Uuid,
+        expires_in_secs: i64,
+    ) -> AppResult<String> {
+        let mut conn = self.db.write().acquire().await?;
+        insert_verification_token(&mut conn, user_id, expires_in_secs).await
+    }
+
+    async fn mark_email_verified(&self, token: &str) -> AppResult<Option<Uuid>> {
+        let mut tx = self.db.write().begin().await?;
+
+        // Consume the token atomically so it can only ever be used once
+        let Some(user_id) = sqlx::query_scalar::<_, Uuid>(
+            "UPDATE email_verification_tokens SET used_at = NOW() \
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW() \
+             RETURNING user_id",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "UPDATE active_users SET email_verified_at = NOW() \
+             WHERE id = $1 AND email_verified_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Invalidate any other outstanding verification tokens for this user
+        sqlx::query(
+            "UPDATE email_verification_tokens SET used_at = NOW() \
+             WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+
+    async fn update(&self, id: Uuid, changes: UserChanges) -> AppResult<User> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET \
+                 name = COALESCE($1, name), \
+                 email = COALESCE($2, email), \
+                 email_verified_at = CASE WHEN $2 IS NULL THEN email_verified_at END \
+             WHERE id = $3 RETURNING *",
+        )
+        .bind(&changes.name)
+        .bind(&changes.email)
+        .bind(id)
+        .fetch_one(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        revoke_tokens: bool,
+    ) -> AppResult<User> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET password_hash = $1, \
+                 password_changed_at = CASE WHEN $2 THEN NOW() ELSE password_changed_at END \
+             WHERE id = $3 RETURNING *",
+        )
+        .bind(password_hash)
+        .bind(revoke_tokens)
+        .bind(id)
+        .fetch_one(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn create_password_reset_token(
+        &self,
+        user_id: Uuid,
+        expires_in_secs: i64,
+    ) -> AppResult<String> {
+        let token = generate_token();
+
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) \
+             VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+        )
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(expires_in_secs as f64)
+        .execute(self.db.write())
+        .await?;
+
+        Ok(token)
+    }
+
+    async fn find_email_by_reset_token(&self, token: &str) -> AppResult<Option<String>> {
+        let email = sqlx::query_scalar::<_, String>(
+            "SELECT u.email FROM password_reset_tokens t \
+             JOIN active_users u ON u.id = t.user_id \
+             WHERE t.token_hash = $1 AND t.used_at IS NULL AND t.expires_at > NOW()",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(email)
+    }
+
+    async fn set_password(&self, token: &str, password_hash: &str) -> AppResult<Option<Uuid>> {
+        let mut tx = self.db.write().begin().await?;
+
+        // Consume the token atomically so it can only ever be used once
+        let Some(user_id) = sqlx::query_scalar::<_, Uuid>(
+            "UPDATE password_reset_tokens SET used_at = NOW() \
+             WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW() \
+             RETURNING user_id",
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query(
+            "UPDATE active_users SET password_hash = $1, password_changed_at = NOW() WHERE id = $2",
+        )
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Invalidate any other outstanding reset tokens for this user
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+
+    async fn suspend(
+        &self,
+        id: Uuid,
+        until: DateTime<Utc>,
+        reason: &str,
+    ) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET suspended_until = $1, suspension_reason = $2 \
+             WHERE id = $3 RETURNING *",
+        )
+        .bind(until)
+        .bind(reason)
+        .bind(id)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn unsuspend(&self, id: Uuid) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET suspended_until = NULL, suspension_reason = NULL \
+             WHERE id = $1 RETURNING *",
+        )
+        .bind(id)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn set_role(&self, id: Uuid, role: UserRole) -> AppResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET role = $1 WHERE id = $2 RETURNING *",
+        )
+        .bind(role)
+        .bind(id)
+        .fetch_optional(self.db.write())
+        .await?;
+        Ok(user)
+    }
+
+    async fn change_handle(
+        &self,
+        id: Uuid,
+        handle: &str,
+        settings: &HandleSettings,
+    ) -> AppResult<User> {
+        let mut tx = self.db.write().begin().await?;
+
+        // Lock the row so concurrent changes by the same user serialize on the cooldown check
+        let user = sqlx::query_as::<_, User>("SELECT * FROM active_users WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if user.handle.as_deref() == Some(handle) {
+            return Ok(user);
+        }
+
+        // Claiming a first handle is free; changing it is rate limited
+        if let (Some(_), Some(changed_at)) = (&user.handle, user.handle_changed_at) {
+            let next_change = changed_at + Duration::seconds(settings.change_cooldown_secs);
+            let remaining = (next_change - Utc::now()).num_seconds();
+            if remaining > 0 {
+                return Err(AppError::TooManyRequests(remaining as u64));
+            }
+        }
+
+        // Handles recently released by someone else stay quarantined to prevent impersonation
+        let quarantined = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM handle_history \
+             WHERE handle = $1 AND user_id <> $2 AND released_at > $3)",
+        )
+        .bind(handle)
+        .bind(id)
+        .bind(Utc::now() - Duration::seconds(settings.quarantine_secs))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if quarantined {
+            return Err(AppError::conflict("Handle is not available"));
+        }
+
+        // The unique index settles concurrent claims of the same handle
+        let updated = sqlx::query_as::<_, User>(
+            "UPDATE active_users SET handle = $1, handle_changed_at = NOW() \
+             WHERE id = $2 RETURNING *",
+        )
+        .bind(handle)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let Some(old_handle) = &user.handle {
+            sqlx::query("INSERT INTO handle_history (user_id, handle) VALUES ($1, $2)")
+                .bind(id)
+                .bind(old_handle)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    async fn soft_delete(&self, id: Uuid) -> AppResult<()> {
+        let mut tx = self.db.write().begin().await?;
+
+        // The row is kept but excluded from every lookup, which also stops the user's
+        // outstanding tokens from authenticating
+        sqlx::query("UPDATE users SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Quarantine the handle so nobody can take it over straight away
+        sqlx::query(
+            "INSERT INTO handle_history (user_id, handle) \
+             SELECT id, handle FROM users WHERE id = $1 AND handle IS NOT NULL",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Invalidate any outstanding single-use tokens
+        sqlx::query(
+            "UPDATE password_reset_tokens SET used_at = NOW() \
+             WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            "UPDATE email_verification_tokens SET used_at = NOW() \
+             WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        ip: Option<&str>,
+        expires_in_secs: i64,
+    ) -> AppResult<Uuid> {
+        // Drop this user's sessions whose tokens have expired anyway
+        sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND expires_at < NOW()")
+            .bind(user_id)
+            .execute(self.db.write())
+            .await?;
+
+        let session_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO sessions (user_id, user_agent, ip, expires_at) \
+             VALUES ($1, $2, $3, NOW() + make_interval(secs => $4)) \
+             RETURNING id",
+        )
+        .bind(user_id)
+        .bind(user_agent)
+        .bind(ip)
+        .bind(expires_in_secs as f64)
+        .fetch_one(self.db.write())
+        .await?;
+        Ok(session_id)
+    }
+
+    async fn list_sessions(&self, user_id: Uuid) -> AppResult<Vec<Session>> {
+        let sessions = sqlx::query_as::<_, Session>(
+            "SELECT * FROM sessions WHERE user_id = $1 AND expires_at > NOW() \
+             ORDER BY last_seen_at DESC, id",
+        )
+        .bind(user_id)
+        .fetch_all(self.db.read())
+        .await?;
+        Ok(sessions)
+    }
+
+    async fn touch_session(&self, user_id: Uuid, session_id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query(
+            "UPDATE sessions SET last_seen_at = NOW() \
+             WHERE id = $1 AND user_id = $2 AND expires_at > NOW()",
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(self.db.write())
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_session(&self, user_id: Uuid, session_id: Uuid) -> AppResult<bool> {
+        let result = sqlx::query("DELETE FROM sessions WHERE id = $1 AND user_id = $2")
+            .bind(session_id)
+            .bind(user_id)
+            .execute(self.db.write())
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete_sessions(&self, user_id: Uuid, keep: Option<Uuid>) -> AppResult<Vec<Uuid>> {
+        // `IS DISTINCT FROM` so that no `keep` deletes them all
+        let deleted = sqlx::query_scalar::<_, Uuid>(
+            "DELETE FROM sessions WHERE user_id = $1 AND id IS DISTINCT FROM $2 RETURNING id",
+        )
+        .bind(user_id)
+        .bind(keep)
+        .fetch_all(self.db.write())
+        .await?;
+        Ok(deleted)
+    }
+}
+
+/// Escapes `LIKE` wildcards so user input is matched literally.
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Stores a new single-use verification token for `user_id` and returns it. Takes a
+/// connection so the token can be created in the same transaction as the user.
+async fn insert_verification_token(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    expires_in_secs: i64,
+) -> AppResult<String> {
+    let token = generate_token();
+
+    sqlx::query(
+        "INSERT INTO email_verification_tokens (user_id, token_hash, expires_at) \
+         VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+    )
+    .bind(user_id)
+    .bind(hash_token(&token))
+    .bind(expires_in_secs as f64)
+    .execute(conn)
+    .await?;
+
+    Ok(token)
+}
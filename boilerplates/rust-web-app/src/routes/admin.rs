@@ -0,0 +1,169 @@
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    middleware::{auth::AdminUser, validated_json::ValidatedJson},
+    models::{ListUsersQuery, SuspendUserRequest, UserResponse},
+    utils::{
+        error::{AppError, AppResult},
+        pagination::{Cursor, CursorPagination, Pagination},
+        response::{ApiResponse, CursorPage, PaginatedResponse, ResponseMeta},
+    },
+    AppState,
+};
+
+/// Log target for admin actions. Audit records are log events only; there is no audit
+/// table, so keep them by shipping logs somewhere durable.
+const AUDIT_TARGET: &str = "audit";
+
+/// Emails a user about a change to their account in the background.
+fn notify_user(state: &AppState, email: String, subject: &'static str, body: String) {
+    let mailer = state.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&email, subject, &body).await {
+            tracing::error!("Failed to send account notification email: {}", e);
+        }
+    });
+}
+
+async fn list_users(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    pagination: Pagination,
+    query: Result<Query<ListUsersQuery>, QueryRejection>,
+) -> AppResult<Json<ApiResponse<PaginatedResponse<UserResponse>>>> {
+    let Query(query) = query.map_err(|e| AppError::BadRequest(e.body_text()))?;
+
+    let (users, total) = state.users.list(&query, pagination).await?;
+
+    let page = PaginatedResponse::new(
+        users.into_iter().map(UserResponse::from).collect(),
+        total,
+        pagination,
+    );
+    Ok(Json(ApiResponse::success_with_meta(
+        page,
+        ResponseMeta::new(total, pagination),
+    )))
+}
+
+/// Keyset-paginated user listing, newest first. Unlike `list_users` it stays fast on
+/// deep pages because it never scans past skipped rows.
+async fn list_users_by_cursor(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    pagination: CursorPagination,
+) -> AppResult<Json<ApiResponse<CursorPage<UserResponse>>>> {
+    let users = state
+        .users
+        .list_before(pagination.cursor, pagination.fetch_limit())
+        .await?;
+
+    let page = CursorPage::new(users, pagination, |user| {
+        Cursor::new(user.created_at, user.id)
+    });
+
+    Ok(Json(ApiResponse::success(page.map(UserResponse::from))))
+}
+
+async fn suspend_user(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<SuspendUserRequest>,
+) -> AppResult<Json<ApiResponse<UserResponse>>> {
+    if admin.user_id == user_id {
+        return Err(AppError::BadRequest(
+            "Admins cannot suspend their own account".to_string(),
+        ));
+    }
+
+    let until = match (payload.duration_secs, payload.until) {
+        (Some(secs), None) => Utc::now() + Duration::seconds(secs),
+        (None, Some(until)) => until,
+        _ => {
+            return Err(AppError::BadRequest(
+                "Provide exactly one of duration_secs or until".to_string(),
+            ))
+        }
+    };
+
+    if until <= Utc::now() {
+        return Err(AppError::BadRequest(
+            "Suspension must end in the future".to_string(),
+        ));
+    }
+
+    let user = state
+        .users
+        .suspend(user_id, until, &payload.reason)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    state.cache.invalidate_user(user.id).await;
+
+    tracing::info!(
+        target: AUDIT_TARGET,
+        action = "user.suspend",
+        admin_id = %admin.user_id,
+        user_id = %user.id,
+        suspended_until = %until,
+        reason = %payload.reason,
+        "User suspended"
+    );
+
+    notify_user(
+        &state,
+        user.email.clone(),
+        "Your account has been suspended",
+        format!(
+            "Your account has been suspended until {}.\n\nReason: {}",
+            until.to_rfc3339(),
+            payload.reason
+        ),
+    );
+
+    Ok(Json(ApiResponse::success(user.into())))
+}
+
+async fn unsuspend_user(
+    admin: AdminUser,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<Json<ApiResponse<UserResponse>>> {
+    let user = state
+        .users
+        .unsuspend(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+    state.cache.invalidate_user(user.id).await;
+
+    tracing::info!(
+        target: AUDIT_TARGET,
+        action = "user.unsuspend",
+        admin_id = %admin.user_id,
+        user_id = %user.id,
+        "User unsuspended"
+    );
+
+    notify_user(
+        &state,
+        user.email.clone(),
+        "Your account has been reinstated",
+        "Your account suspension has been lifted.".to_string(),
+    );
+
+    Ok(Json(ApiResponse::success(user.into())))
+}
+
+pub fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/admin/users", get(list_users))
+        .route("/admin/users/cursor", get(list_users_by_cursor))
+        .route("/admin/users/:id/suspend", post(suspend_user))
+        .route("/admin/users/:id/unsuspend", post(unsuspend_user))
+}
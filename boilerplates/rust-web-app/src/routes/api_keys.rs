@@ -0,0 +1,119 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::{
+    middleware::{auth::AuthUser, validated_json::ValidatedJson},
+    models::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKeyResponse},
+    repositories::NewApiKey,
+    utils::{
+        api_key::generate_api_key,
+        error::{AppError, AppResult, ErrorResponse},
+        response::ApiResponse,
+    },
+    AppState,
+};
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/api-keys",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "Key created; the plaintext key is only returned here", body = ApiResponse<CreatedApiKeyResponse>),
+        (status = 400, description = "Invalid name, scopes or expiry", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn create_api_key(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<CreateApiKeyRequest>,
+) -> AppResult<(StatusCode, Json<ApiResponse<CreatedApiKeyResponse>>)> {
+    if payload
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(AppError::BadRequest(
+            "Expiry must be in the future".to_string(),
+        ));
+    }
+
+    let key = generate_api_key();
+    let api_key = state
+        .api_keys
+        .create(NewApiKey {
+            user_id: auth_user.user_id,
+            name: payload.name.trim().to_string(),
+            key: key.clone(),
+            scopes: payload.scopes,
+            expires_at: payload.expires_at,
+        })
+        .await?;
+
+    let response = CreatedApiKeyResponse {
+        key,
+        api_key: api_key.into(),
+    };
+    Ok((StatusCode::CREATED, Json(ApiResponse::success(response))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/api-keys",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The current user's API keys, newest first", body = ApiResponse<Vec<ApiKeyResponse>>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn list_api_keys(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> AppResult<Json<ApiResponse<Vec<ApiKeyResponse>>>> {
+    let keys = state.api_keys.list(auth_user.user_id).await?;
+
+    Ok(Json(ApiResponse::success(
+        keys.into_iter().map(ApiKeyResponse::from).collect(),
+    )))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/me/api-keys/{id}",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    params(("id" = Uuid, Path, description = "API key id")),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such key for the current user", body = ErrorResponse),
+    )
+)]
+async fn revoke_api_key(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    if !state.api_keys.delete(auth_user.user_id, id).await? {
+        return Err(AppError::NotFound("API key not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn api_key_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/users/me/api-keys",
+            get(list_api_keys).post(create_api_key),
+        )
+        .route("/users/me/api-keys/:id", delete(revoke_api_key))
+}
@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    routing::{get, post},
+    Json, Router,
+};
+use jsonwebtoken::jwk::JwkSet;
+use validator::ValidateArgs;
+
+use super::sessions::revoke_all_sessions;
+use crate::{
+    middleware::{
+        csrf::csrf_cookie,
+        validated_json::{JsonBody, ValidatedJson},
+    },
+    models::{
+        CsrfTokenResponse, ForgotPasswordRequest, ResendVerificationRequest, ResetPasswordRequest,
+        User, VerifyEmailQuery,
+    },
+    utils::{
+        auth::{generate_token, hash_password},
+        error::{AppError, AppResult, ErrorResponse},
+        password_policy::PasswordContext,
+        response::ApiResponse,
+    },
+    AppState,
+};
+
+const FORGOT_PASSWORD_MESSAGE: &str =
+    "If an account exists for that email, password reset instructions have been sent";
+
+const RESEND_VERIFICATION_MESSAGE: &str =
+    "If an unverified account exists for that email, a verification email has been sent";
+
+/// Issues a single-use email verification token for `user` and mails it in the background.
+pub(super) async fn send_verification_email(state: &AppState, user: &User) -> AppResult<()> {
+    let token = state
+        .users
+        .create_verification_token(
+            user.id,
+            state.config.application.email_verification_expiration,
+        )
+        .await?;
+
+    mail_verification_token(state, &user.email, token);
+    Ok(())
+}
+
+/// Mails a verification token in the background.
+pub(super) fn mail_verification_token(state: &AppState, email: &str, token: String) {
+    let mailer = state.mailer.clone();
+    let email = email.to_string();
+    tokio::spawn(async move {
+        let body = format!(
+            "Use the following token to verify your email address: {}",
+            token
+        );
+        if let Err(e) = mailer
+            .send(&email, "Verify your email address", &body)
+            .await
+        {
+            tracing::error!("Failed to send verification email: {}", e);
+        }
+    });
+}
+
+async fn verify_email(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> AppResult<Json<ApiResponse<()>>> {
+    let user_id = state
+        .users
+        .mark_email_verified(&query.token)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired verification token".to_string()))?;
+    state.cache.invalidate_user(user_id).await;
+
+    Ok(Json(ApiResponse::success_with_message(
+        (),
+        "Email address verified".to_string(),
+    )))
+}
+
+async fn resend_verification(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<ResendVerificationRequest>,
+) -> AppResult<Json<ApiResponse<()>>> {
+    let user = state.users.find_unverified_by_email(&payload.email).await?;
+
+    // Respond identically whether or not the account exists to avoid user enumeration
+    if let Some(user) = user {
+        send_verification_email(&state, &user).await?;
+    }
+
+    Ok(Json(ApiResponse::success_with_message(
+        (),
+        RESEND_VERIFICATION_MESSAGE.to_string(),
+    )))
+}
+
+async fn forgot_password(
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<ForgotPasswordRequest>,
+) -> AppResult<Json<ApiResponse<()>>> {
+    let user = state.users.find_by_email(&payload.email).await?;
+
+    // Respond identically whether or not the account exists to avoid user enumeration
+    if let Some(user) = user {
+        let token = state
+            .users
+            .create_password_reset_token(
+                user.id,
+                state.config.application.password_reset_expiration,
+            )
+            .await?;
+
+        // Send in the background so response time doesn't reveal whether the account exists
+        let mailer = state.mailer.clone();
+        tokio::spawn(async move {
+            let body = format!(
+                "Use the following token to reset your password: {}\n\n\
+                 If you did not request a password reset, you can ignore this email.",
+                token
+            );
+            if let Err(e) = mailer.send(&user.email, "Reset your password", &body).await {
+                tracing::error!("Failed to send password reset email: {}", e);
+            }
+        });
+    }
+
+    Ok(Json(ApiResponse::success_with_message(
+        (),
+        FORGOT_PASSWORD_MESSAGE.to_string(),
+    )))
+}
+
+async fn reset_password(
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<ResetPasswordRequest>,
+) -> AppResult<Json<ApiResponse<()>>> {
+    let invalid_token = || AppError::BadRequest("Invalid or expired reset token".to_string());
+
+    // The policy needs the account's email, so look it up before the token is consumed.
+    // The request is validated first, so field errors are reported whatever the token.
+    let email = state
+        .users
+        .find_email_by_reset_token(&payload.token)
+        .await?;
+    payload.validate_with_args(&PasswordContext {
+        policy: &state.config.application.password_policy,
+        email: email.as_deref().unwrap_or_default(),
+    })?;
+    email.ok_or_else(invalid_token)?;
+
+    let password_hash = hash_password(&payload.new_password, &state.config.application).await?;
+
+    let user_id = state
+        .users
+        .set_password(&payload.token, &password_hash)
+        .await?
+        .ok_or_else(invalid_token)?;
+    state.cache.invalidate_user(user_id).await;
+    revoke_all_sessions(&state, user_id).await?;
+
+    Ok(Json(ApiResponse::success_with_message(
+        (),
+        "Password has been reset".to_string(),
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/csrf-token",
+    tag = "auth",
+    responses(
+        (status = 200, description = "New CSRF token, also set as the csrf_token cookie", body = ApiResponse<CsrfTokenResponse>),
+        (status = 404, description = "Cookie authentication is disabled", body = ErrorResponse),
+    )
+)]
+async fn csrf_token(
+    State(state): State<AppState>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<CsrfTokenResponse>>)> {
+    if !state.config.auth.cookie_enabled {
+        return Err(AppError::NotFound(
+            "Cookie authentication is disabled".to_string(),
+        ));
+    }
+
+    let token = generate_token();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::SET_COOKIE,
+        csrf_cookie(&token, state.config.application.jwt_expiration),
+    );
+
+    Ok((
+        headers,
+        Json(ApiResponse::success(CsrfTokenResponse {
+            csrf_token: token,
+        })),
+    ))
+}
+
+/// Public keys for verifying tokens offline, as a JWK set. Empty when tokens are signed
+/// with the shared HS256 secret.
+async fn jwks(State(state): State<AppState>) -> Json<JwkSet> {
+    Json(state.jwt.jwks().clone())
+}
+
+pub fn auth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/auth/forgot-password", post(forgot_password))
+        .route("/auth/reset-password", post(reset_password))
+        .route("/auth/verify-email", get(verify_email))
+        .route("/auth/verify", get(verify_email))
+        .route("/auth/resend-verification", post(resend_verification))
+        .route("/auth/csrf-token", get(csrf_token))
+}
+
+pub fn key_routes() -> Router<AppState> {
+    Router::new().route("/auth/jwks.json", get(jwks))
+}
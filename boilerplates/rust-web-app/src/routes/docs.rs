@@ -0,0 +1,79 @@
+use axum::Router;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use super::{api_keys, auth, health, oauth, sessions, two_factor, users};
+use crate::AppState;
+
+/// OpenAPI description of the public API, served at `/api/docs/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Rust Web App API"),
+    paths(
+        health::liveness_check,
+        health::readiness_check,
+        users::register,
+        users::login,
+        users::logout,
+        users::get_profile,
+        users::update_profile,
+        users::change_password,
+        users::claim_handle,
+        users::get_user_by_handle,
+        users::get_user,
+        users::delete_account,
+        two_factor::setup,
+        two_factor::enable,
+        two_factor::disable,
+        two_factor::verify,
+        auth::csrf_token,
+        oauth::start,
+        oauth::callback,
+        api_keys::create_api_key,
+        api_keys::list_api_keys,
+        api_keys::revoke_api_key,
+        sessions::list_sessions,
+        sessions::revoke,
+        sessions::revoke_other_sessions,
+    ),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "health", description = "Liveness and readiness"),
+        (name = "auth", description = "Registration and login"),
+        (name = "users", description = "The current user's account and public profiles"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer_auth` scheme referenced by protected operations, and the
+/// `api_key` header machine-to-machine clients can use in its place.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        );
+    }
+}
+
+/// Swagger UI at `/api/docs` backed by the generated spec.
+pub fn docs_routes() -> Router<AppState> {
+    SwaggerUi::new("/api/docs")
+        .url("/api/docs/openapi.json", ApiDoc::openapi())
+        .into()
+}
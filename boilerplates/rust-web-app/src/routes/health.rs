@@ -1,41 +1,231 @@
-use axum::{extract::State, routing::get, Json, Router};
+use std::{
+    fmt,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
 use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{db, AppState};
 
-use crate::AppState;
+/// Key read to check the cache connection; it never exists.
+const READINESS_CACHE_KEY: &str = "health:readiness";
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct HealthResponse {
     status: String,
     version: String,
+    /// Git commit the binary was built from, or `unknown`.
+    commit: String,
+    /// RFC 3339 build timestamp.
+    built_at: String,
+    /// Seconds since the process started; resets on every restart.
+    uptime_seconds: u64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ReadinessResponse {
+    /// `ready`, or `not_ready` with a 503 status.
     status: String,
+    /// `connected`, `disconnected` or `timeout`; a summary of `checks.database`.
     database: String,
+    checks: ReadinessChecks,
+}
+
+#[derive(Serialize, ToSchema)]
+struct ReadinessChecks {
+    database: CheckResult,
+    /// Present only when `database.replica_url` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replica: Option<CheckResult>,
+    /// Fails while migrations embedded in the binary haven't been applied.
+    migrations: MigrationsCheck,
+    /// Reported only; the app keeps serving from the database while the cache is down.
+    cache: CheckResult,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Error,
+    Timeout,
+    /// Not run because a check it depends on failed.
+    Skipped,
+}
+
+#[derive(Serialize, ToSchema)]
+struct CheckResult {
+    status: CheckStatus,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MigrationsCheck {
+    #[serde(flatten)]
+    check: CheckResult,
+    applied: usize,
+    /// Versions of migrations that haven't been applied yet.
+    pending: Vec<i64>,
+}
+
+impl CheckResult {
+    fn skipped() -> Self {
+        CheckResult {
+            status: CheckStatus::Skipped,
+            latency_ms: 0,
+            error: None,
+        }
+    }
+}
+
+/// Runs one readiness check under `timeout`, recording how long it took and why it failed.
+async fn run_check<T, E: fmt::Display>(
+    name: &str,
+    timeout: Duration,
+    check: impl Future<Output = Result<T, E>>,
+) -> (CheckResult, Option<T>) {
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(timeout, check).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let (status, error, value) = match outcome {
+        Ok(Ok(value)) => (CheckStatus::Ok, None, Some(value)),
+        Ok(Err(e)) => (CheckStatus::Error, Some(format!("{:#}", e)), None),
+        Err(_) => (
+            CheckStatus::Timeout,
+            Some(format!("timed out after {}ms", timeout.as_millis())),
+            None,
+        ),
+    };
+    if let Some(error) = &error {
+        tracing::warn!(check = name, "Readiness check failed: {}", error);
+    }
+
+    let result = CheckResult {
+        status,
+        latency_ms,
+        error,
+    };
+    (result, value)
 }
 
-async fn health_check() -> Json<HealthResponse> {
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "Process is up; does not check dependencies", body = HealthResponse))
+)]
+async fn liveness_check(State(state): State<AppState>) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "healthy".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        commit: env!("GIT_SHA").to_string(),
+        built_at: env!("BUILD_TIME").to_string(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
     })
 }
 
-async fn readiness_check(State(state): State<AppState>) -> Json<ReadinessResponse> {
-    let db_status = match sqlx::query("SELECT 1").fetch_one(&state.db).await {
-        Ok(_) => "connected",
-        Err(_) => "disconnected",
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = ReadinessResponse),
+        (status = 503, description = "Database or replica unreachable or too slow to answer, or migrations pending", body = ReadinessResponse),
+    )
+)]
+async fn readiness_check(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    // Each check is bounded so a hung dependency fails the probe instead of hanging it
+    let timeout = state.config.database.readiness_timeout();
+
+    let database_checks = async {
+        let ping = sqlx::query("SELECT 1").execute(state.db.write());
+        let (database, _) = run_check("database", timeout, ping).await;
+        if database.status != CheckStatus::Ok {
+            let migrations = MigrationsCheck {
+                check: CheckResult::skipped(),
+                applied: 0,
+                pending: Vec::new(),
+            };
+            return (database, migrations);
+        }
+
+        let (mut check, status) = run_check(
+            "migrations",
+            timeout,
+            db::migration_status(state.db.write()),
+        )
+        .await;
+        let status = status.unwrap_or_default();
+        if !status.pending.is_empty() {
+            check.status = CheckStatus::Error;
+            check.error = Some(format!("{} pending migrations", status.pending.len()));
+            tracing::warn!(
+                check = "migrations",
+                "Readiness check failed: pending migrations"
+            );
+        }
+        let migrations = MigrationsCheck {
+            check,
+            applied: status.applied.len(),
+            pending: status.pending.iter().map(|m| m.version).collect(),
+        };
+        (database, migrations)
     };
+    let replica_check = async {
+        match state.db.replica() {
+            Some(replica) => {
+                let ping = sqlx::query("SELECT 1").execute(replica);
+                Some(run_check("replica", timeout, ping).await.0)
+            }
+            None => None,
+        }
+    };
+    let cache_check = run_check("cache", timeout, state.cache.get(READINESS_CACHE_KEY));
 
-    Json(ReadinessResponse {
-        status: "ready".to_string(),
-        database: db_status.to_string(),
-    })
+    let ((database, migrations), replica, (cache, _)) =
+        tokio::join!(database_checks, replica_check, cache_check);
+
+    let summary = match database.status {
+        CheckStatus::Ok => "connected",
+        CheckStatus::Timeout => "timeout",
+        CheckStatus::Error | CheckStatus::Skipped => "disconnected",
+    };
+    let ready = database.status == CheckStatus::Ok
+        && migrations.check.status == CheckStatus::Ok
+        && replica
+            .iter()
+            .all(|replica| replica.status == CheckStatus::Ok);
+    let (status, readiness) = if ready {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+
+    (
+        status,
+        Json(ReadinessResponse {
+            status: readiness.to_string(),
+            database: summary.to_string(),
+            checks: ReadinessChecks {
+                database,
+                replica,
+                migrations,
+                cache,
+            },
+        }),
+    )
 }
 
 pub fn health_routes() -> Router<AppState> {
     Router::new()
-        .route("/", get(health_check))
+        // `/health` predates the split and stays as an alias for liveness
+        .route("/", get(liveness_check))
+        .route("/live", get(liveness_check))
         .route("/ready", get(readiness_check))
 }
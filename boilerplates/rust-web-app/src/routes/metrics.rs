@@ -0,0 +1,23 @@
+use axum::{extract::State, routing::get, Extension, Router};
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use crate::AppState;
+
+async fn metrics_handler(
+    State(state): State<AppState>,
+    Extension(handle): Extension<PrometheusHandle>,
+) -> String {
+    // Pool stats are sampled at scrape time
+    metrics::gauge!("db_pool_connections").set(state.db.write().size() as f64);
+    metrics::gauge!("db_pool_idle_connections").set(state.db.write().num_idle() as f64);
+
+    handle.render()
+}
+
+/// `GET /metrics` in Prometheus text format. Kept separate from the API routers so it
+/// can be mounted on an internal listener instead of the public one.
+pub fn metrics_routes(handle: PrometheusHandle) -> Router<AppState> {
+    Router::new()
+        .route("/metrics", get(metrics_handler))
+        .layer(Extension(handle))
+}
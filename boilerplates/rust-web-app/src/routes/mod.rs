@@ -1,5 +1,55 @@
+mod admin;
+mod api_keys;
+mod auth;
+mod docs;
 mod health;
+mod metrics;
+mod oauth;
+mod sessions;
+mod two_factor;
 mod users;
 
+use std::sync::Arc;
+
+use axum::{
+    middleware::{from_fn, from_fn_with_state},
+    Router,
+};
+
+use crate::{
+    config::Settings,
+    middleware::{csrf::require_csrf_token, rate_limit::limit_by_ip},
+    utils::rate_limit::IpRateLimiter,
+    AppState,
+};
+
+pub use docs::{docs_routes, ApiDoc};
 pub use health::health_routes;
-pub use users::api_routes;
+pub use metrics::metrics_routes;
+
+pub fn api_routes(settings: &Settings) -> Router<AppState> {
+    let limiter = Arc::new(IpRateLimiter::new(&settings.rate_limit));
+    // Endpoints that take credentials or send email are throttled per client IP
+    let throttled = Router::new()
+        .merge(users::credential_routes())
+        .merge(auth::auth_routes())
+        .merge(two_factor::two_factor_routes())
+        .merge(oauth::oauth_routes())
+        .route_layer(from_fn_with_state(limiter, limit_by_ip));
+
+    let routes = Router::new()
+        .merge(throttled)
+        .merge(users::user_routes())
+        .merge(api_keys::api_key_routes())
+        .merge(sessions::session_routes())
+        .merge(auth::key_routes())
+        .merge(admin::admin_routes());
+
+    // Browsers attach cookies to cross-site requests on their own, so requests they
+    // authenticate have to prove they come from our frontend
+    if settings.auth.cookie_enabled {
+        routes.route_layer(from_fn(require_csrf_token))
+    } else {
+        routes
+    }
+}
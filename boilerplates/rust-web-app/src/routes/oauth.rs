@@ -0,0 +1,158 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Redirect,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use super::{
+    sessions::{issue_token, SessionClient},
+    two_factor::start_challenge,
+    users::token_cookie_headers,
+};
+use crate::{
+    models::{AuthResponse, LoginResponse, UserResponse},
+    utils::{
+        auth::generate_token,
+        error::{AppError, AppResult, ErrorResponse},
+        oauth::OAuthClient,
+        response::ApiResponse,
+    },
+    AppState,
+};
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct OAuthCallbackQuery {
+    /// Authorization code; absent when the user declined.
+    code: Option<String>,
+    state: Option<String>,
+    /// Set by the provider when the user declined or the request was invalid.
+    error: Option<String>,
+}
+
+fn oauth_client<'a>(state: &'a AppState, provider: &str) -> AppResult<OAuthClient<'a>> {
+    OAuthClient::new(provider, &state.config.oauth)
+        .ok_or_else(|| AppError::NotFound("Unknown OAuth provider".to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/oauth/{provider}/start",
+    tag = "auth",
+    params(("provider" = String, Path, description = "`google` or `github`")),
+    responses(
+        (status = 303, description = "Redirect to the provider's sign-in page"),
+        (status = 404, description = "Unknown or unconfigured provider", body = ErrorResponse),
+    )
+)]
+async fn start(State(state): State<AppState>, Path(provider): Path<String>) -> AppResult<Redirect> {
+    let client = oauth_client(&state, &provider)?;
+    let oauth_state = generate_token();
+    let code_verifier = generate_token();
+
+    state
+        .oauth
+        .create_state(
+            &oauth_state,
+            client.provider.name(),
+            &code_verifier,
+            state.config.oauth.state_ttl_secs,
+        )
+        .await?;
+
+    Ok(Redirect::to(
+        &client.authorize_url(&oauth_state, &code_verifier)?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/auth/oauth/{provider}/callback",
+    tag = "auth",
+    params(
+        ("provider" = String, Path, description = "`google` or `github`"),
+        OAuthCallbackQuery,
+    ),
+    responses(
+        (status = 200, description = "Logged in, or a pending token when two-factor authentication is enabled", body = ApiResponse<LoginResponse>),
+        (status = 400, description = "Invalid or expired state, declined login, rejected code or no verified email", body = ErrorResponse),
+        (status = 403, description = "Email not verified", body = ErrorResponse),
+        (status = 404, description = "Unknown or unconfigured provider", body = ErrorResponse),
+        (status = 503, description = "The provider could not be reached", body = ErrorResponse),
+    )
+)]
+async fn callback(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    session_client: SessionClient,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<LoginResponse>>)> {
+    let client = oauth_client(&state, &provider)?;
+
+    let code_verifier = state
+        .oauth
+        .take_state(
+            query.state.as_deref().unwrap_or_default(),
+            client.provider.name(),
+        )
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired OAuth state".to_string()))?;
+
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            return Err(AppError::BadRequest(format!(
+                "OAuth login was not completed: {}",
+                error.as_deref().unwrap_or("no authorization code")
+            )))
+        }
+    };
+
+    let access_token = client.exchange_code(&code, &code_verifier).await?;
+    let profile = client.fetch_profile(&access_token).await?;
+
+    let user = state
+        .oauth
+        .find_or_create_user(client.provider.name(), profile)
+        .await?;
+    state.cache.invalidate_user(user.id).await;
+
+    if state.config.application.require_email_verification && user.email_verified_at.is_none() {
+        return Err(AppError::EmailNotVerified);
+    }
+
+    // The token is only issued once the second factor checks out
+    if user.totp_enabled_at.is_some() {
+        let challenge = start_challenge(&state, user.id).await?;
+        return Ok((
+            HeaderMap::new(),
+            Json(ApiResponse::success(LoginResponse::TwoFactorRequired(
+                challenge,
+            ))),
+        ));
+    }
+
+    let token = issue_token(&state, user.id, &session_client).await?;
+    let headers = token_cookie_headers(&state, &token);
+
+    let response = AuthResponse {
+        token,
+        user: UserResponse::for_owner(user),
+    };
+
+    Ok((
+        headers,
+        Json(ApiResponse::success(LoginResponse::Authenticated(response))),
+    ))
+}
+
+/// OAuth login. The callback logs users in, so it is throttled along with the other
+/// credential endpoints.
+pub fn oauth_routes() -> Router<AppState> {
+    Router::new()
+        .route("/auth/oauth/:provider/start", get(start))
+        .route("/auth/oauth/:provider/callback", get(callback))
+}
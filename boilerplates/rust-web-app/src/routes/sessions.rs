@@ -0,0 +1,184 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    routing::{delete, get},
+    Json, Router,
+};
+use uuid::Uuid;
+
+use super::users::cleared_cookie_headers;
+use crate::{
+    middleware::{auth::AuthUser, client_ip::ClientIp},
+    models::SessionResponse,
+    utils::{
+        error::{AppError, AppResult, ErrorResponse},
+        response::ApiResponse,
+    },
+    AppState,
+};
+
+/// Longer `User-Agent` headers are cut to this many characters before they are stored.
+const MAX_USER_AGENT_CHARS: usize = 512;
+
+/// What a new session records about the client signing in.
+pub struct SessionClient {
+    user_agent: Option<String>,
+    ip: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionClient
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_CHARS).collect());
+
+        Ok(SessionClient {
+            user_agent,
+            ip: ip.map(|ip| ip.to_string()),
+        })
+    }
+}
+
+/// Starts a session for `user_id` and signs a token for it. The token stops working
+/// when the session is revoked.
+pub(super) async fn issue_token(
+    state: &AppState,
+    user_id: Uuid,
+    client: &SessionClient,
+) -> AppResult<String> {
+    let session_id = state
+        .users
+        .create_session(
+            user_id,
+            client.user_agent.as_deref(),
+            client.ip.as_deref(),
+            state.config.application.jwt_expiration,
+        )
+        .await?;
+    state
+        .jwt
+        .create_session_jwt(&user_id.to_string(), session_id)
+}
+
+/// Revokes every session of `user_id`, e.g. after its password changed.
+pub(super) async fn revoke_all_sessions(state: &AppState, user_id: Uuid) -> AppResult<()> {
+    let revoked = state.users.delete_sessions(user_id, None).await?;
+    state.cache.invalidate_sessions(&revoked).await;
+    Ok(())
+}
+
+/// Revokes the session `auth_user` authenticated with, if any. Returns whether there
+/// was one to revoke.
+pub(super) async fn revoke_current_session(
+    state: &AppState,
+    auth_user: &AuthUser,
+) -> AppResult<bool> {
+    let Some(session_id) = auth_user.session_id else {
+        return Ok(false);
+    };
+    revoke_session(state, auth_user.user_id, session_id).await
+}
+
+async fn revoke_session(state: &AppState, user_id: Uuid, session_id: Uuid) -> AppResult<bool> {
+    let revoked = state.users.delete_session(user_id, session_id).await?;
+    state.cache.invalidate_sessions(&[session_id]).await;
+    Ok(revoked)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/sessions",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The current user's active sessions, most recently used first", body = ApiResponse<Vec<SessionResponse>>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn list_sessions(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> AppResult<Json<ApiResponse<Vec<SessionResponse>>>> {
+    let sessions = state.users.list_sessions(auth_user.user_id).await?;
+
+    Ok(Json(ApiResponse::success(
+        sessions
+            .into_iter()
+            .map(|session| SessionResponse::new(session, auth_user.session_id))
+            .collect(),
+    )))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/me/sessions/{id}",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    params(("id" = Uuid, Path, description = "Session id")),
+    responses(
+        (status = 204, description = "Session revoked; revoking the current session also clears the auth cookies"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No such session for the current user", body = ErrorResponse),
+    )
+)]
+async fn revoke(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<(HeaderMap, StatusCode)> {
+    if !revoke_session(&state, auth_user.user_id, id).await? {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+
+    // Revoking the current session is a logout
+    let headers = if auth_user.session_id == Some(id) {
+        cleared_cookie_headers()
+    } else {
+        HeaderMap::new()
+    };
+
+    Ok((headers, StatusCode::NO_CONTENT))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/me/sessions",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Every session but the current one revoked"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn revoke_other_sessions(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> AppResult<StatusCode> {
+    // API key requests have no session, so they revoke them all
+    let revoked = state
+        .users
+        .delete_sessions(auth_user.user_id, auth_user.session_id)
+        .await?;
+    state.cache.invalidate_sessions(&revoked).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn session_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/users/me/sessions",
+            get(list_sessions).delete(revoke_other_sessions),
+        )
+        .route("/users/me/sessions/:id", delete(revoke))
+}
@@ -0,0 +1,225 @@
+use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+use uuid::Uuid;
+
+use super::{
+    sessions::{issue_token, SessionClient},
+    users::{find_current_user, no_password_set, token_cookie_headers},
+};
+use crate::{
+    middleware::{auth::AuthUser, validated_json::ValidatedJson},
+    models::{
+        AuthResponse, DisableTwoFactorRequest, EnableTwoFactorRequest, TwoFactorChallengeResponse,
+        TwoFactorEnabledResponse, TwoFactorSetupResponse, UserResponse, VerifyTwoFactorRequest,
+    },
+    repositories::two_factor::{ChallengeOutcome, SecondFactor},
+    utils::{
+        auth::verify_password,
+        error::{AppError, AppResult, ErrorResponse},
+        response::ApiResponse,
+        two_factor::{
+            base32_encode, current_step, generate_recovery_codes, generate_secret, is_totp_code,
+            otpauth_uri, verify_totp, SecretCipher,
+        },
+    },
+    AppState,
+};
+
+fn already_enabled() -> AppError {
+    AppError::conflict("Two-factor authentication is already enabled")
+}
+
+/// Starts the second step of a login for a user with two-factor authentication enabled.
+pub(super) async fn start_challenge(
+    state: &AppState,
+    user_id: Uuid,
+) -> AppResult<TwoFactorChallengeResponse> {
+    let ttl = state.config.two_factor.challenge_ttl_secs;
+    let token = state.two_factor.create_challenge(user_id, ttl).await?;
+
+    Ok(TwoFactorChallengeResponse {
+        two_factor_required: true,
+        pending_token: token,
+        expires_in: ttl,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/2fa/setup",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Secret generated; confirm it with a code to enable 2FA", body = ApiResponse<TwoFactorSetupResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication is already enabled", body = ErrorResponse),
+        (status = 503, description = "Two-factor authentication is not configured", body = ErrorResponse),
+    )
+)]
+async fn setup(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> AppResult<Json<ApiResponse<TwoFactorSetupResponse>>> {
+    let cipher = SecretCipher::from_settings(&state.config.two_factor)?;
+    let user = find_current_user(&state, auth_user.user_id).await?;
+    if user.totp_enabled_at.is_some() {
+        return Err(already_enabled());
+    }
+
+    // Replaces any earlier unconfirmed secret
+    let secret = generate_secret();
+    state
+        .two_factor
+        .set_totp_secret(user.id, &cipher.encrypt(user.id, &secret)?)
+        .await?;
+
+    let response = TwoFactorSetupResponse {
+        secret: base32_encode(&secret),
+        otpauth_uri: otpauth_uri(&state.config.two_factor.issuer, &user.email, &secret),
+    };
+    Ok(Json(ApiResponse::success(response)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/2fa/enable",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = EnableTwoFactorRequest,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled", body = ApiResponse<TwoFactorEnabledResponse>),
+        (status = 400, description = "Setup not started or wrong code", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication is already enabled", body = ErrorResponse),
+    )
+)]
+async fn enable(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<EnableTwoFactorRequest>,
+) -> AppResult<Json<ApiResponse<TwoFactorEnabledResponse>>> {
+    let cipher = SecretCipher::from_settings(&state.config.two_factor)?;
+    let user = find_current_user(&state, auth_user.user_id).await?;
+    if user.totp_enabled_at.is_some() {
+        return Err(already_enabled());
+    }
+    let encrypted = user.totp_secret.as_deref().ok_or_else(|| {
+        AppError::BadRequest("Start two-factor setup before enabling it".to_string())
+    })?;
+
+    let secret = cipher.decrypt(user.id, encrypted)?;
+    let step = verify_totp(&secret, &payload.code, current_step(), None)
+        .ok_or_else(|| AppError::BadRequest("Invalid two-factor code".to_string()))?;
+
+    let recovery_codes = generate_recovery_codes();
+    state
+        .two_factor
+        .enable_totp(user.id, step, &recovery_codes)
+        .await?;
+    state.cache.invalidate_user(user.id).await;
+
+    Ok(Json(ApiResponse::success_with_message(
+        TwoFactorEnabledResponse { recovery_codes },
+        "Two-factor authentication enabled. Store the recovery codes somewhere safe".to_string(),
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/me/2fa/disable",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = DisableTwoFactorRequest,
+    responses(
+        (status = 200, description = "Two-factor authentication disabled"),
+        (status = 400, description = "The account has no password yet", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or wrong password", body = ErrorResponse),
+    )
+)]
+async fn disable(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<DisableTwoFactorRequest>,
+) -> AppResult<Json<ApiResponse<()>>> {
+    let user = find_current_user(&state, auth_user.user_id).await?;
+
+    let password_hash = user.password_hash.as_deref().ok_or_else(no_password_set)?;
+    if !verify_password(&payload.password, password_hash).await? {
+        return Err(AppError::Unauthorized("Password is incorrect".to_string()));
+    }
+
+    state.two_factor.disable_totp(user.id).await?;
+    state.cache.invalidate_user(user.id).await;
+
+    Ok(Json(ApiResponse::success_with_message(
+        (),
+        "Two-factor authentication disabled".to_string(),
+    )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/2fa/verify",
+    tag = "auth",
+    request_body = VerifyTwoFactorRequest,
+    responses(
+        (status = 200, description = "Logged in", body = ApiResponse<AuthResponse>),
+        (status = 401, description = "Wrong code, or unknown or expired pending token", body = ErrorResponse),
+    )
+)]
+async fn verify(
+    State(state): State<AppState>,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<VerifyTwoFactorRequest>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<AuthResponse>>)> {
+    let invalid_challenge =
+        || AppError::Unauthorized("Invalid or expired two-factor challenge".to_string());
+    let cipher = SecretCipher::from_settings(&state.config.two_factor)?;
+
+    let code = payload.code;
+    let outcome = state
+        .two_factor
+        .complete_challenge(&payload.pending_token, |user, encrypted| {
+            if !is_totp_code(&code) {
+                return Ok(SecondFactor::RecoveryCode(code.clone()));
+            }
+            let secret = cipher.decrypt(user.id, encrypted)?;
+            let last_used = user.totp_last_used_step.map(|step| step as u64);
+            Ok(SecondFactor::Totp(verify_totp(
+                &secret,
+                &code,
+                current_step(),
+                last_used,
+            )))
+        })
+        .await?;
+
+    let user = match outcome {
+        ChallengeOutcome::Passed(user) => *user,
+        ChallengeOutcome::Rejected => {
+            return Err(AppError::Unauthorized(
+                "Invalid two-factor code".to_string(),
+            ))
+        }
+        ChallengeOutcome::Invalid => return Err(invalid_challenge()),
+    };
+
+    let token = issue_token(&state, user.id, &client).await?;
+    let headers = token_cookie_headers(&state, &token);
+
+    let response = AuthResponse {
+        token,
+        user: UserResponse::for_owner(user),
+    };
+
+    Ok((headers, Json(ApiResponse::success(response))))
+}
+
+/// Two-factor endpoints. They take passwords and codes, so they are throttled along with
+/// the other credential endpoints.
+pub fn two_factor_routes() -> Router<AppState> {
+    Router::new()
+        .route("/users/me/2fa/setup", post(setup))
+        .route("/users/me/2fa/enable", post(enable))
+        .route("/users/me/2fa/disable", post(disable))
+        .route("/auth/2fa/verify", post(verify))
+}
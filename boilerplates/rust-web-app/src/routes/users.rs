@@ -1,120 +1,545 @@
 use axum::{
-    extract::State,
-    routing::{get, post},
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::{get, post, put},
     Json, Router,
 };
-use validator::Validate;
+use uuid::Uuid;
+use validator::ValidateArgs;
 
+use super::{
+    auth::{mail_verification_token, send_verification_email},
+    sessions::{issue_token, revoke_all_sessions, revoke_current_session, SessionClient},
+    two_factor::start_challenge,
+};
 use crate::{
-    middleware::auth::AuthUser,
-    models::{AuthResponse, CreateUserRequest, LoginRequest, User, UserResponse},
+    middleware::{
+        auth::{
+            access_token_cookie, expired_cookie, AuthUser, OptionalAuthUser, ACCESS_TOKEN_COOKIE,
+        },
+        client_ip::ClientIp,
+        csrf::{csrf_cookie, CSRF_COOKIE},
+        validated_json::{JsonBody, ValidatedJson},
+    },
+    models::{
+        AuthResponse, ChangePasswordRequest, ClaimHandleRequest, CreateUserRequest, LoginRequest,
+        LoginResponse, PublicUserResponse, UpdateUserRequest, User, UserResponse,
+    },
+    repositories::{NewUser, UserChanges},
     utils::{
-        auth::{create_jwt, hash_password, verify_password},
-        error::{AppError, AppResult},
+        auth::{dummy_password_hash, generate_token, hash_password, needs_rehash, verify_password},
+        cache::profile_key,
+        error::{AppError, AppResult, ErrorResponse},
+        handle::{is_reserved_handle, normalize_handle},
+        password_policy::PasswordContext,
         response::ApiResponse,
     },
     AppState,
 };
 
+/// For accounts created through an OAuth provider, which have nothing to confirm a
+/// password-protected change with.
+pub(super) fn no_password_set() -> AppError {
+    AppError::BadRequest(
+        "No password is set for this account; set one with the password reset flow".to_string(),
+    )
+}
+
+/// Sets the token as a cookie too when `auth.cookie_enabled` is on, along with a fresh
+/// CSRF token for the requests the cookie authenticates.
+pub(super) fn token_cookie_headers(state: &AppState, token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if state.config.auth.cookie_enabled {
+        let max_age = state.config.application.jwt_expiration;
+        headers.append(header::SET_COOKIE, access_token_cookie(token, max_age));
+        headers.append(header::SET_COOKIE, csrf_cookie(&generate_token(), max_age));
+    }
+    headers
+}
+
+/// `Set-Cookie` headers that make the browser forget the auth cookies.
+pub(super) fn cleared_cookie_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.append(header::SET_COOKIE, expired_cookie(ACCESS_TOKEN_COOKIE));
+    headers.append(header::SET_COOKIE, expired_cookie(CSRF_COOKIE));
+    headers
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    tag = "auth",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 200, description = "User registered", body = ApiResponse<AuthResponse>),
+        (status = 409, description = "Email already in use", body = ErrorResponse),
+        (status = 422, description = "Validation failed", body = ErrorResponse),
+    )
+)]
 async fn register(
     State(state): State<AppState>,
-    Json(payload): Json<CreateUserRequest>,
-) -> AppResult<Json<ApiResponse<AuthResponse>>> {
-    // Validate input
-    payload
-        .validate()
-        .map_err(|e| AppError::ValidationError(e.to_string()))?;
-
-    // Check if user already exists
-    let existing_user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
-        .bind(&payload.email)
-        .fetch_optional(&state.db)
-        .await?;
+    client: SessionClient,
+    JsonBody(payload): JsonBody<CreateUserRequest>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<AuthResponse>>)> {
+    payload.validate_with_args(&PasswordContext {
+        policy: &state.config.application.password_policy,
+        email: &payload.email,
+    })?;
 
-    if existing_user.is_some() {
-        return Err(AppError::BadRequest("User already exists".to_string()));
+    // Check if user already exists; emails are unique regardless of case
+    if state.users.email_in_use(&payload.email, None).await? {
+        return Err(AppError::already_in_use("email"));
     }
 
     // Hash password
-    let password_hash = hash_password(&payload.password)?;
+    let password_hash = hash_password(&payload.password, &state.config.application).await?;
 
-    // Create user
-    let user = sqlx::query_as::<_, User>(
-        "INSERT INTO users (email, password_hash, name) VALUES ($1, $2, $3) RETURNING *",
-    )
-    .bind(&payload.email)
-    .bind(&password_hash)
-    .bind(&payload.name)
-    .fetch_one(&state.db)
-    .await?;
+    // Create the user and its verification token together, so a failure can't leave an
+    // account behind that was never sent a token
+    let new_user = NewUser {
+        email: payload.email,
+        password_hash,
+        name: payload.name,
+    };
+    let (user, verification_token) = state
+        .users
+        .create(
+            new_user,
+            state.config.application.email_verification_expiration,
+        )
+        .await?;
+
+    // Only mail the token once it has been committed
+    mail_verification_token(&state, &user.email, verification_token);
 
     // Generate JWT token
-    let token = create_jwt(
-        &user.id.to_string(),
-        &state.config.application.jwt_secret,
-        state.config.application.jwt_expiration,
-    )?;
+    let token = issue_token(&state, user.id, &client).await?;
+    let headers = token_cookie_headers(&state, &token);
 
     let response = AuthResponse {
         token,
-        user: user.into(),
+        user: UserResponse::for_owner(user),
     };
 
-    Ok(Json(ApiResponse::success(response)))
+    Ok((headers, Json(ApiResponse::success(response))))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in, or a pending token when two-factor authentication is enabled", body = ApiResponse<LoginResponse>),
+        (status = 401, description = "Invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Email not verified", body = ErrorResponse),
+        (status = 429, description = "Too many failed attempts", body = ErrorResponse),
+    )
+)]
 async fn login(
     State(state): State<AppState>,
-    Json(payload): Json<LoginRequest>,
-) -> AppResult<Json<ApiResponse<AuthResponse>>> {
-    // Validate input
-    payload
-        .validate()
-        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    ClientIp(ip): ClientIp,
+    client: SessionClient,
+    ValidatedJson(payload): ValidatedJson<LoginRequest>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<LoginResponse>>)> {
+    // Throttle repeated failures for the same email, from one address or from anywhere
+    if let Err(retry_after) = state.login_limiter.check(&payload.email, ip) {
+        return Err(AppError::TooManyRequests(retry_after.as_secs().max(1)));
+    }
 
     // Find user by email
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
-        .bind(&payload.email)
-        .fetch_optional(&state.db)
-        .await?
-        .ok_or_else(|| AppError::Unauthorized("Invalid credentials".to_string()))?;
+    let user = state.users.find_by_email(&payload.email).await?;
 
-    // Verify password
-    let valid = verify_password(&payload.password, &user.password_hash)?;
-    if !valid {
-        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+    // Verify password. Unknown emails, and accounts that only sign in through an OAuth
+    // provider, are checked against a dummy hash so that response times don't reveal which
+    // emails are registered.
+    let hash = match user.as_ref().and_then(|user| user.password_hash.as_deref()) {
+        Some(hash) => hash,
+        None => dummy_password_hash(&state.config.application).await?,
+    };
+    let valid = verify_password(&payload.password, hash).await?;
+
+    let user = match user {
+        Some(user) if valid && user.password_hash.is_some() => user,
+        _ => {
+            state.login_limiter.record_failure(&payload.email, ip);
+            return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+        }
+    };
+
+    state.login_limiter.reset(&payload.email, ip);
+
+    // Transparently upgrade hashes from the other scheme or older cost settings now that
+    // we know the plaintext
+    if user
+        .password_hash
+        .as_deref()
+        .is_some_and(|hash| needs_rehash(hash, &state.config.application))
+    {
+        if let Err(e) = rehash_password(&state, &user, &payload.password).await {
+            tracing::warn!(user_id = %user.id, "Failed to upgrade password hash: {}", e);
+        }
+    }
+
+    if state.config.application.require_email_verification && user.email_verified_at.is_none() {
+        return Err(AppError::EmailNotVerified);
+    }
+
+    // The token is only issued once the second factor checks out
+    if user.totp_enabled_at.is_some() {
+        let challenge = start_challenge(&state, user.id).await?;
+        return Ok((
+            HeaderMap::new(),
+            Json(ApiResponse::success(LoginResponse::TwoFactorRequired(
+                challenge,
+            ))),
+        ));
     }
 
     // Generate JWT token
-    let token = create_jwt(
-        &user.id.to_string(),
-        &state.config.application.jwt_secret,
-        state.config.application.jwt_expiration,
-    )?;
+    let token = issue_token(&state, user.id, &client).await?;
+    let headers = token_cookie_headers(&state, &token);
 
     let response = AuthResponse {
         token,
-        user: user.into(),
+        user: UserResponse::for_owner(user),
     };
 
-    Ok(Json(ApiResponse::success(response)))
+    Ok((
+        headers,
+        Json(ApiResponse::success(LoginResponse::Authenticated(response))),
+    ))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Session revoked and auth cookies cleared"),
+        (status = 403, description = "Missing or invalid CSRF token", body = ErrorResponse),
+    )
+)]
+async fn logout(
+    auth_user: Option<AuthUser>,
+    State(state): State<AppState>,
+) -> AppResult<(HeaderMap, Json<ApiResponse<()>>)> {
+    // Revoke the token's session so it stops working even if it was copied elsewhere.
+    // Invalid or missing credentials still get their cookies cleared.
+    if let Some(auth_user) = auth_user {
+        revoke_current_session(&state, &auth_user).await?;
+    }
+
+    Ok((
+        cleared_cookie_headers(),
+        Json(ApiResponse::success_with_message(
+            (),
+            "Logged out".to_string(),
+        )),
+    ))
+}
+
+async fn rehash_password(state: &AppState, user: &User, password: &str) -> AppResult<()> {
+    let password_hash = hash_password(password, &state.config.application).await?;
+
+    state
+        .users
+        .update_password(user.id, &password_hash, false)
+        .await?;
+    state.cache.invalidate_user(user.id).await;
+
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Current user", body = ApiResponse<UserResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
 async fn get_profile(
     auth_user: AuthUser,
     State(state): State<AppState>,
 ) -> AppResult<Json<ApiResponse<UserResponse>>> {
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
-        .bind(&auth_user.user_id)
-        .fetch_one(&state.db)
+    let key = profile_key(auth_user.user_id);
+    if let Some(profile) = state.cache.get_json::<UserResponse>(&key).await {
+        return Ok(Json(ApiResponse::success(profile)));
+    }
+
+    let user = find_current_user(&state, auth_user.user_id).await?;
+
+    let profile = UserResponse::for_owner(user);
+    state
+        .cache
+        .set_json(&key, &profile, state.config.cache.ttl())
+        .await;
+
+    Ok(Json(ApiResponse::success(profile)))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/users/me",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "Updated user", body = ApiResponse<UserResponse>),
+        (status = 400, description = "No fields to update", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Email is already in use", body = ErrorResponse),
+        (status = 422, description = "Validation failed", body = ErrorResponse),
+    )
+)]
+async fn update_profile(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    ValidatedJson(payload): ValidatedJson<UpdateUserRequest>,
+) -> AppResult<Json<ApiResponse<UserResponse>>> {
+    if payload.name.is_none() && payload.email.is_none() {
+        return Err(AppError::BadRequest("No fields to update".to_string()));
+    }
+
+    let current = find_current_user(&state, auth_user.user_id).await?;
+
+    // Only treat the email as changed if it differs from the current one
+    let new_email = payload.email.filter(|email| *email != current.email);
+
+    if let Some(email) = &new_email {
+        if state
+            .users
+            .email_in_use(email, Some(auth_user.user_id))
+            .await?
+        {
+            return Err(AppError::already_in_use("email"));
+        }
+    }
+
+    // Only overwrite provided fields; a new email must be verified again
+    let changes = UserChanges {
+        name: payload.name,
+        email: new_email.clone(),
+    };
+    // Losing a race for the email to a concurrent change is the same 409 as the check above
+    let user = state.users.update(auth_user.user_id, changes).await?;
+    state.cache.invalidate_user(user.id).await;
+
+    if new_email.is_some() {
+        send_verification_email(&state, &user).await?;
+    }
+
+    Ok(Json(ApiResponse::success(UserResponse::for_owner(user))))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/users/me/password",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 200, description = "Password changed; returns a fresh token", body = ApiResponse<AuthResponse>),
+        (status = 400, description = "The account has no password yet", body = ErrorResponse),
+        (status = 401, description = "Current password is incorrect", body = ErrorResponse),
+        (status = 422, description = "Validation failed, including a new password equal to the current one", body = ErrorResponse),
+    )
+)]
+async fn change_password(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    client: SessionClient,
+    JsonBody(payload): JsonBody<ChangePasswordRequest>,
+) -> AppResult<Json<ApiResponse<AuthResponse>>> {
+    let user = find_current_user(&state, auth_user.user_id).await?;
+    payload.validate_change(&PasswordContext {
+        policy: &state.config.application.password_policy,
+        email: &user.email,
+    })?;
+
+    // Verify the current password
+    let current_hash = user.password_hash.as_deref().ok_or_else(no_password_set)?;
+    let valid = verify_password(&payload.current_password, current_hash).await?;
+    if !valid {
+        return Err(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ));
+    }
+
+    let password_hash = hash_password(&payload.new_password, &state.config.application).await?;
+
+    // Bumping password_changed_at revokes every token issued before this change
+    let user = state
+        .users
+        .update_password(auth_user.user_id, &password_hash, true)
+        .await?;
+    state.cache.invalidate_user(user.id).await;
+    revoke_all_sessions(&state, user.id).await?;
+
+    // Issue a fresh token so the caller stays signed in
+    let token = issue_token(&state, user.id, &client).await?;
+
+    let response = AuthResponse {
+        token,
+        user: UserResponse::for_owner(user),
+    };
+
+    Ok(Json(ApiResponse::success(response)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/users/me/handle",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    request_body = ClaimHandleRequest,
+    responses(
+        (status = 200, description = "Handle claimed", body = ApiResponse<UserResponse>),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 409, description = "Handle taken, reserved or quarantined", body = ErrorResponse),
+        (status = 422, description = "Invalid handle", body = ErrorResponse),
+        (status = 429, description = "Handle changed too recently", body = ErrorResponse),
+    )
+)]
+async fn claim_handle(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+    Json(payload): Json<ClaimHandleRequest>,
+) -> AppResult<Json<ApiResponse<UserResponse>>> {
+    let settings = &state.config.handles;
+
+    let handle = normalize_handle(&payload.handle)?;
+    if is_reserved_handle(&handle, &settings.reserved) {
+        return Err(AppError::conflict("Handle is not available"));
+    }
+
+    let updated = state
+        .users
+        .change_handle(auth_user.user_id, &handle, settings)
         .await?;
+    state.cache.invalidate_user(auth_user.user_id).await;
+
+    Ok(Json(ApiResponse::success(UserResponse::for_owner(updated))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/by-handle/{handle}",
+    tag = "users",
+    security((), ("bearer_auth" = [])),
+    params(("handle" = String, Path, description = "Handle, with or without a leading @")),
+    responses(
+        (status = 200, description = "Public profile", body = ApiResponse<PublicUserResponse>),
+        (status = 401, description = "Token supplied but invalid", body = ErrorResponse),
+        (status = 404, description = "No user with that handle", body = ErrorResponse),
+    )
+)]
+async fn get_user_by_handle(
+    OptionalAuthUser(auth_user): OptionalAuthUser,
+    State(state): State<AppState>,
+    Path(handle): Path<String>,
+) -> AppResult<Json<ApiResponse<PublicUserResponse>>> {
+    let not_found = || AppError::NotFound("User not found".to_string());
+
+    let handle = normalize_handle(&handle).map_err(|_| not_found())?;
+
+    let user = state
+        .users
+        .find_by_handle(&handle)
+        .await?
+        .ok_or_else(not_found)?;
+
+    Ok(Json(ApiResponse::success(PublicUserResponse::new(
+        user,
+        auth_user.map(|auth_user| auth_user.user_id),
+    ))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}",
+    tag = "users",
+    security((), ("bearer_auth" = [])),
+    params(("id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Public profile", body = ApiResponse<PublicUserResponse>),
+        (status = 401, description = "Token supplied but invalid", body = ErrorResponse),
+        (status = 404, description = "No user with that id", body = ErrorResponse),
+    )
+)]
+async fn get_user(
+    OptionalAuthUser(auth_user): OptionalAuthUser,
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> AppResult<Json<ApiResponse<PublicUserResponse>>> {
+    let not_found = || AppError::NotFound("User not found".to_string());
+
+    // A malformed id can't name a user, so it gets the same 404 as an unknown one
+    let id = id.parse::<Uuid>().map_err(|_| not_found())?;
 
-    Ok(Json(ApiResponse::success(user.into())))
+    let user = state.users.find_by_id(id).await?.ok_or_else(not_found)?;
+
+    Ok(Json(ApiResponse::success(PublicUserResponse::new(
+        user,
+        auth_user.map(|auth_user| auth_user.user_id),
+    ))))
 }
 
-pub fn api_routes() -> Router<AppState> {
+#[utoipa::path(
+    delete,
+    path = "/api/users/me",
+    tag = "users",
+    security(("bearer_auth" = [])),
+    responses(
+        (status = 204, description = "Account deleted"),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    )
+)]
+async fn delete_account(
+    auth_user: AuthUser,
+    State(state): State<AppState>,
+) -> AppResult<StatusCode> {
+    // Soft delete: the row is kept but excluded from every lookup, which also stops
+    // the user's outstanding tokens from authenticating
+    state.users.soft_delete(auth_user.user_id).await?;
+    state.cache.invalidate_user(auth_user.user_id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The authenticated user's row. The auth extractor already rejected deleted users, so
+/// a miss means the account was deleted mid-request.
+pub(super) async fn find_current_user(state: &AppState, user_id: Uuid) -> AppResult<User> {
+    state
+        .users
+        .find_by_id(user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+pub fn credential_routes() -> Router<AppState> {
     Router::new()
         .route("/auth/register", post(register))
         .route("/auth/login", post(login))
-        .route("/users/me", get(get_profile))
+        .route("/auth/logout", post(logout))
+}
+
+pub fn user_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/users/me",
+            get(get_profile)
+                .patch(update_profile)
+                .delete(delete_account),
+        )
+        .route(
+            "/users/me/password",
+            put(change_password).post(change_password),
+        )
+        .route("/users/me/handle", put(claim_handle))
+        .route("/users/by-handle/:handle", get(get_user_by_handle))
+        .route("/users/:id", get(get_user))
 }
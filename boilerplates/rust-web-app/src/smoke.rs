@@ -0,0 +1,286 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use reqwest::{RequestBuilder, StatusCode, Url};
+use serde::Serialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::utils::http::client;
+
+/// The instance [`run`] exercises, and how.
+pub struct SmokeTestOptions {
+    pub base_url: Url,
+    /// Each request failing to complete within this fails its step.
+    pub timeout: Duration,
+    /// Leave the disposable account as registered: no profile update, no deletion.
+    pub skip_destructive: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SmokeTestReport {
+    pub base_url: String,
+    pub passed: bool,
+    pub duration_ms: u64,
+    /// The disposable account's email, so a leftover account can be found.
+    pub email: String,
+    pub steps: Vec<StepReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StepReport {
+    pub name: &'static str,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    /// Not run, because an earlier step failed or destructive steps were skipped.
+    Skipped,
+}
+
+impl SmokeTestReport {
+    /// The first step that failed, if any.
+    pub fn failed_step(&self) -> Option<&StepReport> {
+        self.steps
+            .iter()
+            .find(|step| step.status == StepStatus::Failed)
+    }
+}
+
+/// Health check, register, login, fetch the profile, update it and delete the account.
+/// A failed step skips the ones after it, except the deletion, which still cleans up
+/// any account that was registered.
+pub async fn run(options: &SmokeTestOptions) -> SmokeTestReport {
+    let started = Instant::now();
+    let target = Target {
+        base_url: &options.base_url,
+        timeout: options.timeout,
+    };
+    let suffix = Uuid::new_v4().simple().to_string();
+    let email = format!("smoke-test+{}@example.com", suffix);
+    // Meets every character class a password policy can ask for
+    let password = format!("Smoke-{}-1", suffix);
+    let mut steps = Steps::default();
+
+    steps
+        .run("health", async {
+            target
+                .expect(target.get("/health/ready"), StatusCode::OK)
+                .await
+        })
+        .await;
+
+    let registered = steps
+        .run("register", async {
+            let body = json!({ "email": email, "password": password, "name": "Smoke Test" });
+            let response = target
+                .expect(
+                    target.post("/api/auth/register").json(&body),
+                    StatusCode::OK,
+                )
+                .await?;
+            token(&response)
+        })
+        .await;
+
+    let logged_in = steps
+        .run("login", async {
+            let body = json!({ "email": email, "password": password });
+            let response = target
+                .expect(target.post("/api/auth/login").json(&body), StatusCode::OK)
+                .await?;
+            token(&response)
+        })
+        .await;
+
+    steps
+        .run("profile", async {
+            let token = logged_in.as_deref().unwrap_or_default();
+            let response = target
+                .expect(
+                    target.get("/api/users/me").bearer_auth(token),
+                    StatusCode::OK,
+                )
+                .await?;
+            expect_field(&response, "email", &email)
+        })
+        .await;
+
+    if options.skip_destructive {
+        steps.skip("update_profile");
+        steps.skip("delete_account");
+    } else {
+        steps
+            .run("update_profile", async {
+                let token = logged_in.as_deref().unwrap_or_default();
+                let body = json!({ "name": "Smoke Test Updated" });
+                let response = target
+                    .expect(
+                        target.patch("/api/users/me").bearer_auth(token).json(&body),
+                        StatusCode::OK,
+                    )
+                    .await?;
+                expect_field(&response, "name", "Smoke Test Updated")
+            })
+            .await;
+
+        match logged_in.or(registered) {
+            Some(token) => {
+                steps
+                    .run_always("delete_account", async {
+                        let request = target.delete("/api/users/me").bearer_auth(token);
+                        target.expect(request, StatusCode::NO_CONTENT).await
+                    })
+                    .await;
+            }
+            None => steps.skip("delete_account"),
+        }
+    }
+
+    let passed = !steps.failed;
+    SmokeTestReport {
+        base_url: options.base_url.to_string(),
+        passed,
+        duration_ms: started.elapsed().as_millis() as u64,
+        email,
+        steps: steps.reports,
+    }
+}
+
+struct Target<'a> {
+    base_url: &'a Url,
+    timeout: Duration,
+}
+
+impl Target<'_> {
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.as_str().trim_end_matches('/'), path)
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        client().get(self.url(path))
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        client().post(self.url(path))
+    }
+
+    fn patch(&self, path: &str) -> RequestBuilder {
+        client().patch(self.url(path))
+    }
+
+    fn delete(&self, path: &str) -> RequestBuilder {
+        client().delete(self.url(path))
+    }
+
+    /// Sends `request` and returns its JSON body, `null` when it has none, or an error
+    /// naming the status and error code when the status isn't `expected`.
+    async fn expect(&self, request: RequestBuilder, expected: StatusCode) -> Result<Value, String> {
+        let response = request
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(describe)?;
+        let status = response.status();
+        let text = response.text().await.map_err(describe)?;
+        let body = serde_json::from_str(&text).unwrap_or(Value::Null);
+
+        if status != expected {
+            return Err(match (&body["error"], &body["message"]) {
+                (Value::String(code), Value::String(message)) => {
+                    format!(
+                        "expected {}, got {}: {}: {}",
+                        expected, status, code, message
+                    )
+                }
+                _ => format!("expected {}, got {}", expected, status),
+            });
+        }
+        Ok(body)
+    }
+}
+
+/// The error with its causes, e.g. the connection being refused.
+fn describe(error: reqwest::Error) -> String {
+    format!("{:#}", anyhow::Error::from(error))
+}
+
+fn token(response: &Value) -> Result<String, String> {
+    response["data"]["token"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| "response has no data.token".to_string())
+}
+
+fn expect_field(response: &Value, field: &str, expected: &str) -> Result<(), String> {
+    match response["data"][field].as_str() {
+        Some(value) if value == expected => Ok(()),
+        value => Err(format!(
+            "expected data.{} to be {:?}, got {:?}",
+            field, expected, value
+        )),
+    }
+}
+
+#[derive(Default)]
+struct Steps {
+    reports: Vec<StepReport>,
+    failed: bool,
+}
+
+impl Steps {
+    /// Times `step` and records the outcome, or skips it once a step has failed.
+    async fn run<T>(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<T, String>>,
+    ) -> Option<T> {
+        if self.failed {
+            self.skip(name);
+            return None;
+        }
+        self.run_always(name, step).await
+    }
+
+    /// Like [`Self::run`], but runs even after a failure, for cleanup.
+    async fn run_always<T>(
+        &mut self,
+        name: &'static str,
+        step: impl Future<Output = Result<T, String>>,
+    ) -> Option<T> {
+        let started = Instant::now();
+        let result = step.await;
+        let (status, error) = match &result {
+            Ok(_) => (StepStatus::Passed, None),
+            Err(e) => {
+                self.failed = true;
+                (StepStatus::Failed, Some(e.clone()))
+            }
+        };
+        self.reports.push(StepReport {
+            name,
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error,
+        });
+        result.ok()
+    }
+
+    fn skip(&mut self, name: &'static str) {
+        self.reports.push(StepReport {
+            name,
+            status: StepStatus::Skipped,
+            duration_ms: 0,
+            error: None,
+        });
+    }
+}
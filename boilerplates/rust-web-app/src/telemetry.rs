@@ -0,0 +1,228 @@
+use anyhow::{Context, Result};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use sentry::{types::Dsn, ClientInitGuard};
+use std::{fmt, sync::Arc};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    fmt::{
+        format::{FmtSpan, Format, Json, JsonFields, Writer},
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+    },
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter,
+};
+
+use crate::{
+    config::{LogFormat, LoggingSettings, Settings, TelemetrySettings},
+    middleware::{error_context::current_error_context, request_id::current_request_id},
+};
+
+const DEFAULT_LOG_FILTER: &str = "rust_web_app=debug,tower_http=debug";
+
+/// Latency buckets in seconds for `http_request_duration_seconds`.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// OTLP/HTTP path spans are posted to, relative to `telemetry.otlp_endpoint`.
+const OTLP_TRACES_PATH: &str = "/v1/traces";
+
+/// Installs the global tracing subscriber. `logging.level` takes precedence over
+/// `RUST_LOG`; an invalid filter is an error rather than a silent fallback. Spans are
+/// also exported through `tracer` when given.
+pub fn init(settings: &LoggingSettings, tracer: Option<&TracerGuard>) -> Result<()> {
+    let filter = match &settings.level {
+        Some(level) => EnvFilter::try_new(level)
+            .with_context(|| format!("Invalid logging.level filter: {:?}", level))?,
+        None => {
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER))
+        }
+    };
+
+    // Exactly one of the two layers is installed
+    let (pretty, json) = match settings.format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .fmt_fields(JsonFields::new())
+                    // Emit a record with busy/idle timings when each span closes
+                    .with_span_events(FmtSpan::CLOSE)
+                    .event_format(RequestIdJson(
+                        tracing_subscriber::fmt::format()
+                            .json()
+                            .flatten_event(true)
+                            .with_current_span(true)
+                            .with_span_list(false),
+                    )),
+            ),
+        ),
+    };
+
+    let otlp = tracer.map(|guard| {
+        tracing_opentelemetry::layer().with_tracer(guard.provider().tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(pretty)
+        .with(json)
+        .with(otlp)
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+
+    Ok(())
+}
+
+/// Owns the OTLP span exporter. Buffered spans are flushed when it is dropped, so keep it
+/// alive until shutdown.
+pub struct TracerGuard(SdkTracerProvider);
+
+impl TracerGuard {
+    pub fn provider(&self) -> &SdkTracerProvider {
+        &self.0
+    }
+}
+
+impl Drop for TracerGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            tracing::warn!("Failed to flush spans to the OTLP collector: {}", e);
+        }
+    }
+}
+
+/// Builds the OTLP span exporter when `telemetry.otlp_endpoint` is set. Spans are sent
+/// in batches from a background thread, so a slow collector doesn't hold up requests.
+pub fn init_tracer(settings: &TelemetrySettings) -> Result<Option<TracerGuard>> {
+    let Some(endpoint) = &settings.otlp_endpoint else {
+        return Ok(None);
+    };
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!(
+            "{}{}",
+            endpoint.trim_end_matches('/'),
+            OTLP_TRACES_PATH
+        ))
+        .build()
+        .context("Invalid telemetry.otlp_endpoint")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(settings.service_name.clone())
+                .build(),
+        )
+        .build();
+    Ok(Some(TracerGuard(provider)))
+}
+
+/// JSON event format that copies `request_id` from the enclosing request span to a
+/// top-level field, so log shippers can index it without digging into `span`.
+struct RequestIdJson(Format<Json>);
+
+impl<S, N> FormatEvent<S, N> for RequestIdJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut line = String::new();
+        self.0.format_event(ctx, Writer::new(&mut line), event)?;
+
+        // Span fields are stored as a JSON object by `JsonFields`
+        let request_id = ctx.event_scope().and_then(|scope| {
+            scope.into_iter().find_map(|span| {
+                let extensions = span.extensions();
+                let fields = extensions.get::<FormattedFields<N>>()?;
+                let fields: serde_json::Value = serde_json::from_str(fields).ok()?;
+                fields.get("request_id").cloned()
+            })
+        });
+
+        match (request_id, line.strip_prefix('{')) {
+            (Some(id), Some(rest)) => write!(writer, "{{\"request_id\":{},{}", id, rest),
+            _ => writer.write_str(&line),
+        }
+    }
+}
+
+/// Installs the global Prometheus recorder and returns the handle used to render it.
+pub fn init_metrics() -> Result<PrometheusHandle> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full("http_request_duration_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )
+        .context("Invalid metrics histogram buckets")?
+        .install_recorder()
+        .context("Failed to install metrics recorder")
+}
+
+/// Request headers never sent to Sentry.
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "set-cookie", "x-api-key"];
+
+/// Sentry client options for `dsn`. Credentials are scrubbed from the request attached to
+/// each event, and the query string is dropped since it can carry single-use tokens.
+pub fn sentry_options(dsn: Option<Dsn>, environment: &str) -> sentry::ClientOptions {
+    sentry::ClientOptions {
+        dsn,
+        release: sentry::release_name!(),
+        environment: Some(environment.to_string().into()),
+        send_default_pii: false,
+        before_send: Some(Arc::new(|mut event| {
+            if let Some(request) = &mut event.request {
+                request
+                    .headers
+                    .retain(|name, _| !SENSITIVE_HEADERS.contains(&name.to_lowercase().as_str()));
+                request.cookies = None;
+                request.query_string = None;
+                if let Some(url) = &mut request.url {
+                    url.set_query(None);
+                }
+            }
+            Some(event)
+        })),
+        ..Default::default()
+    }
+}
+
+/// Starts reporting to Sentry when `sentry.dsn` is set. Events are flushed when the
+/// returned guard is dropped, so keep it alive until shutdown.
+pub fn init_sentry(settings: &Settings) -> Result<Option<ClientInitGuard>> {
+    let Some(dsn) = &settings.sentry.dsn else {
+        return Ok(None);
+    };
+    let dsn = dsn.parse().context("Invalid sentry.dsn")?;
+    let options = sentry_options(Some(dsn), &settings.application.environment);
+    Ok(Some(sentry::init(options)))
+}
+
+/// Reports an unexpected error to Sentry with the request id and path. The method, URL and
+/// user come from the request's scope. Does nothing when Sentry isn't configured.
+pub fn report_error(message: &str) {
+    sentry::with_scope(
+        |scope| {
+            if let Some(request_id) = current_request_id() {
+                scope.set_tag("request_id", request_id);
+            }
+            let path = current_error_context().path;
+            if !path.is_empty() {
+                scope.set_tag("path", path);
+            }
+        },
+        || sentry::capture_message(message, sentry::Level::Error),
+    );
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use super::auth::generate_token;
+
+/// Header machine-to-machine clients send their key in, instead of a bearer token.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Marks the string as one of this app's keys, so leaked keys are easy to grep for.
+const KEY_MARKER: &str = "rwa_";
+
+/// Characters of the key stored in the clear: the marker and the first 8 hex digits.
+const PREFIX_LEN: usize = KEY_MARKER.len() + 8;
+
+/// The key is the marker followed by a 256-bit token in hex.
+const KEY_LEN: usize = KEY_MARKER.len() + 64;
+
+/// `last_used_at` is written at most this often per key, so authenticating with a key
+/// doesn't cost a database write on every request.
+pub const LAST_USED_UPDATE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub const MAX_SCOPES: usize = 20;
+
+/// Generates a new key, like `rwa_` followed by 64 hex digits.
+pub fn generate_api_key() -> String {
+    format!("{}{}", KEY_MARKER, generate_token())
+}
+
+/// The indexed prefix of `key`, or `None` if it isn't shaped like a key at all.
+pub fn api_key_prefix(key: &str) -> Option<&str> {
+    let is_key = key.len() == KEY_LEN
+        && key.starts_with(KEY_MARKER)
+        && key[KEY_MARKER.len()..]
+            .bytes()
+            .all(|b| b.is_ascii_hexdigit());
+    is_key.then(|| &key[..PREFIX_LEN])
+}
+
+/// Scopes are short labels like `reports:read`: lowercase letters, digits and `:._-`.
+pub fn is_valid_scope(scope: &str) -> bool {
+    (1..=64).contains(&scope.len())
+        && scope
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b":._-".contains(&b))
+}
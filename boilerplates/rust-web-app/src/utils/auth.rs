@@ -1,47 +1,470 @@
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::sync::OnceLock;
+
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+    },
+    Algorithm, Argon2, Params, Version,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    errors::{Error as JwtError, ErrorKind},
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, PublicKeyUse, RSAKeyParameters,
+        RSAKeyType,
+    },
+    DecodingKey, EncodingKey, Header, Validation,
+};
+use pkcs1::der::Decode;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use spki::{der::DecodePem, ObjectIdentifier, SubjectPublicKeyInfoOwned};
+
+use uuid::Uuid;
 
 use super::error::{AppError, AppResult};
+use crate::config::{ApplicationSettings, JwtAlgorithm, PasswordAlgorithm};
+
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const PRIME256V1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,    // Subject (user id)
     pub exp: i64,       // Expiration time
     pub iat: i64,       // Issued at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>, // Issuer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>, // Audience
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>, // Session id
+}
+
+/// Key id written to token headers so verification can pick the matching key. It is
+/// derived from the secret or public key, so it stays stable while keys are rotated.
+fn key_id(key: &str) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+fn jwt_algorithm(settings: &ApplicationSettings) -> jsonwebtoken::Algorithm {
+    match settings.jwt_algorithm {
+        JwtAlgorithm::HS256 => jsonwebtoken::Algorithm::HS256,
+        JwtAlgorithm::RS256 => jsonwebtoken::Algorithm::RS256,
+        JwtAlgorithm::ES256 => jsonwebtoken::Algorithm::ES256,
+    }
+}
+
+/// PEM contents of the configured key pair; only set for `RS256`/`ES256`.
+fn key_pair(settings: &ApplicationSettings) -> Result<(&str, &str), String> {
+    match (&settings.jwt_private_key, &settings.jwt_public_key) {
+        (Some(private), Some(public)) => Ok((private, public)),
+        _ => Err("jwt_private_key_path and jwt_public_key_path must both be set".to_string()),
+    }
+}
+
+/// The configured signing and verification keys, parsed once at startup so tokens can be
+/// issued and checked without re-reading key material on every request.
+#[derive(Clone)]
+pub struct JwtKeys {
+    header: Header,
+    signing_key: EncodingKey,
+    /// Every key tokens may be verified with, paired with its key id.
+    verification_keys: Vec<(String, DecodingKey)>,
+    validation: Validation,
+    jwks: JwkSet,
+    expiration: i64,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl JwtKeys {
+    /// Parses the configured secrets or key pair, failing if the key material is invalid.
+    pub fn new(settings: &ApplicationSettings) -> Result<Self, String> {
+        let algorithm = jwt_algorithm(settings);
+        let (signing_key, kid) = signing_key(settings)?;
+        Ok(Self {
+            header: Header {
+                kid: Some(kid),
+                ..Header::new(algorithm)
+            },
+            signing_key,
+            verification_keys: verification_keys(settings)?,
+            validation: validation(settings),
+            jwks: public_jwks(settings)?,
+            expiration: settings.jwt_expiration,
+            issuer: settings.jwt_issuer.clone(),
+            audience: settings.jwt_audience.clone(),
+        })
+    }
+
+    /// Signs a token for `user_id` with the newest key, adding `iss`/`aud` when they are
+    /// configured.
+    pub fn create_jwt(&self, user_id: &str) -> AppResult<String> {
+        self.sign_jwt(user_id, None)
+    }
+
+    /// Like [`JwtKeys::create_jwt`], for a token that stops working once session
+    /// `session_id` is revoked.
+    pub fn create_session_jwt(&self, user_id: &str, session_id: Uuid) -> AppResult<String> {
+        self.sign_jwt(user_id, Some(session_id))
+    }
+
+    fn sign_jwt(&self, user_id: &str, session_id: Option<Uuid>) -> AppResult<String> {
+        let now = chrono::Utc::now().timestamp();
+        let claims = Claims {
+            sub: user_id.to_string(),
+            exp: now + self.expiration,
+            iat: now,
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            sid: session_id,
+        };
+
+        encode(&self.header, &claims, &self.signing_key)
+            .map_err(|e| AppError::InternalError(format!("Failed to create JWT: {}", e)))
+    }
+
+    /// Verifies a token's signature and expiry, and its `iss`/`aud` when configured;
+    /// a configured claim must be present in the token. The 401 message says which check
+    /// failed. Only the configured algorithm is accepted, and the key is picked by the
+    /// token's `kid`; tokens issued before key ids were added are tried against every key.
+    pub fn verify_jwt(&self, token: &str) -> AppResult<Claims> {
+        let kid = decode_header(token).map_err(token_error)?.kid;
+
+        let mut result = Err(AppError::Unauthorized(
+            "Token was signed with an unknown key".to_string(),
+        ));
+        for (_, key) in self
+            .verification_keys
+            .iter()
+            .filter(|(key_id, _)| kid.iter().all(|kid| kid == key_id))
+        {
+            match decode::<Claims>(token, key, &self.validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => {
+                    let retry = *e.kind() == ErrorKind::InvalidSignature;
+                    result = Err(token_error(e));
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// The public verification key as a JWK set, so other services can verify tokens
+    /// offline. Empty for `HS256`, whose secret must never be published.
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+/// The key new tokens are signed with and its key id.
+fn signing_key(settings: &ApplicationSettings) -> Result<(EncodingKey, String), String> {
+    if settings.jwt_algorithm == JwtAlgorithm::HS256 {
+        let secret = settings.jwt_keys()[0];
+        return Ok((EncodingKey::from_secret(secret.as_bytes()), key_id(secret)));
+    }
+
+    let (private, public) = key_pair(settings)?;
+    let key = match settings.jwt_algorithm {
+        JwtAlgorithm::RS256 => EncodingKey::from_rsa_pem(private.as_bytes()),
+        _ => EncodingKey::from_ec_pem(private.as_bytes()),
+    }
+    .map_err(|e| format!("invalid private key: {}", e))?;
+    Ok((key, key_id(public)))
+}
+
+/// Every key tokens may be verified with, paired with its key id.
+fn verification_keys(settings: &ApplicationSettings) -> Result<Vec<(String, DecodingKey)>, String> {
+    if settings.jwt_algorithm == JwtAlgorithm::HS256 {
+        let keys = settings
+            .jwt_keys()
+            .into_iter()
+            .map(|secret| (key_id(secret), DecodingKey::from_secret(secret.as_bytes())))
+            .collect();
+        return Ok(keys);
+    }
+
+    let (_, public) = key_pair(settings)?;
+    let key = match settings.jwt_algorithm {
+        JwtAlgorithm::RS256 => DecodingKey::from_rsa_pem(public.as_bytes()),
+        _ => DecodingKey::from_ec_pem(public.as_bytes()),
+    }
+    .map_err(|e| format!("invalid public key: {}", e))?;
+    Ok(vec![(key_id(public), key)])
+}
+
+/// The checks applied to incoming tokens: the configured algorithm, the leeway and the
+/// `iss`/`aud` claims when they are configured.
+fn validation(settings: &ApplicationSettings) -> Validation {
+    let mut validation = Validation::new(jwt_algorithm(settings));
+    validation.leeway = settings.jwt_leeway_secs;
+    let mut required = vec!["exp"];
+
+    if let Some(issuer) = &settings.jwt_issuer {
+        validation.set_issuer(&[issuer]);
+        required.push("iss");
+    }
+
+    match &settings.jwt_audience {
+        Some(audience) => {
+            validation.set_audience(&[audience]);
+            required.push("aud");
+        }
+        None => validation.validate_aud = false,
+    }
+
+    validation.set_required_spec_claims(&required);
+    validation
 }
 
-pub fn create_jwt(user_id: &str, secret: &str, expiration: i64) -> AppResult<String> {
-    let now = chrono::Utc::now().timestamp();
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp: now + expiration,
-        iat: now,
+/// Checks that the configured key pair parses and that its halves match, so bad key
+/// material fails at startup rather than on the first login.
+pub fn check_jwt_keys(settings: &ApplicationSettings) -> Result<(), String> {
+    let keys = JwtKeys::new(settings)?;
+    let token = keys.create_jwt("key-check").map_err(|e| e.to_string())?;
+    keys.verify_jwt(&token)
+        .map(|_| ())
+        .map_err(|_| "the private key does not match the public key".to_string())
+}
+
+/// Maps a rejected token to a 401 that says which check failed.
+fn token_error(e: JwtError) -> AppError {
+    let message = match e.kind() {
+        ErrorKind::ExpiredSignature => "Token has expired".to_string(),
+        ErrorKind::InvalidIssuer => "Token has the wrong issuer".to_string(),
+        ErrorKind::InvalidAudience => "Token has the wrong audience".to_string(),
+        ErrorKind::MissingRequiredClaim(claim) => {
+            format!("Token is missing the {} claim", claim)
+        }
+        _ => format!("Invalid token: {}", e),
     };
+    AppError::Unauthorized(message)
+}
+
+/// The public key of the configured key pair as a JWK set; empty for `HS256`.
+fn public_jwks(settings: &ApplicationSettings) -> Result<JwkSet, String> {
+    if settings.jwt_algorithm == JwtAlgorithm::HS256 {
+        return Ok(JwkSet { keys: Vec::new() });
+    }
+
+    let (_, pem) = key_pair(settings)?;
+    let info = SubjectPublicKeyInfoOwned::from_pem(pem)
+        .map_err(|e| format!("invalid public key: {}", e))?;
+    let key = info
+        .subject_public_key
+        .as_bytes()
+        .ok_or("invalid public key: unexpected padding")?;
+
+    let (algorithm, key_algorithm) = match settings.jwt_algorithm {
+        JwtAlgorithm::RS256 => {
+            if info.algorithm.oid != RSA_ENCRYPTION {
+                return Err("public key is not an RSA key".to_string());
+            }
+            let key = pkcs1::RsaPublicKey::from_der(key)
+                .map_err(|e| format!("invalid RSA public key: {}", e))?;
+            let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(key.modulus.as_bytes()),
+                e: URL_SAFE_NO_PAD.encode(key.public_exponent.as_bytes()),
+            });
+            (parameters, KeyAlgorithm::RS256)
+        }
+        _ => {
+            let curve = info
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|parameters| parameters.decode_as::<ObjectIdentifier>().ok());
+            if info.algorithm.oid != EC_PUBLIC_KEY || curve != Some(PRIME256V1) {
+                return Err("public key is not a P-256 key".to_string());
+            }
+            // Uncompressed point: 0x04 followed by the x and y coordinates
+            if key.len() != 65 || key[0] != 0x04 {
+                return Err("public key is not an uncompressed P-256 point".to_string());
+            }
+            let parameters = AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: EllipticCurve::P256,
+                x: URL_SAFE_NO_PAD.encode(&key[1..33]),
+                y: URL_SAFE_NO_PAD.encode(&key[33..]),
+            });
+            (parameters, KeyAlgorithm::ES256)
+        }
+    };
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(key_id(pem)),
+            ..Default::default()
+        },
+        algorithm,
+    };
+    Ok(JwkSet { keys: vec![jwk] })
+}
+
+/// A password hashing scheme with its cost settings.
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &str) -> AppResult<String>;
+
+    /// Whether `hash` was produced by this scheme with the same cost settings; stored
+    /// hashes that aren't are upgraded on the next successful login.
+    fn is_current(&self, hash: &str) -> bool;
+}
+
+pub struct Argon2idHasher {
+    params: Params,
+}
+
+impl PasswordHasher for Argon2idHasher {
+    fn hash(&self, password: &str) -> AppResult<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e)))
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return false;
+        };
+        let Ok(params) = Params::try_from(&parsed) else {
+            return false;
+        };
+        parsed.algorithm == Algorithm::Argon2id.ident()
+            && parsed.version == Some(Version::V0x13.into())
+            && params.m_cost() == self.params.m_cost()
+            && params.t_cost() == self.params.t_cost()
+            && params.p_cost() == self.params.p_cost()
+    }
+}
+
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn hash(&self, password: &str) -> AppResult<String> {
+        bcrypt::hash(password, self.cost)
+            .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e)))
+    }
+
+    fn is_current(&self, hash: &str) -> bool {
+        hash.parse::<bcrypt::HashParts>()
+            .is_ok_and(|parts| parts.get_cost() == self.cost)
+    }
+}
+
+/// The hasher for the configured `password_algorithm`.
+pub fn password_hasher(settings: &ApplicationSettings) -> AppResult<Box<dyn PasswordHasher>> {
+    match settings.password_algorithm {
+        PasswordAlgorithm::Argon2id => {
+            let params = Params::new(
+                settings.argon2_memory_kib,
+                settings.argon2_iterations,
+                settings.argon2_parallelism,
+                None,
+            )
+            .map_err(|e| AppError::InternalError(format!("Invalid Argon2 parameters: {}", e)))?;
+            Ok(Box::new(Argon2idHasher { params }))
+        }
+        PasswordAlgorithm::Bcrypt => Ok(Box::new(BcryptHasher {
+            cost: settings.bcrypt_cost,
+        })),
+    }
+}
+
+/// Hashes a password with the configured scheme. The hashing runs on the blocking thread
+/// pool so it doesn't stall the async workers.
+pub async fn hash_password(password: &str, settings: &ApplicationSettings) -> AppResult<String> {
+    let hasher = password_hasher(settings)?;
+    let password = password.to_owned();
+    blocking(move || hasher.hash(&password)).await
+}
+
+/// Whether a stored hash should be replaced with one from the configured scheme and costs.
+pub fn needs_rehash(hash: &str, settings: &ApplicationSettings) -> bool {
+    password_hasher(settings).is_ok_and(|hasher| !hasher.is_current(hash))
+}
+
+/// Verifies a password against an Argon2 or bcrypt hash, whichever scheme the stored hash
+/// uses, on the blocking thread pool.
+pub async fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
+    let (password, hash) = (password.to_owned(), hash.to_owned());
+    blocking(move || verify_password_sync(&password, &hash)).await
+}
+
+fn verify_password_sync(password: &str, hash: &str) -> AppResult<bool> {
+    if is_bcrypt_hash(hash) {
+        return bcrypt::verify(password, hash)
+            .map_err(|e| AppError::InternalError(format!("Failed to verify password: {}", e)));
+    }
+
+    // Argon2 reads the algorithm and cost parameters from the hash itself
+    let parsed = PasswordHash::new(hash)
+        .map_err(|e| AppError::InternalError(format!("Failed to verify password: {}", e)))?;
+
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) => Ok(true),
+        Err(argon2::password_hash::Error::Password) => Ok(false),
+        Err(e) => Err(AppError::InternalError(format!(
+            "Failed to verify password: {}",
+            e
+        ))),
+    }
+}
+
+/// Runs CPU-heavy work such as password hashing on the blocking thread pool.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> AppResult<T> + Send + 'static,
+) -> AppResult<T> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::InternalError(format!("Password hashing task failed: {}", e)))?
+}
+
+/// bcrypt hashes start with `$2`; everything else is a PHC string such as `$argon2id$`.
+fn is_bcrypt_hash(hash: &str) -> bool {
+    hash.starts_with("$2")
+}
+
+/// A hash of a random password with the configured cost, for verifying against when a
+/// login names no account so that unknown emails take as long to reject as wrong
+/// passwords. Computed once per process.
+pub async fn dummy_password_hash(settings: &ApplicationSettings) -> AppResult<&'static str> {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| AppError::InternalError(format!("Failed to create JWT: {}", e)))
+    if let Some(hash) = DUMMY_HASH.get() {
+        return Ok(hash);
+    }
+    let hash = hash_password(&generate_token(), settings).await?;
+    Ok(DUMMY_HASH.get_or_init(|| hash))
 }
 
-pub fn verify_jwt(token: &str, secret: &str) -> AppResult<Claims> {
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims)
-    .map_err(|e| AppError::Unauthorized(format!("Invalid token: {}", e)))
+/// Generates a random single-use token suitable for emailing to a user.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
 }
 
-pub fn hash_password(password: &str) -> AppResult<String> {
-    bcrypt::hash(password, bcrypt::DEFAULT_COST)
-        .map_err(|e| AppError::InternalError(format!("Failed to hash password: {}", e)))
+/// Hashes a single-use token for storage so a database leak doesn't expose usable tokens.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
 
-pub fn verify_password(password: &str, hash: &str) -> AppResult<bool> {
-    bcrypt::verify(password, hash)
-        .map_err(|e| AppError::InternalError(format!("Failed to verify password: {}", e)))
+/// Compares without returning early, so timing doesn't reveal how much of a guess was
+/// right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
 }
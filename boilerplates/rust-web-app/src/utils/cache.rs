@@ -0,0 +1,205 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    AsyncCommands,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use uuid::Uuid;
+
+/// Timeout for connecting to and talking to Redis, so an outage costs a request at most
+/// this long before it falls through to the database.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Entries kept by [`MemoryCache`] before expired ones are swept.
+const MEMORY_SWEEP_THRESHOLD: usize = 10_000;
+
+/// Key-value cache for values that can be rebuilt from the database. Failures are
+/// reported to the caller, but the typed helpers on `dyn Cache` log and swallow them
+/// so a cache outage never fails a request.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> anyhow::Result<()>;
+    async fn delete(&self, keys: &[String]) -> anyhow::Result<()>;
+
+    /// Whether every instance of the app sees the same entries, so an invalidation made
+    /// by one reaches the others.
+    fn is_shared(&self) -> bool {
+        false
+    }
+}
+
+impl dyn Cache {
+    /// Returns the cached value, or `None` on a miss, a cache error or a stale format.
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        match self.get(key).await {
+            Ok(Some(value)) => serde_json::from_str(&value).ok(),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!(
+                    key,
+                    "Cache read failed, falling back to the database: {:#}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    pub async fn set_json<T: Serialize>(&self, key: &str, value: &T, ttl: Duration) {
+        let value = match serde_json::to_string(value) {
+            Ok(value) => value,
+            Err(e) => return tracing::warn!(key, "Failed to serialize cache value: {}", e),
+        };
+
+        if let Err(e) = self.set(key, value, ttl).await {
+            tracing::warn!(key, "Cache write failed: {:#}", e);
+        }
+    }
+
+    /// Drops everything cached about a user; call after any change to their row.
+    pub async fn invalidate_user(&self, user_id: Uuid) {
+        let keys = [profile_key(user_id), auth_key(user_id)];
+        if let Err(e) = self.delete(&keys).await {
+            tracing::warn!(%user_id, "Cache invalidation failed: {:#}", e);
+        }
+    }
+
+    /// Drops the markers of revoked sessions, so their tokens are rejected right away
+    /// rather than once the marker expires.
+    pub async fn invalidate_sessions(&self, session_ids: &[Uuid]) {
+        if session_ids.is_empty() {
+            return;
+        }
+        let keys: Vec<String> = session_ids.iter().copied().map(session_seen_key).collect();
+        if let Err(e) = self.delete(&keys).await {
+            tracing::warn!("Session cache invalidation failed: {:#}", e);
+        }
+    }
+}
+
+/// Key for the owner's view of a user's profile.
+pub fn profile_key(user_id: Uuid) -> String {
+    format!("user:{}:profile", user_id)
+}
+
+/// Key for the suspension and revocation state checked on every authenticated request.
+pub fn auth_key(user_id: Uuid) -> String {
+    format!("user:{}:auth", user_id)
+}
+
+/// Marker that an API key's `last_used_at` was written recently and can be skipped.
+pub fn api_key_used_key(key_id: Uuid) -> String {
+    format!("api_key:{}:used", key_id)
+}
+
+/// Marker that a session was found active and its `last_seen_at` written recently.
+pub fn session_seen_key(session_id: Uuid) -> String {
+    format!("session:{}:seen", session_id)
+}
+
+/// Cache backed by Redis. Connects lazily and reconnects after failures, so the app
+/// starts and serves requests while Redis is down.
+pub struct RedisCache {
+    client: redis::Client,
+    connection: tokio::sync::Mutex<Option<ConnectionManager>>,
+}
+
+impl RedisCache {
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connection: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn connection(&self) -> anyhow::Result<ConnectionManager> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+
+        let config = ConnectionManagerConfig::new()
+            .set_number_of_retries(0)
+            .set_connection_timeout(REDIS_TIMEOUT)
+            .set_response_timeout(REDIS_TIMEOUT);
+        let manager = ConnectionManager::new_with_config(self.client.clone(), config).await?;
+        *connection = Some(manager.clone());
+
+        Ok(manager)
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.connection().await?.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> anyhow::Result<()> {
+        let _: () = self
+            .connection()
+            .await?
+            .set_ex(key, value, ttl.as_secs().max(1))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, keys: &[String]) -> anyhow::Result<()> {
+        let _: () = self.connection().await?.del(keys).await?;
+        Ok(())
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
+
+/// Per-process cache used when Redis isn't configured, and in tests. Each instance
+/// only sees its own invalidations, so prefer Redis when running several replicas; auth
+/// state is never kept here.
+#[derive(Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl MemoryCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > Instant::now())
+            .map(|(value, _)| value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: String, ttl: Duration) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        if entries.len() >= MEMORY_SWEEP_THRESHOLD {
+            entries.retain(|_, (_, expires_at)| *expires_at > now);
+        }
+        entries.insert(key.to_string(), (value, now + ttl));
+        Ok(())
+    }
+
+    async fn delete(&self, keys: &[String]) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        for key in keys {
+            entries.remove(key);
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,20 @@
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, Utc};
 use serde::Serialize;
-use std::fmt;
+use serde_json::json;
+use std::{collections::HashMap, fmt};
+use utoipa::ToSchema;
+
+use crate::{
+    config::ErrorFormat,
+    middleware::{error_context::current_error_context, request_id::current_request_id},
+    telemetry::report_error,
+    utils::response::{ApiError, ApiResponse},
+};
 
 pub type AppResult<T> = Result<T, AppError>;
 
@@ -14,14 +24,124 @@ pub enum AppError {
     NotFound(String),
     BadRequest(String),
     Unauthorized(String),
+    Forbidden(String),
+    EmailNotVerified,
+    AccountSuspended(DateTime<Utc>),
+    /// `field` is set when the request's value for it is already in use, whether a
+    /// pre-check or a unique constraint caught it; see [`AppError::already_in_use`].
+    Conflict {
+        message: String,
+        field: Option<&'static str>,
+    },
+    MethodNotAllowed(Vec<String>),
+    Timeout,
+    PayloadTooLarge,
+    TooManyRequests(u64),
+    ServiceUnavailable(String),
     InternalError(String),
     ValidationError(String),
+    ValidationErrors(validator::ValidationErrors),
 }
 
+/// Body of every error response when `application.error_format = "problem"`, following
+/// RFC 7807. Extra context from `details` is added as extension members.
 #[derive(Serialize)]
-struct ErrorResponse {
+struct ProblemDetails {
+    /// Always `about:blank`: the status code and `code` identify the problem.
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: String,
+    instance: String,
+    /// Machine-readable error code, the `error` of the simple format.
+    code: String,
+    /// Per-field validation messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<HashMap<String, Vec<String>>>,
+    #[serde(flatten)]
+    details: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl AppError {
+    /// A 409 without a field, e.g. for a state the request can't be applied to.
+    pub fn conflict(message: impl Into<String>) -> Self {
+        AppError::Conflict {
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    /// The 409 for a `field` whose value another record already has.
+    pub fn already_in_use(field: &'static str) -> Self {
+        let mut chars = field.chars();
+        let capitalized: String = chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default();
+        AppError::Conflict {
+            message: format!("{} is already in use", capitalized),
+            field: Some(field),
+        }
+    }
+}
+
+/// Unique constraints and the request field each one guards, for 409 responses.
+const UNIQUE_CONSTRAINT_FIELDS: &[(&str, &str)] = &[
+    ("users_email_lower_active_key", "email"),
+    ("users_handle_active_key", "handle"),
+];
+
+/// Body of every error response.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Machine-readable error code, e.g. `VALIDATION_ERROR`.
     error: String,
     message: String,
+    /// Per-field validation messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<HashMap<String, Vec<String>>>,
+    /// Extra context for specific errors, e.g. `suspended_until`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    details: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+/// Formats an error with its chain of sources, e.g. `outer: inner`.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut chain = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        chain.push_str(": ");
+        chain.push_str(&e.to_string());
+        source = e.source();
+    }
+    chain
+}
+
+/// Flattens `validator` errors into a map of field name to messages,
+/// falling back to the validation code when no message was provided.
+fn field_errors(errors: &validator::ValidationErrors) -> HashMap<String, Vec<String>> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages = errors
+                .iter()
+                .map(|e| {
+                    e.message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| e.code.to_string())
+                })
+                .collect();
+            (field.to_string(), messages)
+        })
+        .collect()
 }
 
 impl fmt::Display for AppError {
@@ -31,8 +151,20 @@ impl fmt::Display for AppError {
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
             AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::EmailNotVerified => write!(f, "Forbidden: email address not verified"),
+            AppError::AccountSuspended(until) => write!(f, "Forbidden: suspended until {}", until),
+            AppError::Conflict { message, .. } => write!(f, "Conflict: {}", message),
+            AppError::MethodNotAllowed(allowed) => {
+                write!(f, "Method not allowed: expected {}", allowed.join(", "))
+            }
+            AppError::Timeout => write!(f, "Request timed out"),
+            AppError::PayloadTooLarge => write!(f, "Payload too large"),
+            AppError::TooManyRequests(secs) => write!(f, "Too many requests: retry in {}s", secs),
+            AppError::ServiceUnavailable(msg) => write!(f, "Service unavailable: {}", msg),
             AppError::InternalError(msg) => write!(f, "Internal error: {}", msg),
             AppError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
+            AppError::ValidationErrors(e) => write!(f, "Validation error: {}", e),
         }
     }
 }
@@ -41,38 +173,210 @@ impl std::error::Error for AppError {}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let fields = match &self {
+            AppError::ValidationErrors(errors) => Some(field_errors(errors)),
+            AppError::Conflict {
+                field: Some(field), ..
+            } => Some(HashMap::from([(
+                field.to_string(),
+                vec!["is already in use".to_string()],
+            )])),
+            _ => None,
+        };
+
+        let details = match &self {
+            AppError::AccountSuspended(until) => Some(json!({ "suspended_until": until })),
+            AppError::MethodNotAllowed(allowed) => Some(json!({ "allowed_methods": allowed })),
+            _ => None,
+        };
+
+        let allow = match &self {
+            AppError::MethodNotAllowed(allowed) => HeaderValue::from_str(&allowed.join(", ")).ok(),
+            _ => None,
+        };
+
+        let retry_after = match &self {
+            AppError::TooManyRequests(secs) => Some(*secs),
+            _ => None,
+        };
+
+        // Outside production, 5xx bodies keep the underlying error to ease debugging
+        let context = current_error_context();
+        let redacted = |detail: String| {
+            if context.expose_details {
+                detail
+            } else {
+                "Internal server error".to_string()
+            }
+        };
+
         let (status, error_type, message) = match self {
-            AppError::DatabaseError(e) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "DATABASE_ERROR",
-                e.to_string(),
-            ),
+            AppError::DatabaseError(e) => {
+                // Driver messages can expose schema details, so production only logs them
+                let detail = error_chain(&e);
+                tracing::error!("Database error: {}", detail);
+                report_error(&detail);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "DATABASE_ERROR",
+                    redacted(detail),
+                )
+            }
             AppError::NotFound(msg) => (StatusCode::NOT_FOUND, "NOT_FOUND", msg),
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, "BAD_REQUEST", msg),
             AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "UNAUTHORIZED", msg),
-            AppError::InternalError(msg) => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "INTERNAL_ERROR",
-                msg,
+            AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, "FORBIDDEN", msg),
+            AppError::EmailNotVerified => (
+                StatusCode::FORBIDDEN,
+                "EMAIL_NOT_VERIFIED",
+                "Email address has not been verified".to_string(),
+            ),
+            AppError::AccountSuspended(until) => (
+                StatusCode::FORBIDDEN,
+                "ACCOUNT_SUSPENDED",
+                format!("Account is suspended until {}", until.to_rfc3339()),
             ),
+            AppError::Conflict { message, .. } => (StatusCode::CONFLICT, "CONFLICT", message),
+            AppError::MethodNotAllowed(_) => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                "METHOD_NOT_ALLOWED",
+                "Method not allowed for this route".to_string(),
+            ),
+            AppError::Timeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "GATEWAY_TIMEOUT",
+                "Request took too long to process".to_string(),
+            ),
+            AppError::PayloadTooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "PAYLOAD_TOO_LARGE",
+                "Request body is too large".to_string(),
+            ),
+            AppError::TooManyRequests(secs) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "TOO_MANY_REQUESTS",
+                format!("Too many attempts, try again in {} seconds", secs),
+            ),
+            AppError::ServiceUnavailable(msg) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "SERVICE_UNAVAILABLE", msg)
+            }
+            AppError::InternalError(msg) => {
+                tracing::error!("Internal error: {}", msg);
+                report_error(&msg);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "INTERNAL_ERROR",
+                    redacted(msg),
+                )
+            }
             AppError::ValidationError(msg) => (
                 StatusCode::UNPROCESSABLE_ENTITY,
                 "VALIDATION_ERROR",
                 msg,
             ),
+            AppError::ValidationErrors(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VALIDATION_ERROR",
+                "Request validation failed".to_string(),
+            ),
         };
 
-        let body = Json(ErrorResponse {
-            error: error_type.to_string(),
-            message,
-        });
+        let mut response = match context.format {
+            ErrorFormat::Simple => {
+                let body = Json(ErrorResponse {
+                    error: error_type.to_string(),
+                    message,
+                    fields,
+                    details,
+                    request_id: current_request_id(),
+                });
+                (status, body).into_response()
+            }
+            ErrorFormat::Problem => {
+                let details = match details {
+                    Some(serde_json::Value::Object(details)) => details,
+                    _ => serde_json::Map::new(),
+                };
+                let body = Json(ProblemDetails {
+                    problem_type: "about:blank",
+                    title: status.canonical_reason().unwrap_or("Error"),
+                    status: status.as_u16(),
+                    detail: message,
+                    instance: context.path,
+                    code: error_type.to_string(),
+                    errors: fields,
+                    details,
+                    request_id: current_request_id(),
+                });
+                let content_type = [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/problem+json"),
+                )];
+                (status, content_type, body).into_response()
+            }
+            ErrorFormat::Envelope => {
+                let body = ApiResponse::<()>::from_error(ApiError {
+                    code: error_type.to_string(),
+                    message,
+                    fields,
+                    details,
+                    request_id: current_request_id(),
+                });
+                (status, Json(body)).into_response()
+            }
+        };
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        if let Some(allow) = allow {
+            response.headers_mut().insert(header::ALLOW, allow);
+        }
 
-        (status, body).into_response()
+        response
+    }
+}
+
+impl From<validator::ValidationErrors> for AppError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        AppError::ValidationErrors(errors)
     }
 }
 
 impl From<sqlx::Error> for AppError {
     fn from(err: sqlx::Error) -> Self {
-        AppError::DatabaseError(err)
+        match &err {
+            sqlx::Error::RowNotFound => {
+                return AppError::NotFound("Resource not found".to_string());
+            }
+            sqlx::Error::PoolTimedOut => {
+                return AppError::ServiceUnavailable(
+                    "Database is busy, try again shortly".to_string(),
+                );
+            }
+            _ => {}
+        }
+
+        let Some(db) = err.as_database_error() else {
+            return AppError::DatabaseError(err);
+        };
+        match db.code().as_deref() {
+            // Unique violations usually mean a pre-check lost a race with a concurrent insert
+            Some("23505") => {
+                let field = db.constraint().and_then(|constraint| {
+                    UNIQUE_CONSTRAINT_FIELDS
+                        .iter()
+                        .find(|(name, _)| *name == constraint)
+                        .map(|(_, field)| *field)
+                });
+                match field {
+                    Some(field) => AppError::already_in_use(field),
+                    None => AppError::conflict("Resource already exists"),
+                }
+            }
+            Some("23503") => AppError::BadRequest("Referenced resource does not exist".to_string()),
+            _ => AppError::DatabaseError(err),
+        }
     }
 }
@@ -0,0 +1,98 @@
+use super::error::{AppError, AppResult};
+
+pub const MIN_HANDLE_LEN: usize = 3;
+pub const MAX_HANDLE_LEN: usize = 30;
+
+/// Handles nobody may claim, on top of `handles.reserved` from config. Mostly names that
+/// could pass for staff or collide with routes.
+const DEFAULT_RESERVED_HANDLES: &[&str] = &[
+    "admin",
+    "administrator",
+    "api",
+    "auth",
+    "help",
+    "login",
+    "logout",
+    "me",
+    "metrics",
+    "moderator",
+    "null",
+    "official",
+    "register",
+    "root",
+    "security",
+    "settings",
+    "staff",
+    "support",
+    "system",
+    "undefined",
+    "well-known",
+    "www",
+];
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '_' | '.' | '-')
+}
+
+/// Normalizes a requested handle: strips one leading `@`, lowercases it and enforces the
+/// allowed charset (`a-z`, `0-9`, `_`, `.`, `-`) and length. Handles must start and end
+/// with a letter or digit and may not contain consecutive separators.
+pub fn normalize_handle(input: &str) -> AppResult<String> {
+    let trimmed = input.trim();
+    let handle = trimmed
+        .strip_prefix('@')
+        .unwrap_or(trimmed)
+        .to_ascii_lowercase();
+
+    if !handle
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || is_separator(c))
+    {
+        return Err(AppError::ValidationError(
+            "Handle may only contain letters, digits, '_', '.' and '-'".to_string(),
+        ));
+    }
+
+    if !(MIN_HANDLE_LEN..=MAX_HANDLE_LEN).contains(&handle.len()) {
+        return Err(AppError::ValidationError(format!(
+            "Handle must be between {} and {} characters",
+            MIN_HANDLE_LEN, MAX_HANDLE_LEN
+        )));
+    }
+
+    if handle.starts_with(is_separator) || handle.ends_with(is_separator) {
+        return Err(AppError::ValidationError(
+            "Handle must start and end with a letter or digit".to_string(),
+        ));
+    }
+
+    if handle
+        .as_bytes()
+        .windows(2)
+        .any(|pair| is_separator(pair[0] as char) && is_separator(pair[1] as char))
+    {
+        return Err(AppError::ValidationError(
+            "Handle may not contain consecutive separators".to_string(),
+        ));
+    }
+
+    Ok(handle)
+}
+
+/// Whether a normalized handle is reserved. Separators are ignored when comparing, so
+/// `ad.min` and `sup_port` are caught as well.
+pub fn is_reserved_handle(handle: &str, extra: &[String]) -> bool {
+    let skeleton = |s: &str| -> String {
+        s.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    };
+    let handle = skeleton(handle);
+
+    DEFAULT_RESERVED_HANDLES
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .any(|reserved| skeleton(reserved) == handle)
+}
@@ -0,0 +1,22 @@
+use std::{sync::OnceLock, time::Duration};
+
+use reqwest::Client;
+
+/// GitHub rejects API requests without a User-Agent.
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Connecting to any host taking longer than this fails the request.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outbound HTTP client, shared so connections are reused. It has no overall
+/// timeout; each caller sets its own with `RequestBuilder::timeout`.
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .user_agent(USER_AGENT)
+            .build()
+            .expect("failed to build the HTTP client")
+    })
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::error::{AppError, AppResult};
+use crate::config::{SmtpSettings, SmtpTls};
+
+/// Timeout for each SMTP command, so a stalled relay doesn't pile up send tasks.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outbound email delivery used by account flows such as password reset.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> AppResult<()>;
+}
+
+/// Mailer that only logs messages instead of delivering them.
+pub struct NoopMailer;
+
+#[async_trait]
+impl Mailer for NoopMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> AppResult<()> {
+        tracing::info!(to, subject, body, "Email not sent (no-op mailer)");
+        Ok(())
+    }
+}
+
+/// Mailer that delivers plain-text messages through an SMTP relay.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(host: &str, settings: &SmtpSettings) -> anyhow::Result<Self> {
+        let builder = match settings.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let mut builder = builder.port(settings.port).timeout(Some(SMTP_TIMEOUT));
+
+        if let Some(username) = &settings.username {
+            let password = settings.password.clone().unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username.clone(), password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: settings
+                .from
+                .parse()
+                .with_context(|| format!("Invalid smtp.from address {:?}", settings.from))?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> AppResult<()> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| AppError::InternalError(format!("Invalid recipient {:?}: {}", to, e)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .body(body.to_string())
+            .map_err(|e| AppError::InternalError(format!("Failed to build email: {}", e)))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| AppError::InternalError(format!("Failed to send email: {}", e)))?;
+        Ok(())
+    }
+}
@@ -1,6 +1,17 @@
+pub mod api_key;
+pub mod oauth;
+pub mod password_policy;
 pub mod error;
 pub mod auth;
+pub mod cache;
+pub mod handle;
+pub mod http;
+pub mod mailer;
+pub mod pagination;
+pub mod rate_limit;
 pub mod response;
+pub mod transaction;
+pub mod two_factor;
 
 pub use error::{AppError, AppResult};
 pub use response::ApiResponse;
@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::{header, RequestBuilder, Response, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    error::{AppError, AppResult},
+    http::client,
+};
+use crate::config::{OAuthProviderSettings, OAuthSettings};
+
+/// Calls to a provider taking longer than this fail the login with 503.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OAuthProvider {
+    Google,
+    Github,
+}
+
+impl OAuthProvider {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "google" => Some(Self::Google),
+            "github" => Some(Self::Github),
+            _ => None,
+        }
+    }
+
+    /// The name used in URLs and stored with linked accounts.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Google => "google",
+            Self::Github => "github",
+        }
+    }
+
+    fn settings(self, settings: &OAuthSettings) -> Option<&OAuthProviderSettings> {
+        match self {
+            Self::Google => settings.google.as_ref(),
+            Self::Github => settings.github.as_ref(),
+        }
+    }
+
+    fn default_authorize_url(self) -> &'static str {
+        match self {
+            Self::Google => "https://accounts.google.com/o/oauth2/v2/auth",
+            Self::Github => "https://github.com/login/oauth/authorize",
+        }
+    }
+
+    fn default_token_url(self) -> &'static str {
+        match self {
+            Self::Google => "https://oauth2.googleapis.com/token",
+            Self::Github => "https://github.com/login/oauth/access_token",
+        }
+    }
+
+    fn default_api_url(self) -> &'static str {
+        match self {
+            Self::Google => "https://openidconnect.googleapis.com/v1",
+            Self::Github => "https://api.github.com",
+        }
+    }
+
+    /// Just enough to read the account's id, name and verified email.
+    fn scope(self) -> &'static str {
+        match self {
+            Self::Google => "openid email profile",
+            Self::Github => "read:user user:email",
+        }
+    }
+}
+
+/// What a login needs from the provider's account.
+#[derive(Debug)]
+pub struct OAuthProfile {
+    pub provider_user_id: String,
+    /// Only set when the provider has verified the address.
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+/// A configured provider.
+pub struct OAuthClient<'a> {
+    pub provider: OAuthProvider,
+    settings: &'a OAuthProviderSettings,
+}
+
+impl<'a> OAuthClient<'a> {
+    /// `None` for unknown providers and ones without settings.
+    pub fn new(name: &str, settings: &'a OAuthSettings) -> Option<Self> {
+        let provider = OAuthProvider::from_name(name)?;
+        let settings = provider.settings(settings)?;
+        Some(Self { provider, settings })
+    }
+
+    /// Where to send the user to sign in with the provider.
+    pub fn authorize_url(&self, state: &str, code_verifier: &str) -> AppResult<String> {
+        let base = self.endpoint(
+            &self.settings.authorize_url,
+            self.provider.default_authorize_url(),
+        );
+        let url = Url::parse_with_params(
+            base,
+            [
+                ("response_type", "code"),
+                ("client_id", self.settings.client_id.as_str()),
+                ("redirect_uri", self.settings.redirect_url.as_str()),
+                ("scope", self.provider.scope()),
+                ("state", state),
+                ("code_challenge", pkce_challenge(code_verifier).as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| AppError::InternalError(format!("Invalid OAuth authorize URL: {}", e)))?;
+        Ok(url.into())
+    }
+
+    /// Trades the code from the callback for an access token.
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str) -> AppResult<String> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: Option<String>,
+        }
+
+        let token_url = self.endpoint(&self.settings.token_url, self.provider.default_token_url());
+        let request = client()
+            .post(token_url)
+            .header(header::ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.settings.redirect_url.as_str()),
+                ("client_id", self.settings.client_id.as_str()),
+                ("client_secret", self.settings.client_secret.as_str()),
+                ("code_verifier", code_verifier),
+            ]);
+        let response = send(request).await?;
+
+        // GitHub reports a bad code with a 200 and an `error` field instead of a token
+        let rejected = || {
+            AppError::BadRequest("The OAuth provider rejected the authorization code".to_string())
+        };
+        if response.status().is_client_error() {
+            return Err(rejected());
+        }
+        let token: TokenResponse = read_json(response).await?;
+        token.access_token.ok_or_else(rejected)
+    }
+
+    /// Fetches the signed-in account with the token from [`Self::exchange_code`].
+    pub async fn fetch_profile(&self, access_token: &str) -> AppResult<OAuthProfile> {
+        match self.provider {
+            OAuthProvider::Google => {
+                #[derive(Deserialize)]
+                struct UserInfo {
+                    sub: String,
+                    email: Option<String>,
+                    #[serde(default)]
+                    email_verified: bool,
+                    name: Option<String>,
+                }
+
+                let info: UserInfo = self.get_json(access_token, "/userinfo").await?;
+                Ok(OAuthProfile {
+                    provider_user_id: info.sub,
+                    email: info.email.filter(|_| info.email_verified),
+                    name: info.name,
+                })
+            }
+            OAuthProvider::Github => {
+                #[derive(Deserialize)]
+                struct GithubUser {
+                    id: u64,
+                    login: String,
+                    name: Option<String>,
+                }
+
+                #[derive(Deserialize)]
+                struct GithubEmail {
+                    email: String,
+                    primary: bool,
+                    verified: bool,
+                }
+
+                // The profile's public email may be unverified or hidden, the primary
+                // address from the emails endpoint is neither
+                let user: GithubUser = self.get_json(access_token, "/user").await?;
+                let emails: Vec<GithubEmail> = self.get_json(access_token, "/user/emails").await?;
+                Ok(OAuthProfile {
+                    provider_user_id: user.id.to_string(),
+                    email: emails
+                        .into_iter()
+                        .find(|email| email.primary && email.verified)
+                        .map(|email| email.email),
+                    name: user.name.or(Some(user.login)),
+                })
+            }
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, access_token: &str, path: &str) -> AppResult<T> {
+        let api_url = self.endpoint(&self.settings.api_url, self.provider.default_api_url());
+        let request = client()
+            .get(format!("{}{}", api_url.trim_end_matches('/'), path))
+            .header(header::ACCEPT, "application/json")
+            .bearer_auth(access_token);
+        let response = send(request).await?;
+        if !response.status().is_success() {
+            return Err(unavailable(format!(
+                "{} answered {}",
+                path,
+                response.status()
+            )));
+        }
+        read_json(response).await
+    }
+
+    fn endpoint<'s>(&'s self, configured: &'s Option<String>, default: &'static str) -> &'s str {
+        configured.as_deref().unwrap_or(default)
+    }
+}
+
+/// The S256 PKCE challenge for `verifier`.
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn unavailable(reason: impl std::fmt::Display) -> AppError {
+    tracing::warn!("OAuth provider request failed: {}", reason);
+    AppError::ServiceUnavailable("The OAuth provider could not be reached".to_string())
+}
+
+async fn send(request: RequestBuilder) -> AppResult<Response> {
+    let response = request
+        .timeout(PROVIDER_TIMEOUT)
+        .send()
+        .await
+        .map_err(unavailable)?;
+    if response.status().is_server_error() {
+        return Err(unavailable(format!("answered {}", response.status())));
+    }
+    Ok(response)
+}
+
+async fn read_json<T: DeserializeOwned>(response: Response) -> AppResult<T> {
+    response.json().await.map_err(unavailable)
+}
@@ -0,0 +1,147 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::error::AppError;
+
+pub const DEFAULT_PER_PAGE: u32 = 20;
+pub const MAX_PER_PAGE: u32 = 100;
+
+#[derive(Debug, Deserialize)]
+struct PaginationParams {
+    page: Option<u32>,
+    per_page: Option<u32>,
+}
+
+/// Page-based pagination parsed from `?page=&per_page=`, with `per_page` clamped to
+/// [`MAX_PER_PAGE`].
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+}
+
+impl Pagination {
+    pub fn limit(&self) -> i64 {
+        i64::from(self.per_page)
+    }
+
+    pub fn offset(&self) -> i64 {
+        i64::from(self.page - 1) * i64::from(self.per_page)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Pagination
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PaginationParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                AppError::BadRequest(format!("Invalid pagination parameters: {}", e.body_text()))
+            })?;
+
+        Ok(Pagination {
+            page: params.page.unwrap_or(1).max(1),
+            per_page: params
+                .per_page
+                .unwrap_or(DEFAULT_PER_PAGE)
+                .clamp(1, MAX_PER_PAGE),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CursorParams {
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+/// Position in a keyset-paginated listing ordered by `(created_at, id)`. The `id`
+/// breaks ties between rows created in the same microsecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn new(created_at: DateTime<Utc>, id: Uuid) -> Self {
+        Self { created_at, id }
+    }
+
+    /// Encodes the cursor as an opaque, URL-safe string.
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    /// Decodes a cursor produced by [`Cursor::encode`].
+    pub fn decode(encoded: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+        let raw = URL_SAFE_NO_PAD.decode(encoded).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (created_at, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Cursor-based pagination parsed from `?cursor=&limit=`, with `limit` clamped to
+/// [`MAX_PER_PAGE`]. A missing cursor starts from the first row.
+#[derive(Debug, Clone, Copy)]
+pub struct CursorPagination {
+    pub cursor: Option<Cursor>,
+    pub limit: u32,
+}
+
+impl CursorPagination {
+    /// Rows to fetch: one more than the page size, so the extra row signals `has_more`.
+    pub fn fetch_limit(&self) -> i64 {
+        i64::from(self.limit) + 1
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CursorPagination
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<CursorParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| {
+                AppError::BadRequest(format!("Invalid pagination parameters: {}", e.body_text()))
+            })?;
+
+        Ok(CursorPagination {
+            cursor: params.cursor.as_deref().map(Cursor::decode).transpose()?,
+            limit: params
+                .limit
+                .unwrap_or(DEFAULT_PER_PAGE)
+                .clamp(1, MAX_PER_PAGE),
+        })
+    }
+}
@@ -0,0 +1,117 @@
+use std::borrow::Cow;
+
+use validator::ValidationError;
+
+use crate::config::PasswordPolicySettings;
+
+/// The most common passwords from public breach lists, one per line.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Shorter email local parts, like `jo`, would reject too many unrelated passwords.
+const MIN_EMAIL_MATCH_CHARS: usize = 3;
+
+fn rule_error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Owned(message));
+    error
+}
+
+fn is_common(password: &str) -> bool {
+    let password = password.to_lowercase();
+    COMMON_PASSWORDS.lines().any(|common| common == password)
+}
+
+/// What a new password is checked against: the configured policy and the email of the
+/// account it is for. Passed to `validate_with_args` on requests carrying a new password.
+pub struct PasswordContext<'a> {
+    pub policy: &'a PasswordPolicySettings,
+    pub email: &'a str,
+}
+
+/// Checks a new password against the policy in `context`, for
+/// `#[validate(custom(function = "validate_password_strength", use_context))]` on request
+/// fields. The error's code and message name the first rule that failed.
+pub fn validate_password_strength(
+    password: &str,
+    context: &PasswordContext,
+) -> Result<(), ValidationError> {
+    let PasswordContext { policy, email } = *context;
+
+    if password.chars().count() < policy.min_length {
+        return Err(rule_error(
+            "password_too_short",
+            format!("Password must be at least {} characters", policy.min_length),
+        ));
+    }
+
+    let classes = [
+        (
+            policy.require_letter,
+            char::is_alphabetic as fn(char) -> bool,
+            "password_missing_letter",
+            "a letter",
+        ),
+        (
+            policy.require_digit,
+            |c: char| c.is_ascii_digit(),
+            "password_missing_digit",
+            "a digit",
+        ),
+        (
+            policy.require_lowercase,
+            char::is_lowercase,
+            "password_missing_lowercase",
+            "a lowercase letter",
+        ),
+        (
+            policy.require_uppercase,
+            char::is_uppercase,
+            "password_missing_uppercase",
+            "an uppercase letter",
+        ),
+        (
+            policy.require_symbol,
+            |c: char| !c.is_alphanumeric(),
+            "password_missing_symbol",
+            "a symbol",
+        ),
+    ];
+    for (required, matches, code, class) in classes {
+        if required && !password.chars().any(matches) {
+            return Err(rule_error(code, format!("Password must contain {}", class)));
+        }
+    }
+
+    let local_part = email.split('@').next().unwrap_or_default().to_lowercase();
+    if policy.reject_email
+        && local_part.chars().count() >= MIN_EMAIL_MATCH_CHARS
+        && password.to_lowercase().contains(&local_part)
+    {
+        return Err(rule_error(
+            "password_contains_email",
+            "Password must not contain your email address".to_string(),
+        ));
+    }
+
+    if policy.reject_common && is_common(password) {
+        return Err(rule_error(
+            "password_too_common",
+            "Password is too common".to_string(),
+        ));
+    }
+
+    if let Some(min_score) = policy.min_score {
+        let score = u8::from(zxcvbn::zxcvbn(password, &[email, &local_part]).score());
+        if score < min_score {
+            return Err(rule_error(
+                "password_too_weak",
+                format!(
+                    "Password is too easy to guess (strength {} of 4, at least {} required)",
+                    score, min_score
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
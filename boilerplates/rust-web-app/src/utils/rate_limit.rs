@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::config::RateLimitSettings;
+
+/// Entries are pruned once the map grows past this many keys.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug)]
+struct AttemptState {
+    failures: u32,
+    window_start: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// In-memory tracker of failed login attempts. After `max_attempts` failures for an
+/// email from one client IP, or `account_max_attempts` for the email from any IP, within
+/// `window`, logins for it are locked out for `cooldown`.
+pub struct LoginRateLimiter {
+    attempts: Mutex<HashMap<String, AttemptState>>,
+    max_attempts: u32,
+    account_max_attempts: u32,
+    window: Duration,
+    cooldown: Duration,
+}
+
+impl LoginRateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            attempts: Mutex::new(HashMap::new()),
+            max_attempts: settings.login_max_attempts,
+            account_max_attempts: settings.account_max_attempts,
+            window: Duration::from_secs(settings.login_window_secs),
+            cooldown: Duration::from_secs(settings.login_cooldown_secs),
+        }
+    }
+
+    /// The per-client and per-account keys for a login, with their thresholds.
+    fn keys(&self, email: &str, ip: Option<IpAddr>) -> [(String, u32); 2] {
+        let email = email.to_lowercase();
+        let client = ip.map(|ip| ip.to_string()).unwrap_or_default();
+        [
+            (format!("{}|{}", email, client), self.max_attempts),
+            (format!("account|{}", email), self.account_max_attempts),
+        ]
+    }
+
+    /// Returns the remaining cooldown if logins for `email` from `ip` are locked out.
+    /// `ip` should be the resolved [`ClientIp`](crate::middleware::ClientIp), never a
+    /// header the client can set.
+    pub fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), Duration> {
+        let attempts = self.attempts.lock().unwrap();
+        let now = Instant::now();
+
+        let remaining = self
+            .keys(email, ip)
+            .iter()
+            .filter_map(|(key, _)| attempts.get(key).and_then(|state| state.locked_until))
+            .filter(|until| *until > now)
+            .max();
+        match remaining {
+            Some(until) => Err(until - now),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a failed attempt, locking logins out once a threshold is reached.
+    pub fn record_failure(&self, email: &str, ip: Option<IpAddr>) {
+        let mut attempts = self.attempts.lock().unwrap();
+        let now = Instant::now();
+
+        if attempts.len() >= PRUNE_THRESHOLD {
+            let (window, cooldown) = (self.window, self.cooldown);
+            attempts.retain(|_, state| {
+                now.duration_since(state.window_start) < window.max(cooldown)
+                    || state.locked_until.is_some_and(|until| until > now)
+            });
+        }
+
+        for (key, max_attempts) in self.keys(email, ip) {
+            let state = attempts.entry(key).or_insert(AttemptState {
+                failures: 0,
+                window_start: now,
+                locked_until: None,
+            });
+
+            // Start a fresh window once the previous one (or lockout) has lapsed
+            if now.duration_since(state.window_start) >= self.window
+                || state.locked_until.is_some_and(|until| until <= now)
+            {
+                state.failures = 0;
+                state.window_start = now;
+                state.locked_until = None;
+            }
+
+            state.failures += 1;
+            if state.failures >= max_attempts {
+                state.locked_until = Some(now + self.cooldown);
+            }
+        }
+    }
+
+    /// Clears the failure counts for `email`, e.g. after a successful login.
+    pub fn reset(&self, email: &str, ip: Option<IpAddr>) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for (key, _) in self.keys(email, ip) {
+            attempts.remove(&key);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// In-memory token bucket per client IP. Each client may send `burst` requests at once,
+/// refilled at `requests_per_minute`.
+pub struct IpRateLimiter {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    burst: f64,
+    per_second: f64,
+}
+
+impl IpRateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            burst: f64::from(settings.burst),
+            per_second: f64::from(settings.requests_per_minute) / 60.0,
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+
+        // Buckets that have refilled completely are indistinguishable from new ones
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let (burst, per_second) = (self.burst, self.per_second);
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second
+                    < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+}
@@ -1,27 +1,190 @@
+use std::collections::HashMap;
+
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+use super::pagination::{Cursor, CursorPagination, Pagination};
+use crate::middleware::request_id::current_request_id;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Success,
+    Error,
+}
+
+/// The envelope around every successful response body. Failures use the same shape
+/// with `status: "error"`, no `data` and an `error` member; see [`ApiResponse::error`].
+#[derive(Serialize, ToSchema)]
 pub struct ApiResponse<T: Serialize> {
+    pub status: ResponseStatus,
+    /// Predates `status` and is kept for existing clients; true exactly when `status`
+    /// is `success`.
     pub success: bool,
     pub data: Option<T>,
     pub message: Option<String>,
+    /// Pagination and other information about the response rather than the resource.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ResponseMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ApiError>,
+}
+
+/// The `meta` member of list responses.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ResponseMeta {
+    pub total: i64,
+    pub page: u32,
+    pub per_page: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ResponseMeta {
+    /// Pagination metadata for a page of `total` rows, tagged with the current request id.
+    pub fn new(total: i64, pagination: Pagination) -> Self {
+        Self {
+            total,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            request_id: current_request_id(),
+        }
+    }
+}
+
+/// The `error` member of a failed [`ApiResponse`].
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiError {
+    /// Machine-readable error code, e.g. `VALIDATION_ERROR`.
+    pub code: String,
+    pub message: String,
+    /// Per-field validation messages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<HashMap<String, Vec<String>>>,
+    /// Extra context for specific errors, e.g. `suspended_until`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl<T: Serialize> ApiResponse<T> {
     pub fn success(data: T) -> Self {
         Self {
+            status: ResponseStatus::Success,
             success: true,
             data: Some(data),
             message: None,
+            meta: None,
+            error: None,
         }
     }
 
     pub fn success_with_message(data: T, message: String) -> Self {
         Self {
+            status: ResponseStatus::Success,
             success: true,
             data: Some(data),
             message: Some(message),
+            meta: None,
+            error: None,
+        }
+    }
+
+    pub fn success_with_meta(data: T, meta: ResponseMeta) -> Self {
+        Self {
+            meta: Some(meta),
+            ..Self::success(data)
+        }
+    }
+
+    /// A failure in the envelope shape. It is sent with 200 like any `ApiResponse`, so
+    /// pair it with a status code, e.g. `(StatusCode::CONFLICT, ApiResponse::error(..))`.
+    /// Returning an `AppError` instead gives the same body when `application.error_format`
+    /// is `envelope`.
+    pub fn error(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::from_error(ApiError {
+            code: code.into(),
+            message: message.into(),
+            fields: None,
+            details: None,
+            request_id: None,
+        })
+    }
+
+    pub fn from_error(error: ApiError) -> Self {
+        Self {
+            status: ResponseStatus::Error,
+            success: false,
+            data: None,
+            message: Some(error.message.clone()),
+            meta: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PaginatedResponse<T: Serialize> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub page: u32,
+    pub per_page: u32,
+    pub total_pages: u32,
+}
+
+impl<T: Serialize> PaginatedResponse<T> {
+    pub fn new(items: Vec<T>, total: i64, pagination: Pagination) -> Self {
+        let total_pages = (total.max(0) as u64).div_ceil(u64::from(pagination.per_page));
+
+        Self {
+            items,
+            total,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total_pages: u32::try_from(total_pages).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CursorPage<T: Serialize> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+impl<T: Serialize> CursorPage<T> {
+    /// Builds a page from rows fetched with [`CursorPagination::fetch_limit`], using
+    /// `cursor_of` to derive the next cursor from the last row kept.
+    pub fn new(
+        mut items: Vec<T>,
+        pagination: CursorPagination,
+        cursor_of: impl Fn(&T) -> Cursor,
+    ) -> Self {
+        let has_more = items.len() > pagination.limit as usize;
+        items.truncate(pagination.limit as usize);
+
+        let next_cursor = if has_more {
+            items.last().map(|item| cursor_of(item).encode())
+        } else {
+            None
+        };
+
+        Self {
+            items,
+            next_cursor,
+            has_more,
+        }
+    }
+
+    pub fn map<U: Serialize>(self, f: impl FnMut(T) -> U) -> CursorPage<U> {
+        CursorPage {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+            has_more: self.has_more,
         }
     }
 }
@@ -0,0 +1,32 @@
+use std::{future::Future, pin::Pin};
+
+use sqlx::{PgPool, Postgres, Transaction};
+
+use super::error::AppResult;
+
+/// Future returned by the closure passed to [`with_transaction`].
+pub type TxFuture<'c, T> = Pin<Box<dyn Future<Output = AppResult<T>> + Send + 'c>>;
+
+/// Runs `f` in a transaction, committing when it returns `Ok` and rolling back on `Err`.
+/// The closure returns a boxed future, e.g. `|tx| Box::pin(async move { ... })`, and
+/// runs its queries against `&mut **tx`.
+pub async fn with_transaction<T, F>(pool: &PgPool, f: F) -> AppResult<T>
+where
+    F: for<'c> FnOnce(&'c mut Transaction<'static, Postgres>) -> TxFuture<'c, T>,
+{
+    let mut tx = pool.begin().await?;
+
+    match f(&mut tx).await {
+        Ok(value) => {
+            tx.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            // Dropping the transaction would also roll back, but this surfaces failures
+            if let Err(rollback_error) = tx.rollback().await {
+                tracing::warn!("Failed to roll back transaction: {}", rollback_error);
+            }
+            Err(e)
+        }
+    }
+}
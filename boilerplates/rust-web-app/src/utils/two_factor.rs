@@ -0,0 +1,243 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rand::RngCore;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hmac,
+};
+use uuid::Uuid;
+
+use super::{
+    auth::constant_time_eq,
+    error::{AppError, AppResult},
+};
+use crate::config::TwoFactorSettings;
+
+/// Seconds each TOTP code is valid for.
+pub const TOTP_STEP_SECS: u64 = 30;
+
+/// Steps either side of the current one whose codes are still accepted, to absorb clock
+/// drift between the server and the authenticator app.
+pub const TOTP_SKEW_STEPS: u64 = 1;
+
+const TOTP_DIGITS: usize = 6;
+
+/// 160 bits, the HMAC-SHA1 block size RFC 4226 recommends.
+const SECRET_LEN: usize = 20;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Generates a random TOTP secret.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// RFC 4648 base32 without padding, the encoding authenticator apps expect secrets in.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut block = [0u8; 5];
+        block[..chunk.len()].copy_from_slice(chunk);
+        let bits = block
+            .iter()
+            .fold(0u64, |bits, byte| bits << 8 | u64::from(*byte));
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            let index = (bits >> (35 - i * 5)) & 31;
+            encoded.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// Decodes base32 case-insensitively, ignoring padding and whitespace.
+pub fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let (mut bits, mut pending) = (0u64, 0);
+    for c in text.chars().filter(|c| *c != '=' && !c.is_whitespace()) {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|letter| *letter as char == c.to_ascii_uppercase())?;
+        bits = bits << 5 | value as u64;
+        pending += 5;
+        if pending >= 8 {
+            pending -= 8;
+            decoded.push((bits >> pending) as u8);
+            bits &= (1 << pending) - 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Provisioning URI that authenticator apps read from a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        base32_encode(secret),
+        percent_encode(issuer),
+        TOTP_DIGITS,
+        TOTP_STEP_SECS
+    )
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The RFC 6238 time step for the current time.
+pub fn current_step() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / TOTP_STEP_SECS
+}
+
+/// The code for `step`: HOTP (RFC 4226) over the step counter with HMAC-SHA1.
+pub fn totp_code(secret: &[u8], step: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(TOTP_DIGITS as u32),
+        width = TOTP_DIGITS
+    )
+}
+
+/// Whether `code` has the shape of a TOTP code rather than a recovery code.
+pub fn is_totp_code(code: &str) -> bool {
+    let code = code.trim();
+    code.len() == TOTP_DIGITS && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Checks `code` against the steps within [`TOTP_SKEW_STEPS`] of `step`, skipping any at
+/// or before `last_used_step` so an accepted code can't be replayed. Returns the step
+/// that matched.
+pub fn verify_totp(
+    secret: &[u8],
+    code: &str,
+    step: u64,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    if !is_totp_code(code) {
+        return None;
+    }
+    let code = code.trim().as_bytes();
+
+    (step.saturating_sub(TOTP_SKEW_STEPS)..=step + TOTP_SKEW_STEPS)
+        .filter(|candidate| last_used_step.iter().all(|last| candidate > last))
+        .find(|candidate| constant_time_eq(totp_code(secret, *candidate).as_bytes(), code))
+}
+
+/// Random single-use recovery codes, formatted like `1a2b-3c4d-5e6f`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut bytes = [0u8; 6];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            let hex = hex::encode(bytes);
+            format!("{}-{}-{}", &hex[..4], &hex[4..8], &hex[8..])
+        })
+        .collect()
+}
+
+/// The form recovery codes are hashed in: lowercase without separators, so they can be
+/// typed loosely.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Encrypts TOTP secrets at rest with AES-256-GCM.
+pub struct SecretCipher {
+    key: LessSafeKey,
+}
+
+impl SecretCipher {
+    /// Parses a hex-encoded 32-byte key.
+    pub fn new(hex_key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_key.trim()).map_err(|_| "must be hex-encoded".to_string())?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| {
+            format!(
+                "must be 32 bytes (64 hex characters), got {} bytes",
+                bytes.len()
+            )
+        })?;
+        Ok(Self {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// The cipher for the configured key; two-factor authentication is unavailable
+    /// without one.
+    pub fn from_settings(settings: &TwoFactorSettings) -> AppResult<Self> {
+        let key = settings.encryption_key.as_deref().ok_or_else(|| {
+            AppError::ServiceUnavailable("Two-factor authentication is not configured".to_string())
+        })?;
+        Self::new(key).map_err(|e| {
+            AppError::InternalError(format!("Invalid two_factor.encryption_key: {}", e))
+        })
+    }
+
+    /// Encrypts a user's secret. The user id is authenticated along with it, so a secret
+    /// copied to another account fails to decrypt.
+    pub fn encrypt(&self, user_id: Uuid, secret: &[u8]) -> AppResult<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut sealed = secret.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(user_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| {
+                AppError::InternalError("Failed to encrypt two-factor secret".to_string())
+            })?;
+
+        Ok(STANDARD.encode([nonce.as_slice(), &sealed].concat()))
+    }
+
+    pub fn decrypt(&self, user_id: Uuid, encrypted: &str) -> AppResult<Vec<u8>> {
+        let failed = || AppError::InternalError("Failed to decrypt two-factor secret".to_string());
+
+        let bytes = STANDARD.decode(encrypted).map_err(|_| failed())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(failed());
+        }
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| failed())?;
+
+        let mut sealed = sealed.to_vec();
+        let secret = self
+            .key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut sealed)
+            .map_err(|_| failed())?;
+        Ok(secret.to_vec())
+    }
+}
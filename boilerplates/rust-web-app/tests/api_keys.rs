@@ -0,0 +1,214 @@
+mod common;
+
+use std::time::Duration;
+
+use chrono::{Duration as ChronoDuration, Utc};
+use common::{spawn_app, TestApp};
+use serde_json::{json, Value};
+
+/// Creates a key for the user behind `token`, returning the response data.
+async fn create_key(app: &TestApp, token: &str, body: Value) -> Value {
+    let response = app
+        .post("/api/users/me/api-keys")
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let body: Value = response.json().await.unwrap();
+    body["data"].clone()
+}
+
+async fn me_with_key(app: &TestApp, key: &str) -> reqwest::Response {
+    app.get("/api/users/me")
+        .header("X-Api-Key", key)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn api_keys_authenticate_as_their_owner() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let created = create_key(
+        &app,
+        &token,
+        json!({ "name": "reporting", "scopes": ["reports:read"] }),
+    )
+    .await;
+    let key = created["key"].as_str().unwrap();
+    assert!(key.starts_with("rwa_"));
+    assert!(key.starts_with(created["api_key"]["prefix"].as_str().unwrap()));
+    assert_eq!(created["api_key"]["scopes"], json!(["reports:read"]));
+
+    let response = me_with_key(&app, key).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["email"], "jane@example.com");
+
+    // Only the hash is stored and the key is never listed again
+    let stored: String = sqlx::query_scalar("SELECT key_hash FROM api_keys")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_ne!(stored, key);
+    let listed: Value = app
+        .get("/api/users/me/api-keys")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed["data"].as_array().unwrap().len(), 1);
+    assert_eq!(listed["data"][0]["name"], "reporting");
+    assert!(!listed.to_string().contains(key));
+}
+
+#[tokio::test]
+async fn unknown_and_malformed_keys_are_rejected() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let key = create_key(&app, &token, json!({ "name": "ci" })).await["key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Same prefix, different secret
+    let forged = format!("{}{}", &key[..12], "0".repeat(key.len() - 12));
+    for guess in [forged.as_str(), "rwa_123", "not-a-key", ""] {
+        let response = me_with_key(&app, guess).await;
+        assert_eq!(response.status(), 401, "{}", guess);
+    }
+}
+
+#[tokio::test]
+async fn revoked_and_expired_keys_stop_working() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let revoked = create_key(&app, &token, json!({ "name": "old" })).await;
+    let response = app
+        .delete(&format!(
+            "/api/users/me/api-keys/{}",
+            revoked["api_key"]["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    let response = me_with_key(&app, revoked["key"].as_str().unwrap()).await;
+    assert_eq!(response.status(), 401);
+
+    let expires_at = Utc::now() + ChronoDuration::hours(1);
+    let expiring = create_key(
+        &app,
+        &token,
+        json!({ "name": "temp", "expires_at": expires_at }),
+    )
+    .await;
+    let key = expiring["key"].as_str().unwrap();
+    assert_eq!(me_with_key(&app, key).await.status(), 200);
+
+    sqlx::query("UPDATE api_keys SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let response = me_with_key(&app, key).await;
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "API key has expired");
+}
+
+#[tokio::test]
+async fn keys_can_only_be_revoked_by_their_owner() {
+    let app = spawn_app().await;
+    let jane = app.register_user("jane@example.com").await;
+    let john = app.register_user("john@example.com").await;
+    let created = create_key(&app, &jane, json!({ "name": "ci" })).await;
+
+    let path = format!(
+        "/api/users/me/api-keys/{}",
+        created["api_key"]["id"].as_str().unwrap()
+    );
+    let response = app.delete(&path).bearer_auth(&john).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = me_with_key(&app, created["key"].as_str().unwrap()).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn create_rejects_bad_scopes_and_past_expiry() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    for body in [
+        json!({ "name": "" }),
+        json!({ "name": "ci", "scopes": ["Not A Scope"] }),
+        json!({ "name": "ci", "scopes": vec!["read"; 21] }),
+    ] {
+        let response = app
+            .post("/api/users/me/api-keys")
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422, "{}", body);
+    }
+
+    let response = app
+        .post("/api/users/me/api-keys")
+        .bearer_auth(&token)
+        .json(&json!({ "name": "ci", "expires_at": Utc::now() - ChronoDuration::minutes(1) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn last_used_at_is_written_at_most_once_per_interval() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let key = create_key(&app, &token, json!({ "name": "ci" })).await["key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let last_used_at = || async {
+        sqlx::query_scalar::<_, Option<chrono::DateTime<Utc>>>("SELECT last_used_at FROM api_keys")
+            .fetch_one(&app.db)
+            .await
+            .unwrap()
+    };
+
+    assert_eq!(me_with_key(&app, &key).await.status(), 200);
+    // The write happens in the background
+    let mut recorded = None;
+    for _ in 0..50 {
+        recorded = last_used_at().await;
+        if recorded.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(recorded.is_some());
+
+    // Further uses within the interval don't write again
+    sqlx::query("UPDATE api_keys SET last_used_at = NULL")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    for _ in 0..3 {
+        assert_eq!(me_with_key(&app, &key).await.status(), 200);
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(last_used_at().await, None);
+}
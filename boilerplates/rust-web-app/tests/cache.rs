@@ -0,0 +1,102 @@
+mod common;
+
+use std::time::Instant;
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use serde_json::{json, Value};
+
+async fn profile(app: &TestApp, token: &str) -> reqwest::Response {
+    app.get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn profile_updates_invalidate_the_cached_profile() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    profile(&app, &token).await;
+
+    app.patch("/api/users/me")
+        .bearer_auth(&token)
+        .json(&json!({ "name": "Jane Smith" }))
+        .send()
+        .await
+        .unwrap();
+
+    let body: Value = profile(&app, &token).await.json().await.unwrap();
+    assert_eq!(body["data"]["name"], "Jane Smith");
+}
+
+#[tokio::test]
+async fn password_change_revokes_tokens_despite_cached_auth_state() {
+    let app = spawn_app().await;
+    let old_token = app.register_user("jane@example.com").await;
+    assert_eq!(profile(&app, &old_token).await.status(), 200);
+
+    // Tokens only have second precision, so make sure the change lands in a later second
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&old_token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": "another-password-7" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(profile(&app, &old_token).await.status(), 401);
+}
+
+#[tokio::test]
+async fn suspension_applies_despite_cached_auth_state() {
+    let app = spawn_app().await;
+    let admin_token = app.register_admin("admin@example.com").await;
+    let token = app.register_user("jane@example.com").await;
+    let body: Value = profile(&app, &token).await.json().await.unwrap();
+    let user_id = body["data"]["id"].as_str().unwrap();
+
+    let response = app
+        .post(&format!("/api/admin/users/{}/suspend", user_id))
+        .bearer_auth(&admin_token)
+        .json(&json!({ "duration_secs": 3600, "reason": "Spam" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(profile(&app, &token).await.status(), 403);
+}
+
+#[tokio::test]
+async fn the_in_memory_cache_does_not_hold_auth_state() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    assert_eq!(profile(&app, &token).await.status(), 200);
+
+    // Stands in for a suspension made through another instance, whose invalidation
+    // never reaches this process
+    sqlx::query("UPDATE users SET suspended_until = NOW() + INTERVAL '1 hour'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    assert_eq!(profile(&app, &token).await.status(), 403);
+}
+
+#[tokio::test]
+async fn unreachable_redis_falls_back_to_the_database() {
+    let app = spawn_app_with(|settings| {
+        settings.redis.url = Some("redis://127.0.0.1:1".to_string());
+    })
+    .await;
+    let token = app.register_user("jane@example.com").await;
+
+    let started = Instant::now();
+    let response = profile(&app, &token).await;
+
+    assert_eq!(response.status(), 200);
+    assert!(started.elapsed().as_secs() < 5);
+}
@@ -0,0 +1,37 @@
+use axum::{body::Body, http::Request, routing::get, Router};
+use http_body_util::BodyExt;
+use rust_web_app::middleware::{catch_panic::handle_panic, request_id};
+use serde_json::Value;
+use tower::ServiceExt;
+use tower_http::catch_panic::CatchPanicLayer;
+
+async fn boom() -> &'static str {
+    panic!("handler exploded")
+}
+
+#[tokio::test]
+async fn panicking_handlers_return_the_error_envelope() {
+    // Same layering as `build_app`
+    let app = Router::new()
+        .route("/boom", get(boom))
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(axum::middleware::from_fn(request_id::request_id));
+
+    let response = app
+        .oneshot(
+            Request::get("/boom")
+                .header("x-request-id", "panic-test")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"], "INTERNAL_ERROR");
+    assert_eq!(body["message"], "Internal server error");
+    assert_eq!(body["request_id"], "panic-test");
+}
@@ -0,0 +1,273 @@
+//! Shared helpers for the integration tests. Every test gets its own freshly migrated
+//! database and a server bound to a random port; the database is dropped afterwards.
+
+// Each test binary compiles this module separately and uses a different subset of it
+#![allow(dead_code)]
+
+use std::{
+    net::SocketAddr,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use rust_web_app::{
+    build_app,
+    config::Settings,
+    db::{self, Db},
+    repositories::{ApiKeyRepository, OAuthRepository, PgUserRepository, TwoFactorRepository},
+    utils::{
+        auth::JwtKeys,
+        cache::{Cache, MemoryCache, RedisCache},
+        error::AppResult,
+        mailer::Mailer,
+        rate_limit::LoginRateLimiter,
+    },
+    AppState,
+};
+use serde_json::{json, Value};
+use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection, PgPool};
+use tokio::{net::TcpListener, sync::Mutex};
+use uuid::Uuid;
+
+pub const PASSWORD: &str = "correct-horse-42";
+
+#[derive(Debug, Clone)]
+pub struct SentEmail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Mailer that records messages so tests can read tokens out of them.
+#[derive(Default)]
+pub struct CapturingMailer {
+    sent: Mutex<Vec<SentEmail>>,
+}
+
+#[async_trait]
+impl Mailer for CapturingMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> AppResult<()> {
+        self.sent.lock().await.push(SentEmail {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+        });
+        Ok(())
+    }
+}
+
+impl CapturingMailer {
+    /// Waits for the most recent email to `to`; emails are sent in the background.
+    pub async fn wait_for(&self, to: &str) -> SentEmail {
+        for _ in 0..50 {
+            if let Some(email) = self.sent.lock().await.iter().rev().find(|e| e.to == to) {
+                return email.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("no email sent to {}", to);
+    }
+
+    /// Waits for an email to `to` with `subject`, skipping earlier ones such as the
+    /// verification email sent at registration.
+    pub async fn wait_for_subject(&self, to: &str, subject: &str) -> SentEmail {
+        for _ in 0..50 {
+            let sent = self.sent.lock().await;
+            if let Some(email) = sent
+                .iter()
+                .rev()
+                .find(|e| e.to == to && e.subject == subject)
+            {
+                return email.clone();
+            }
+            drop(sent);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("no email to {} with subject {:?}", to, subject);
+    }
+}
+
+pub struct TestApp {
+    pub address: String,
+    pub db: PgPool,
+    pub client: Client,
+    pub mailer: Arc<CapturingMailer>,
+    db_name: String,
+    maintenance: PgConnectOptions,
+}
+
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like [`spawn_app`], but lets the test adjust settings before the app is built.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
+    let mut settings = Settings::new().expect("failed to load settings");
+    // Cheap password hashing keeps the suite fast
+    settings.application.argon2_memory_kib = 1024;
+    settings.application.argon2_iterations = 1;
+    // Every test client shares one IP, so the per-IP throttle would trip across tests
+    settings.rate_limit.requests_per_minute = 10_000;
+    settings.rate_limit.burst = 10_000;
+    settings.two_factor.encryption_key = Some("42".repeat(32));
+    configure(&mut settings);
+
+    let options =
+        PgConnectOptions::from_str(&settings.database_url()).expect("invalid database url");
+    let maintenance = options.clone().database("postgres");
+    let db_name = format!("test_{}", Uuid::new_v4().simple());
+
+    let mut conn = PgConnection::connect_with(&maintenance)
+        .await
+        .expect("failed to connect to Postgres");
+    conn.execute(format!(r#"CREATE DATABASE "{}""#, db_name).as_str())
+        .await
+        .expect("failed to create test database");
+
+    let db = db::pool_options(&settings.database)
+        .connect_with(options.database(&db_name))
+        .await
+        .expect("failed to connect to test database");
+    sqlx::migrate!("./migrations")
+        .run(&db)
+        .await
+        .expect("failed to run migrations");
+
+    // A configured replica is pointed at the test database on that server, so tests can
+    // use the primary's URL for a working replica or an unreachable one for a broken one
+    let replica = settings.database.replica_url.as_ref().map(|url| {
+        let options = PgConnectOptions::from_str(url).expect("invalid replica url");
+        db::pool_options(&settings.database).connect_lazy_with(options.database(&db_name))
+    });
+
+    let cache: Arc<dyn Cache> = match &settings.redis.url {
+        Some(url) => Arc::new(RedisCache::new(url).expect("invalid redis url")),
+        None => Arc::new(MemoryCache::new()),
+    };
+
+    let app_db = Db::new(db.clone(), replica);
+    let mailer = Arc::new(CapturingMailer::default());
+    let state = AppState {
+        users: Arc::new(PgUserRepository::new(app_db.clone())),
+        two_factor: TwoFactorRepository::new(app_db.clone()),
+        api_keys: ApiKeyRepository::new(app_db.clone()),
+        oauth: OAuthRepository::new(app_db.clone()),
+        db: app_db,
+        login_limiter: Arc::new(LoginRateLimiter::new(&settings.rate_limit)),
+        cache,
+        jwt: Arc::new(JwtKeys::new(&settings.application).expect("invalid JWT keys")),
+        config: settings,
+        mailer: mailer.clone(),
+        started_at: Instant::now(),
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind test listener");
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let app = build_app(state);
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        .expect("test server failed");
+    });
+
+    TestApp {
+        address,
+        db,
+        client: Client::new(),
+        mailer,
+        db_name,
+        maintenance,
+    }
+}
+
+impl TestApp {
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(format!("{}{}", self.address, path))
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.client.post(format!("{}{}", self.address, path))
+    }
+
+    pub fn put(&self, path: &str) -> RequestBuilder {
+        self.client.put(format!("{}{}", self.address, path))
+    }
+
+    pub fn patch(&self, path: &str) -> RequestBuilder {
+        self.client.patch(format!("{}{}", self.address, path))
+    }
+
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        self.client.delete(format!("{}{}", self.address, path))
+    }
+
+    pub async fn register(&self, email: &str, name: &str) -> Response {
+        self.post("/api/auth/register")
+            .json(&json!({ "email": email, "password": PASSWORD, "name": name }))
+            .send()
+            .await
+            .expect("request failed")
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> Response {
+        self.post("/api/auth/login")
+            .json(&json!({ "email": email, "password": password }))
+            .send()
+            .await
+            .expect("request failed")
+    }
+
+    /// Registers a user and returns their token.
+    pub async fn register_user(&self, email: &str) -> String {
+        let response = self.register(email, "Test User").await;
+        assert_eq!(response.status(), 200, "registration failed");
+        let body: Value = response.json().await.unwrap();
+        body["data"]["token"].as_str().unwrap().to_string()
+    }
+
+    /// Registers a user, promotes them to admin and returns their token.
+    pub async fn register_admin(&self, email: &str) -> String {
+        let token = self.register_user(email).await;
+        sqlx::query("UPDATE users SET role = 'admin' WHERE email = $1")
+            .bind(email)
+            .execute(&self.db)
+            .await
+            .unwrap();
+        token
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let maintenance = self.maintenance.clone();
+        let db_name = self.db_name.clone();
+
+        // Drop can't be async and runs inside the test's runtime, so clean up on a
+        // separate thread with its own runtime
+        let _ = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async move {
+                if let Ok(mut conn) = PgConnection::connect_with(&maintenance).await {
+                    let _ = conn
+                        .execute(
+                            format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE)"#, db_name)
+                                .as_str(),
+                        )
+                        .await;
+                }
+            });
+        })
+        .join();
+    }
+}
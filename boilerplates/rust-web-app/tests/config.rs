@@ -0,0 +1,120 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rust_web_app::config::Settings;
+
+#[test]
+fn server_host_accepts_ipv4_and_ipv6_addresses() {
+    let mut settings = Settings::new().unwrap();
+
+    for (host, ip) in [
+        ("127.0.0.1", IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        ("0.0.0.0", IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        ("::1", IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        ("[::]", IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+    ] {
+        settings.server.host = host.to_string();
+        assert_eq!(settings.server.ip().unwrap(), ip);
+        assert!(settings.validate().is_ok(), "{}", host);
+    }
+}
+
+#[test]
+fn server_host_must_be_an_ip_address() {
+    let mut settings = Settings::new().unwrap();
+    settings.server.host = "localhost".to_string();
+
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("server.host"), "{}", error);
+    assert!(error.contains("IPv4 or IPv6"), "{}", error);
+}
+
+#[test]
+fn trusted_proxies_must_be_addresses_or_ranges() {
+    let mut settings = Settings::new().unwrap();
+    settings.server.trusted_proxies = vec!["10.0.0.0/8".to_string(), "::1".to_string()];
+    assert!(settings.validate().is_ok());
+
+    settings
+        .server
+        .trusted_proxies
+        .push("proxy.internal".to_string());
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("server.trusted_proxies"), "{}", error);
+    assert!(error.contains("proxy.internal"), "{}", error);
+}
+
+#[test]
+fn metrics_listener_must_not_share_the_server_port() {
+    let mut settings = Settings::new().unwrap();
+    assert_eq!(
+        settings.metrics.ip().unwrap(),
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    );
+    assert!(settings.validate().is_ok());
+
+    settings.metrics.port = settings.server.port;
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("metrics.port"), "{}", error);
+}
+
+#[test]
+fn auth_cache_ttl_is_capped() {
+    let mut settings = Settings::new().unwrap();
+    settings.cache.auth_ttl_secs = 60;
+    assert!(settings.validate().is_ok());
+
+    settings.cache.auth_ttl_secs = 61;
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("cache.auth_ttl_secs"), "{}", error);
+}
+
+#[test]
+fn argon2_costs_must_be_in_range() {
+    for (key, configure) in [
+        (
+            "application.argon2_iterations",
+            (|s: &mut Settings| s.application.argon2_iterations = 0) as fn(&mut Settings),
+        ),
+        ("application.argon2_parallelism", |s| {
+            s.application.argon2_parallelism = 0
+        }),
+        ("application.argon2_memory_kib", |s| {
+            s.application.argon2_parallelism = 4;
+            s.application.argon2_memory_kib = 16;
+        }),
+        ("application.argon2_memory_kib", |s| {
+            s.application.argon2_memory_kib = 4 * 1024 * 1024
+        }),
+    ] {
+        let mut settings = Settings::new().unwrap();
+        assert!(settings.validate().is_ok());
+        configure(&mut settings);
+
+        let error = settings.validate().unwrap_err().to_string();
+        assert!(error.contains(key), "{}", error);
+    }
+}
+
+#[test]
+fn bcrypt_cost_must_be_in_range() {
+    let mut settings = Settings::new().unwrap();
+    for cost in [3, 32] {
+        settings.application.bcrypt_cost = cost;
+        let error = settings.validate().unwrap_err().to_string();
+        assert!(error.contains("application.bcrypt_cost"), "{}", error);
+    }
+}
+
+#[test]
+fn password_policy_must_be_in_range() {
+    let mut settings = Settings::new().unwrap();
+    settings.application.password_policy.min_length = 7;
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("application.password_policy"), "{}", error);
+    assert!(error.contains("min_length"), "{}", error);
+
+    settings.application.password_policy.min_length = 12;
+    settings.application.password_policy.min_score = Some(5);
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("min_score"), "{}", error);
+}
@@ -0,0 +1,263 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use reqwest::header::{COOKIE, SET_COOKIE};
+use serde_json::Value;
+
+async fn spawn_app_with_cookies() -> TestApp {
+    spawn_app_with(|settings| settings.auth.cookie_enabled = true).await
+}
+
+/// Email of the user `request` authenticates as, or `None` when it is rejected.
+async fn me_email(request: reqwest::RequestBuilder) -> Option<String> {
+    let response = request.send().await.unwrap();
+    if response.status() != 200 {
+        assert_eq!(response.status(), 401);
+        return None;
+    }
+    let body: Value = response.json().await.unwrap();
+    Some(body["data"]["email"].as_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn cookies_are_neither_set_nor_accepted_by_default() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app.login("jane@example.com", PASSWORD).await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    let response = app
+        .get("/api/users/me")
+        .header(COOKIE, format!("access_token={}", token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn login_sets_an_http_only_cookie_that_authenticates() {
+    let app = spawn_app_with_cookies().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app.login("jane@example.com", PASSWORD).await;
+    assert_eq!(response.status(), 200);
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap().to_string();
+    let body: Value = response.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    assert!(cookie.starts_with(&format!("access_token={};", token)));
+    for attribute in [
+        "HttpOnly",
+        "Secure",
+        "SameSite=Strict",
+        "Path=/",
+        "Max-Age=3600",
+    ] {
+        assert!(
+            cookie.contains(attribute),
+            "{} missing {}",
+            cookie,
+            attribute
+        );
+    }
+
+    let request = app
+        .get("/api/users/me")
+        .header(COOKIE, format!("theme=dark; access_token={}", token));
+    assert_eq!(me_email(request).await.as_deref(), Some("jane@example.com"));
+}
+
+#[tokio::test]
+async fn authorization_header_takes_precedence_over_the_cookie() {
+    let app = spawn_app_with_cookies().await;
+    let jane = app.register_user("jane@example.com").await;
+    let john = app.register_user("john@example.com").await;
+    let jane_cookie = format!("access_token={}", jane);
+
+    let request = app
+        .get("/api/users/me")
+        .bearer_auth(&john)
+        .header(COOKIE, &jane_cookie);
+    assert_eq!(me_email(request).await.as_deref(), Some("john@example.com"));
+
+    // A bad header isn't rescued by a valid cookie
+    let request = app
+        .get("/api/users/me")
+        .bearer_auth("not-a-token")
+        .header(COOKIE, &jane_cookie);
+    assert_eq!(me_email(request).await, None);
+}
+
+/// The cookies a response sets, as the `Cookie` header a browser would send back.
+fn returned_cookies(response: &reqwest::Response) -> String {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|cookie| {
+            let cookie = cookie.to_str().unwrap();
+            cookie.split(';').next().unwrap().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Value of the `csrf_token` cookie among `cookies`.
+fn csrf_token(cookies: &str) -> &str {
+    cookies
+        .split("; ")
+        .find_map(|cookie| cookie.strip_prefix("csrf_token="))
+        .unwrap()
+}
+
+/// Status of a profile update sent with `request`.
+async fn rename(request: reqwest::RequestBuilder) -> u16 {
+    request
+        .json(&serde_json::json!({ "name": "Jane Doe" }))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn login_sets_a_csrf_cookie_scripts_can_read() {
+    let app = spawn_app_with_cookies().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app.login("jane@example.com", PASSWORD).await;
+    let csrf_cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap())
+        .find(|cookie| cookie.starts_with("csrf_token="))
+        .unwrap()
+        .to_string();
+
+    assert!(!csrf_cookie.contains("HttpOnly"), "{}", csrf_cookie);
+    for attribute in ["Secure", "SameSite=Strict", "Path=/"] {
+        assert!(csrf_cookie.contains(attribute), "{}", csrf_cookie);
+    }
+    assert!(csrf_token(&returned_cookies(&response)).len() >= 32);
+}
+
+#[tokio::test]
+async fn cookie_authenticated_writes_need_the_csrf_header() {
+    let app = spawn_app_with_cookies().await;
+    app.register_user("jane@example.com").await;
+    let response = app.login("jane@example.com", PASSWORD).await;
+    let cookies = returned_cookies(&response);
+    let body: Value = response.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let path = "/api/users/me";
+    let with_cookies = || app.patch(path).header(COOKIE, &cookies);
+    assert_eq!(rename(with_cookies()).await, 403);
+    assert_eq!(
+        rename(with_cookies().header("X-CSRF-Token", "forged")).await,
+        403
+    );
+    assert_eq!(
+        rename(with_cookies().header("X-CSRF-Token", csrf_token(&cookies))).await,
+        200
+    );
+
+    // Without the CSRF cookie there is nothing to match the header against
+    let access_only = format!("access_token={}", token);
+    let request = app
+        .patch(path)
+        .header(COOKIE, &access_only)
+        .header("X-CSRF-Token", "");
+    assert_eq!(rename(request).await, 403);
+
+    // Reads and header-authenticated requests don't need the token
+    let request = app.get(path).header(COOKIE, &cookies);
+    assert_eq!(me_email(request).await.as_deref(), Some("jane@example.com"));
+    let request = app.patch(path).bearer_auth(token).header(COOKIE, &cookies);
+    assert_eq!(rename(request).await, 200);
+}
+
+#[tokio::test]
+async fn csrf_tokens_are_not_required_without_cookie_auth() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let request = app
+        .patch("/api/users/me")
+        .header(COOKIE, format!("access_token={}", token))
+        .bearer_auth(&token);
+    assert_eq!(rename(request).await, 200);
+
+    let response = app.get("/api/auth/csrf-token").send().await.unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn csrf_token_endpoint_issues_a_fresh_token() {
+    let app = spawn_app_with_cookies().await;
+    app.register_user("jane@example.com").await;
+    let login_cookies = returned_cookies(&app.login("jane@example.com", PASSWORD).await);
+
+    let response = app.get("/api/auth/csrf-token").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let refreshed = returned_cookies(&response);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["csrf_token"], csrf_token(&refreshed));
+    assert_ne!(csrf_token(&refreshed), csrf_token(&login_cookies));
+
+    // The new token replaces the one from login
+    let access_token = login_cookies.split("; ").next().unwrap();
+    let cookies = format!("{}; {}", access_token, refreshed);
+    let request = app
+        .patch("/api/users/me")
+        .header(COOKIE, &cookies)
+        .header("X-CSRF-Token", csrf_token(&refreshed));
+    assert_eq!(rename(request).await, 200);
+}
+
+#[tokio::test]
+async fn logout_clears_the_cookies() {
+    let app = spawn_app_with_cookies().await;
+    app.register_user("jane@example.com").await;
+    let cookies = returned_cookies(&app.login("jane@example.com", PASSWORD).await);
+
+    // Logging someone out is a state change too
+    let response = app
+        .post("/api/auth/logout")
+        .header(COOKIE, &cookies)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = app
+        .post("/api/auth/logout")
+        .header(COOKIE, &cookies)
+        .header("X-CSRF-Token", csrf_token(&cookies))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let cleared: Vec<_> = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(cleared.len(), 2);
+    for name in ["access_token", "csrf_token"] {
+        assert!(
+            cleared
+                .iter()
+                .any(|cookie| cookie.starts_with(&format!("{}=;", name))
+                    && cookie.contains("Max-Age=0")),
+            "{:?}",
+            cleared
+        );
+    }
+}
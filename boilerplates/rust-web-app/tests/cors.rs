@@ -0,0 +1,94 @@
+mod common;
+
+use common::{spawn_app_with, TestApp};
+use reqwest::{Method, Response};
+use rust_web_app::config::Settings;
+
+const ALLOWED_ORIGIN: &str = "https://app.example.com";
+
+async fn spawn_app_allowing(origins: &[&str], allow_credentials: bool) -> TestApp {
+    let origins: Vec<String> = origins.iter().map(|o| o.to_string()).collect();
+    spawn_app_with(move |settings| {
+        settings.cors.allowed_origins = origins;
+        settings.cors.allow_credentials = allow_credentials;
+    })
+    .await
+}
+
+async fn preflight(app: &TestApp, origin: &str) -> Response {
+    app.client
+        .request(Method::OPTIONS, format!("{}/api/users/me", app.address))
+        .header("origin", origin)
+        .header("access-control-request-method", "PUT")
+        .header(
+            "access-control-request-headers",
+            "authorization,content-type",
+        )
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn preflight_from_an_allowed_origin_is_granted() {
+    let app = spawn_app_allowing(&[ALLOWED_ORIGIN], false).await;
+
+    let response = preflight(&app, ALLOWED_ORIGIN).await;
+
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], ALLOWED_ORIGIN);
+    let methods = headers["access-control-allow-methods"].to_str().unwrap();
+    assert!(methods.contains("PUT"), "PUT missing from {}", methods);
+    let allowed_headers = headers["access-control-allow-headers"].to_str().unwrap();
+    assert!(allowed_headers.contains("authorization"));
+    assert!(allowed_headers.contains("content-type"));
+    assert_eq!(headers["access-control-max-age"], "3600");
+    assert!(!headers.contains_key("access-control-allow-credentials"));
+}
+
+#[tokio::test]
+async fn preflight_from_another_origin_is_not_granted() {
+    let app = spawn_app_allowing(&[ALLOWED_ORIGIN], false).await;
+
+    let response = preflight(&app, "https://evil.example.com").await;
+
+    assert!(!response
+        .headers()
+        .contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn cross_origin_requests_are_refused_by_default() {
+    let app = spawn_app_with(|_| {}).await;
+
+    let response = preflight(&app, ALLOWED_ORIGIN).await;
+
+    assert!(!response
+        .headers()
+        .contains_key("access-control-allow-origin"));
+}
+
+#[tokio::test]
+async fn wildcard_with_credentials_echoes_the_request_origin() {
+    let app = spawn_app_allowing(&["*"], true).await;
+
+    let response = preflight(&app, ALLOWED_ORIGIN).await;
+
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], ALLOWED_ORIGIN);
+    assert_eq!(headers["access-control-allow-credentials"], "true");
+}
+
+#[test]
+fn production_refuses_wildcard_origins_with_credentials() {
+    let mut settings = Settings::new().unwrap();
+    settings.application.environment = "production".to_string();
+    settings.application.jwt_secret = "a-sufficiently-long-production-secret".to_string();
+    settings.cors.allowed_origins = vec!["*".to_string()];
+    assert!(settings.validate().is_ok());
+
+    settings.cors.allow_credentials = true;
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("cors.allow_credentials"), "{}", error);
+}
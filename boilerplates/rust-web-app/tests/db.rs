@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+use rust_web_app::{config::Settings, db};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Settings whose database URL points at `port` on localhost, plus the real
+/// database's address.
+fn settings_via_port(port: u16) -> (Settings, String) {
+    let mut settings = Settings::new().unwrap();
+    let mut url = Url::parse(&settings.database.url).unwrap();
+    let target = format!("{}:{}", url.host_str().unwrap(), url.port().unwrap_or(5432));
+    url.set_host(Some("127.0.0.1")).unwrap();
+    url.set_port(Some(port)).unwrap();
+    settings.database.url = url.to_string();
+    (settings, target)
+}
+
+async fn unused_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Forwards connections on `port` to the real database, starting after `delay`.
+fn proxy_after(delay: Duration, port: u16, target: String) {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        loop {
+            let (mut inbound, _) = listener.accept().await.unwrap();
+            let target = target.clone();
+            tokio::spawn(async move {
+                let mut outbound = TcpStream::connect(target).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+            });
+        }
+    });
+}
+
+#[tokio::test]
+async fn connect_retries_until_the_database_is_reachable() {
+    let port = unused_port().await;
+    let (mut settings, target) = settings_via_port(port);
+    settings.database.connect_retry_secs = 10;
+    proxy_after(Duration::from_millis(800), port, target);
+
+    let started = Instant::now();
+    let pool = db::connect(&settings.database).await.unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(800));
+    let one: i32 = sqlx::query_scalar("SELECT 1")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(one, 1);
+}
+
+#[tokio::test]
+async fn connect_gives_up_after_the_retry_budget() {
+    let port = unused_port().await;
+    let (mut settings, _) = settings_via_port(port);
+    settings.database.connect_retry_secs = 1;
+
+    let started = Instant::now();
+    let result = db::connect(&settings.database).await;
+
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn connect_does_not_retry_errors_that_wont_go_away() {
+    let mut settings = Settings::new().unwrap();
+    let mut url = Url::parse(&settings.database.url).unwrap();
+    url.set_path("/database_that_does_not_exist");
+    settings.database.url = url.to_string();
+    settings.database.connect_retry_secs = 30;
+
+    let started = Instant::now();
+    let result = db::connect(&settings.database).await;
+
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn pool_options_apply_the_tuning_settings() {
+    let mut settings = Settings::new().unwrap().database;
+    settings.max_connections = 12;
+    settings.min_connections = 3;
+    settings.acquire_timeout_secs = 7;
+    settings.idle_timeout_secs = 120;
+    settings.max_lifetime_secs = 900;
+
+    let options = db::pool_options(&settings);
+
+    assert_eq!(options.get_max_connections(), 12);
+    assert_eq!(options.get_min_connections(), 3);
+    assert_eq!(options.get_acquire_timeout(), Duration::from_secs(7));
+    assert_eq!(options.get_idle_timeout(), Some(Duration::from_secs(120)));
+    assert_eq!(options.get_max_lifetime(), Some(Duration::from_secs(900)));
+}
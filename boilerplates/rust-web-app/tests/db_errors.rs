@@ -0,0 +1,106 @@
+mod common;
+
+use axum::response::IntoResponse;
+use common::spawn_app;
+use http_body_util::BodyExt;
+use rust_web_app::utils::error::AppError;
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Converts a query error the way `?` does in handlers and renders the response.
+async fn render(error: sqlx::Error) -> (u16, Value) {
+    let response = AppError::from(error).into_response();
+    let status = response.status().as_u16();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+async fn insert_user(db: &sqlx::PgPool, email: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO users (email, password_hash, name) VALUES ($1, 'hash', 'Jane')")
+        .bind(email)
+        .execute(db)
+        .await
+        .map(|_| ())
+}
+
+#[tokio::test]
+async fn missing_rows_become_404() {
+    let app = spawn_app().await;
+    let error = sqlx::query_scalar::<_, i32>("SELECT 1 WHERE false")
+        .fetch_one(&app.db)
+        .await
+        .unwrap_err();
+
+    let (status, body) = render(error).await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn unique_violations_become_409_naming_the_field() {
+    let app = spawn_app().await;
+    insert_user(&app.db, "jane@example.com").await.unwrap();
+    let error = insert_user(&app.db, "JANE@example.com").await.unwrap_err();
+
+    let (status, body) = render(error).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"], "CONFLICT");
+    assert_eq!(body["fields"]["email"][0], "is already in use");
+}
+
+#[tokio::test]
+async fn unique_violations_match_the_pre_check_response() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app.register("JANE@example.com", "Jane Doe").await;
+    assert_eq!(response.status(), 409);
+    let pre_check: Value = response.json().await.unwrap();
+
+    let error = insert_user(&app.db, "Jane@Example.com").await.unwrap_err();
+    let (_, race) = render(error).await;
+
+    for key in ["error", "message", "fields"] {
+        assert_eq!(pre_check[key], race[key], "{}", key);
+    }
+    assert_eq!(race["message"], "Email is already in use");
+}
+
+#[tokio::test]
+async fn foreign_key_violations_become_400() {
+    let app = spawn_app().await;
+    let error = sqlx::query(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) \
+         VALUES ($1, 'hash', NOW())",
+    )
+    .bind(Uuid::new_v4())
+    .execute(&app.db)
+    .await
+    .unwrap_err();
+
+    let (status, body) = render(error).await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"], "BAD_REQUEST");
+}
+
+#[tokio::test]
+async fn pool_timeouts_become_503() {
+    let (status, body) = render(sqlx::Error::PoolTimedOut).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["error"], "SERVICE_UNAVAILABLE");
+}
+
+#[tokio::test]
+async fn other_database_errors_are_500s_without_driver_details() {
+    let app = spawn_app().await;
+    let error = sqlx::query("SELECT * FROM no_such_table")
+        .execute(&app.db)
+        .await
+        .unwrap_err();
+
+    let (status, body) = render(error).await;
+    assert_eq!(status, 500);
+    assert_eq!(body["error"], "DATABASE_ERROR");
+    assert_eq!(body["message"], "Internal server error");
+    assert!(!body.to_string().contains("no_such_table"));
+}
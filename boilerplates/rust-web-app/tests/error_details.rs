@@ -0,0 +1,36 @@
+mod common;
+
+use common::{spawn_app_with, TestApp};
+use serde_json::Value;
+
+/// Breaks the active_users view so any lookup fails with a database error.
+async fn database_error_body(app: &TestApp) -> Value {
+    sqlx::query("ALTER VIEW active_users RENAME TO active_users_renamed")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let response = app.get("/api/users/by-handle/jane").send().await.unwrap();
+    assert_eq!(response.status(), 500);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn error_details_are_shown_in_development_and_redacted_in_production() {
+    let app = spawn_app_with(|settings| {
+        settings.application.environment = "development".to_string();
+    })
+    .await;
+    let body = database_error_body(&app).await;
+    assert_eq!(body["error"], "DATABASE_ERROR");
+    assert!(body["message"].as_str().unwrap().contains("users"));
+
+    let app = spawn_app_with(|settings| {
+        settings.application.environment = "production".to_string();
+    })
+    .await;
+    let body = database_error_body(&app).await;
+    assert_eq!(body["error"], "DATABASE_ERROR");
+    assert_eq!(body["message"], "Internal server error");
+    assert!(body["request_id"].is_string());
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::spawn_app_with;
+use rust_web_app::{config::ErrorFormat, utils::response::ApiResponse};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn problem_format_returns_rfc_7807_bodies() {
+    let app = spawn_app_with(|settings| {
+        settings.application.error_format = ErrorFormat::Problem;
+    })
+    .await;
+
+    let response = app.get("/api/does-not-exist").send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["title"], "Not Found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["detail"], "Route not found");
+    assert_eq!(body["instance"], "/api/does-not-exist");
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert!(body["request_id"].is_string());
+
+    // Extra details become extension members
+    let response = app.delete("/api/auth/login").send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["allowed_methods"], json!(["POST"]));
+}
+
+#[tokio::test]
+async fn problem_format_reports_field_errors_under_errors() {
+    let app = spawn_app_with(|settings| {
+        settings.application.error_format = ErrorFormat::Problem;
+    })
+    .await;
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "not-an-email", "password": "password123", "name": "Jane" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "VALIDATION_ERROR");
+    assert_eq!(body["instance"], "/api/auth/register");
+    assert!(body["errors"]["email"].is_array());
+    assert!(body.get("fields").is_none());
+}
+
+#[tokio::test]
+async fn envelope_format_matches_the_success_envelope() {
+    let app = spawn_app_with(|settings| {
+        settings.application.error_format = ErrorFormat::Envelope;
+    })
+    .await;
+
+    let response = app.register("jane@example.com", "Jane Doe").await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "success");
+    assert_eq!(body["success"], true);
+    assert!(body["data"]["token"].is_string());
+    assert!(body.get("error").is_none());
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "not-an-email", "password": "password123", "name": "Jane" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 422);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "error");
+    assert_eq!(body["success"], false);
+    assert!(body["data"].is_null());
+    assert_eq!(body["message"], "Request validation failed");
+    assert_eq!(body["error"]["code"], "VALIDATION_ERROR");
+    assert!(body["error"]["fields"]["email"].is_array());
+    assert!(body["error"]["request_id"].is_string());
+
+    let response = app.delete("/api/auth/login").send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["details"]["allowed_methods"], json!(["POST"]));
+}
+
+#[test]
+fn api_response_error_uses_the_envelope_shape() {
+    let body = serde_json::to_value(ApiResponse::<()>::error("CONFLICT", "Already taken")).unwrap();
+
+    assert_eq!(
+        body,
+        json!({
+            "status": "error",
+            "success": false,
+            "data": null,
+            "message": "Already taken",
+            "error": { "code": "CONFLICT", "message": "Already taken" },
+        })
+    );
+}
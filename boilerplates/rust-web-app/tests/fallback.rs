@@ -0,0 +1,45 @@
+mod common;
+
+use common::spawn_app;
+use serde_json::Value;
+
+#[tokio::test]
+async fn unknown_routes_return_a_json_404() {
+    let app = spawn_app().await;
+
+    // Metrics are only served on the internal listener
+    for path in ["/nope", "/api/nope", "/metrics"] {
+        let response = app.get(path).send().await.unwrap();
+
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], "NOT_FOUND");
+        assert!(body["message"].is_string());
+        assert!(body["request_id"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn wrong_method_returns_a_json_405_listing_allowed_methods() {
+    let app = spawn_app().await;
+
+    let response = app.post("/api/users/me").send().await.unwrap();
+
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let allow = response.headers()["allow"].to_str().unwrap().to_string();
+    for method in ["GET", "PATCH", "DELETE"] {
+        assert!(allow.contains(method), "{} missing from {}", method, allow);
+    }
+
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "METHOD_NOT_ALLOWED");
+    let allowed: Vec<&str> = body["details"]["allowed_methods"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m.as_str().unwrap())
+        .collect();
+    assert!(allowed.contains(&"PATCH"));
+}
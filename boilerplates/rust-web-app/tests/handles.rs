@@ -0,0 +1,164 @@
+mod common;
+
+use common::spawn_app;
+use rust_web_app::utils::handle::{is_reserved_handle, normalize_handle};
+use serde_json::{json, Value};
+
+#[test]
+fn normalize_handle_lowercases_and_strips_at() {
+    assert_eq!(normalize_handle("@Jane.Doe").unwrap(), "jane.doe");
+    assert_eq!(normalize_handle("  jane_doe-99 ").unwrap(), "jane_doe-99");
+}
+
+#[test]
+fn normalize_handle_rejects_invalid_input() {
+    for input in [
+        "ab",
+        &"a".repeat(31),
+        "_jane",
+        "jane.",
+        "ja..ne",
+        "ja-_ne",
+        "ja ne",
+        "jäne",
+        "јane", // Cyrillic 'ј'
+        "@@jane",
+    ] {
+        assert!(
+            normalize_handle(input).is_err(),
+            "{:?} should be rejected",
+            input
+        );
+    }
+}
+
+#[test]
+fn reserved_handles_ignore_separators() {
+    assert!(is_reserved_handle("admin", &[]));
+    assert!(is_reserved_handle("ad.min", &[]));
+    assert!(is_reserved_handle("sup_port", &[]));
+    assert!(is_reserved_handle("acme", &["Acme".to_string()]));
+    assert!(!is_reserved_handle("jane", &[]));
+}
+
+#[tokio::test]
+async fn handle_can_be_claimed_and_looked_up() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .put("/api/users/me/handle")
+        .bearer_auth(&token)
+        .json(&json!({ "handle": "@Jane" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let body: Value = app
+        .get("/api/users/by-handle/JANE")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["data"]["handle"], "jane");
+    assert!(body["data"].get("email").is_none());
+    assert!(body["data"].get("is_self").is_none());
+}
+
+#[tokio::test]
+async fn profile_lookup_is_personalized_only_for_valid_tokens() {
+    let app = spawn_app().await;
+    let jane = app.register_user("jane@example.com").await;
+    let john = app.register_user("john@example.com").await;
+    let response = app
+        .put("/api/users/me/handle")
+        .bearer_auth(&jane)
+        .json(&json!({ "handle": "jane" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let app = &app;
+    let is_self = |token: String| async move {
+        let response = app
+            .get("/api/users/by-handle/jane")
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        body["data"]["is_self"].clone()
+    };
+    assert_eq!(is_self(jane).await, true);
+    assert_eq!(is_self(john).await, false);
+
+    // A bad token is an error, not an anonymous request
+    let response = app
+        .get("/api/users/by-handle/jane")
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn released_handle_is_quarantined() {
+    let app = spawn_app().await;
+    let jane = app.register_user("jane@example.com").await;
+    let mallory = app.register_user("mallory@example.com").await;
+    let claim = |token: String, handle: &'static str| {
+        app.put("/api/users/me/handle")
+            .bearer_auth(token)
+            .json(&json!({ "handle": handle }))
+            .send()
+    };
+
+    assert_eq!(claim(jane.clone(), "jane").await.unwrap().status(), 200);
+    // Changing again straight away is rate limited
+    assert_eq!(claim(jane.clone(), "jane2").await.unwrap().status(), 429);
+
+    sqlx::query("UPDATE users SET handle_changed_at = NOW() - interval '31 days'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(claim(jane, "jane2").await.unwrap().status(), 200);
+    assert_eq!(claim(mallory, "jane").await.unwrap().status(), 409);
+}
+
+#[tokio::test]
+async fn concurrent_claims_of_the_same_handle_have_one_winner() {
+    let app = spawn_app().await;
+    let mut tokens = Vec::new();
+    for i in 0..5 {
+        tokens.push(app.register_user(&format!("user{}@example.com", i)).await);
+    }
+
+    let claims = tokens.iter().map(|token| {
+        app.put("/api/users/me/handle")
+            .bearer_auth(token)
+            .json(&json!({ "handle": "popular" }))
+            .send()
+    });
+    let statuses: Vec<u16> = statuses_of(claims).await;
+
+    assert_eq!(statuses.iter().filter(|s| **s == 200).count(), 1);
+    assert_eq!(statuses.iter().filter(|s| **s == 409).count(), 4);
+}
+
+async fn statuses_of<F>(requests: impl Iterator<Item = F>) -> Vec<u16>
+where
+    F: std::future::Future<Output = reqwest::Result<reqwest::Response>> + Send + 'static,
+{
+    let handles: Vec<_> = requests.map(tokio::spawn).collect();
+    let mut statuses = Vec::new();
+    for handle in handles {
+        statuses.push(handle.await.unwrap().unwrap().status().as_u16());
+    }
+    statuses
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with};
+use serde_json::Value;
+
+#[tokio::test]
+async fn liveness_and_readiness_succeed_with_a_healthy_database() {
+    let app = spawn_app().await;
+
+    for path in ["/health", "/health/live"] {
+        let response = app.get(path).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["status"], "healthy");
+        assert!(!body["commit"].as_str().unwrap().is_empty());
+        let built_at = body["built_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(built_at).is_ok());
+        assert!(body["uptime_seconds"].is_u64());
+    }
+
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["database"], "connected");
+    let checks = &body["checks"];
+    for check in ["database", "migrations", "cache"] {
+        assert_eq!(checks[check]["status"], "ok", "{} check failed", check);
+        assert!(checks[check]["latency_ms"].is_u64());
+    }
+    assert!(checks["migrations"]["applied"].as_u64().unwrap() > 0);
+    assert_eq!(checks["migrations"]["pending"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn readiness_returns_503_while_migrations_are_pending() {
+    let app = spawn_app().await;
+    let latest: i64 = sqlx::query_scalar(
+        "DELETE FROM _sqlx_migrations \
+         WHERE version = (SELECT max(version) FROM _sqlx_migrations) RETURNING version",
+    )
+    .fetch_one(&app.db)
+    .await
+    .unwrap();
+
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 503);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["database"], "connected");
+    let migrations = &body["checks"]["migrations"];
+    assert_eq!(migrations["status"], "error");
+    assert_eq!(migrations["pending"], serde_json::json!([latest]));
+    assert!(migrations["error"].is_string());
+}
+
+#[tokio::test]
+async fn readiness_returns_503_when_the_database_is_unreachable() {
+    let app = spawn_app().await;
+    app.db.close().await;
+
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 503);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "not_ready");
+    assert_eq!(body["database"], "disconnected");
+    assert_eq!(body["checks"]["database"]["status"], "error");
+    assert!(body["checks"]["database"]["error"].is_string());
+    assert_eq!(body["checks"]["migrations"]["status"], "skipped");
+
+    // Liveness never touches the database
+    let response = app.get("/health/live").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn readiness_times_out_instead_of_hanging() {
+    let app = spawn_app_with(|settings| {
+        settings.database.max_connections = 1;
+        settings.database.readiness_timeout_ms = 200;
+    })
+    .await;
+    // Hold the only connection so the check can't get one
+    let _conn = app.db.acquire().await.unwrap();
+
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 503);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["database"], "timeout");
+    assert_eq!(body["checks"]["database"]["status"], "timeout");
+}
+
+#[tokio::test]
+async fn readiness_checks_the_replica_when_one_is_configured() {
+    let app = spawn_app().await;
+    let body: Value = app
+        .get("/health/ready")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["checks"].get("replica").is_none());
+
+    let app = spawn_app_with(|settings| {
+        settings.database.replica_url = Some(settings.database.url.clone());
+    })
+    .await;
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["checks"]["replica"]["status"], "ok");
+
+    let app = spawn_app_with(|settings| {
+        settings.database.replica_url = Some("postgres://postgres@127.0.0.1:1/app".to_string());
+        settings.database.readiness_timeout_ms = 200;
+    })
+    .await;
+    let response = app.get("/health/ready").send().await.unwrap();
+    assert_eq!(response.status(), 503);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["database"], "connected");
+    assert_ne!(body["checks"]["replica"]["status"], "ok");
+}
@@ -0,0 +1,337 @@
+mod common;
+
+use chrono::Utc;
+use common::{spawn_app, spawn_app_with, TestApp};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rust_web_app::{
+    config::{ApplicationSettings, JwtAlgorithm, Settings},
+    utils::auth::JwtKeys,
+};
+use serde_json::{json, Value};
+
+const ISSUER: &str = "https://auth.example.com";
+const AUDIENCE: &str = "rust-web-app";
+
+async fn spawn_app_with_claims() -> TestApp {
+    spawn_app_with(|settings| {
+        settings.application.jwt_issuer = Some(ISSUER.to_string());
+        settings.application.jwt_audience = Some(AUDIENCE.to_string());
+    })
+    .await
+}
+
+/// Settings signing with the test app's secret but the given `iss`/`aud`.
+fn signer(issuer: Option<&str>, audience: Option<&str>) -> ApplicationSettings {
+    let mut settings = Settings::new().unwrap().application;
+    settings.jwt_issuer = issuer.map(str::to_string);
+    settings.jwt_audience = audience.map(str::to_string);
+    settings
+}
+
+/// A token for `user_id` signed as `settings` would sign it.
+fn create_jwt(user_id: &str, settings: &ApplicationSettings) -> String {
+    JwtKeys::new(settings).unwrap().create_jwt(user_id).unwrap()
+}
+
+async fn user_id(app: &TestApp, token: &str) -> String {
+    let body: Value = app
+        .get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["data"]["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn tokens_issued_by_the_app_carry_the_configured_claims() {
+    let app = spawn_app_with_claims().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn tokens_for_another_issuer_or_audience_are_rejected() {
+    let app = spawn_app_with_claims().await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id = user_id(&app, &token).await;
+
+    for (settings, message) in [
+        (
+            signer(Some("https://other.example.com"), Some(AUDIENCE)),
+            "Token has the wrong issuer",
+        ),
+        (
+            signer(Some(ISSUER), Some("other-service")),
+            "Token has the wrong audience",
+        ),
+    ] {
+        let token = create_jwt(&user_id, &settings);
+        let response = app
+            .get("/api/users/me")
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 401);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["message"], message);
+    }
+}
+
+#[tokio::test]
+async fn tokens_missing_configured_claims_are_rejected() {
+    let app = spawn_app_with_claims().await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id = user_id(&app, &token).await;
+
+    for (settings, message) in [
+        (
+            signer(None, Some(AUDIENCE)),
+            "Token is missing the iss claim",
+        ),
+        (signer(Some(ISSUER), None), "Token is missing the aud claim"),
+    ] {
+        let token = create_jwt(&user_id, &settings);
+        let response = app
+            .get("/api/users/me")
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 401);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["message"], message);
+    }
+}
+
+#[tokio::test]
+async fn expired_tokens_are_accepted_only_within_the_leeway() {
+    let app = spawn_app_with(|settings| settings.application.jwt_leeway_secs = 60).await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id = user_id(&app, &token).await;
+
+    let expired_by = |secs: i64| {
+        let mut settings = Settings::new().unwrap().application;
+        settings.jwt_expiration = -secs;
+        create_jwt(&user_id, &settings)
+    };
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(expired_by(30))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(expired_by(120))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Token has expired");
+}
+
+const NEW_SECRET: &str = "new-secret-that-is-at-least-32-characters";
+const OLD_SECRET: &str = "old-secret-that-is-at-least-32-characters";
+
+/// Settings signing with `secret` alone.
+fn signed_with(secret: &str) -> ApplicationSettings {
+    let mut settings = Settings::new().unwrap().application;
+    settings.jwt_secret = secret.to_string();
+    settings
+}
+
+async fn me_status(app: &TestApp, token: &str) -> u16 {
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    response.status().as_u16()
+}
+
+#[tokio::test]
+async fn tokens_signed_with_any_configured_secret_are_accepted_during_rotation() {
+    let app = spawn_app_with(|settings| {
+        settings.application.jwt_secrets = vec![NEW_SECRET.to_string(), OLD_SECRET.to_string()];
+    })
+    .await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id = user_id(&app, &token).await;
+
+    // New tokens are signed with the first secret
+    let header = jsonwebtoken::decode_header(&token).unwrap();
+    let new_token = create_jwt(&user_id, &signed_with(NEW_SECRET));
+    assert_eq!(
+        header.kid,
+        jsonwebtoken::decode_header(&new_token).unwrap().kid
+    );
+
+    let old_token = create_jwt(&user_id, &signed_with(OLD_SECRET));
+    assert_eq!(me_status(&app, &old_token).await, 200);
+
+    // Tokens issued before key ids were added carry no kid
+    let claims = json!({ "sub": user_id, "iat": Utc::now().timestamp(), "exp": Utc::now().timestamp() + 60 });
+    let legacy_token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(OLD_SECRET.as_bytes()),
+    )
+    .unwrap();
+    assert_eq!(me_status(&app, &legacy_token).await, 200);
+
+    let unknown_token = create_jwt(&user_id, &signed_with("some-other-secret"));
+    assert_eq!(me_status(&app, &unknown_token).await, 401);
+}
+
+#[tokio::test]
+async fn tokens_signed_with_a_retired_secret_are_rejected() {
+    let app = spawn_app_with(|settings| {
+        settings.application.jwt_secrets = vec![NEW_SECRET.to_string()];
+    })
+    .await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id = user_id(&app, &token).await;
+
+    let old_token = create_jwt(&user_id, &signed_with(OLD_SECRET));
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&old_token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Token was signed with an unknown key");
+}
+
+#[test]
+fn production_checks_every_rotation_secret() {
+    let mut settings = Settings::new().unwrap();
+    settings.application.environment = "production".to_string();
+    settings.application.jwt_secrets = vec![NEW_SECRET.to_string(), OLD_SECRET.to_string()];
+    assert!(settings.validate().is_ok());
+
+    settings.application.jwt_secrets.push("short".to_string());
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("application.jwt_secrets"), "{}", error);
+}
+
+/// Points `settings` at a test key pair for `algorithm`.
+fn use_key_pair(settings: &mut ApplicationSettings, algorithm: JwtAlgorithm) {
+    let (private, public) = match algorithm {
+        JwtAlgorithm::RS256 => (
+            include_str!("fixtures/jwt_rs256_private.pem"),
+            include_str!("fixtures/jwt_rs256_public.pem"),
+        ),
+        _ => (
+            include_str!("fixtures/jwt_es256_private.pem"),
+            include_str!("fixtures/jwt_es256_public.pem"),
+        ),
+    };
+    settings.jwt_algorithm = algorithm;
+    settings.jwt_private_key_path = Some("private.pem".to_string());
+    settings.jwt_public_key_path = Some("public.pem".to_string());
+    settings.jwt_private_key = Some(private.to_string());
+    settings.jwt_public_key = Some(public.to_string());
+}
+
+#[tokio::test]
+async fn asymmetric_tokens_verify_against_the_published_jwks() {
+    for (algorithm, jwt_algorithm) in [
+        (JwtAlgorithm::RS256, Algorithm::RS256),
+        (JwtAlgorithm::ES256, Algorithm::ES256),
+    ] {
+        let app =
+            spawn_app_with(|settings| use_key_pair(&mut settings.application, algorithm)).await;
+        let token = app.register_user("jane@example.com").await;
+        let user_id = user_id(&app, &token).await;
+        let header = jsonwebtoken::decode_header(&token).unwrap();
+        assert_eq!(header.alg, jwt_algorithm);
+
+        let jwks: JwkSet = app
+            .get("/api/auth/jwks.json")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(jwks.keys.len(), 1);
+        let jwk = &jwks.keys[0];
+        assert_eq!(jwk.common.key_id, header.kid);
+
+        // Another service can verify the token with nothing but the JWKS
+        let key = DecodingKey::from_jwk(jwk).unwrap();
+        let claims = jsonwebtoken::decode::<Value>(&token, &key, &Validation::new(jwt_algorithm))
+            .unwrap()
+            .claims;
+        assert_eq!(claims["sub"], user_id);
+
+        // An HMAC token can't stand in for the configured algorithm
+        let hmac_token = create_jwt(&user_id, &signed_with(NEW_SECRET));
+        assert_eq!(me_status(&app, &hmac_token).await, 401);
+    }
+}
+
+#[tokio::test]
+async fn jwks_is_empty_for_shared_secrets() {
+    let app = spawn_app().await;
+
+    let jwks: JwkSet = app
+        .get("/api/auth/jwks.json")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert!(jwks.keys.is_empty());
+}
+
+#[test]
+fn misconfigured_key_pairs_fail_validation() {
+    let mut settings = Settings::new().unwrap();
+    use_key_pair(&mut settings.application, JwtAlgorithm::ES256);
+    assert!(settings.validate().is_ok());
+
+    let mut missing = settings.clone();
+    missing.application.jwt_public_key = None;
+    let error = missing.validate().unwrap_err().to_string();
+    assert!(
+        error.contains("application.jwt_public_key_path"),
+        "{}",
+        error
+    );
+
+    let mut mismatched = settings.clone();
+    mismatched.application.jwt_public_key =
+        Some(include_str!("fixtures/jwt_es256_other_public.pem").to_string());
+    let error = mismatched.validate().unwrap_err().to_string();
+    assert!(error.contains("does not match"), "{}", error);
+
+    let mut wrong_type = settings;
+    wrong_type.application.jwt_algorithm = JwtAlgorithm::RS256;
+    assert!(wrong_type.validate().is_err());
+}
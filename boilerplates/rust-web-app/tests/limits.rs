@@ -0,0 +1,84 @@
+mod common;
+
+use common::spawn_app_with;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[tokio::test]
+async fn oversized_bodies_are_rejected_with_a_json_413() {
+    let app = spawn_app_with(|settings| settings.server.max_body_bytes = 1024).await;
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({
+            "email": "jane@example.com",
+            "password": common::PASSWORD,
+            "name": "x".repeat(2048),
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 413);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "PAYLOAD_TOO_LARGE");
+    assert!(body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn slow_requests_time_out_with_a_json_504() {
+    let app = spawn_app_with(|settings| settings.server.request_timeout_secs = 1).await;
+
+    // Hold a lock on the users table so registration blocks until it times out
+    let mut tx = app.db.begin().await.unwrap();
+    sqlx::query("LOCK TABLE users IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+    let response = app.register("jane@example.com", "Jane Doe").await;
+
+    assert_eq!(response.status(), 504);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "GATEWAY_TIMEOUT");
+    assert!(body["request_id"].is_string());
+
+    // Health checks are exempt from the timeout
+    let response = app.get("/health").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    tx.rollback().await.unwrap();
+}
+
+#[tokio::test]
+async fn streamed_bodies_without_a_content_length_are_also_limited() {
+    let app = spawn_app_with(|settings| settings.server.max_body_bytes = 1024).await;
+    let address = app.address.trim_start_matches("http://");
+
+    // A chunked body can't be rejected up front from its Content-Length, so the limit has
+    // to apply while it is read. Asking for gzip checks the 413 survives compression.
+    let chunk = "x".repeat(4096);
+    let request = format!(
+        "POST /api/auth/register HTTP/1.1\r\nHost: {address}\r\n\
+         Content-Type: application/json\r\nAccept-Encoding: gzip\r\n\
+         Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+         {:x}\r\n{chunk}\r\n0\r\n\r\n",
+        chunk.len(),
+    );
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 413"),
+        "unexpected response: {}",
+        response.lines().next().unwrap_or_default()
+    );
+}
@@ -0,0 +1,86 @@
+use rust_web_app::{
+    config::{SmtpSettings, SmtpTls},
+    utils::mailer::{Mailer, SmtpMailer},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+fn settings(port: u16, from: &str) -> SmtpSettings {
+    SmtpSettings {
+        host: Some("127.0.0.1".to_string()),
+        port,
+        username: None,
+        password: None,
+        from: from.to_string(),
+        tls: SmtpTls::None,
+    }
+}
+
+/// Accepts one SMTP session, answering every command with success, and returns
+/// the transcript of what the client sent.
+async fn fake_smtp_server(listener: TcpListener) -> String {
+    let (stream, _) = listener.accept().await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut transcript = String::new();
+    let mut in_data = false;
+
+    writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        transcript.push_str(&line);
+        transcript.push('\n');
+
+        let reply: &[u8] = if in_data {
+            if line != "." {
+                continue;
+            }
+            in_data = false;
+            b"250 Queued\r\n"
+        } else if line.starts_with("DATA") {
+            in_data = true;
+            b"354 End data with <CR><LF>.<CR><LF>\r\n"
+        } else if line.starts_with("QUIT") {
+            writer.write_all(b"221 Bye\r\n").await.unwrap();
+            break;
+        } else {
+            b"250 OK\r\n"
+        };
+        writer.write_all(reply).await.unwrap();
+    }
+
+    transcript
+}
+
+#[tokio::test]
+async fn smtp_mailer_delivers_the_message_to_the_relay() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(fake_smtp_server(listener));
+
+    let mailer =
+        SmtpMailer::new("127.0.0.1", &settings(port, "App <no-reply@example.com>")).unwrap();
+    mailer
+        .send(
+            "jane@example.com",
+            "Reset your password",
+            "Your token is abc123",
+        )
+        .await
+        .unwrap();
+
+    let transcript = server.await.unwrap();
+    assert!(transcript.contains("MAIL FROM:<no-reply@example.com>"));
+    assert!(transcript.contains("RCPT TO:<jane@example.com>"));
+    assert!(transcript.contains("Subject: Reset your password"));
+    assert!(transcript.contains("Your token is abc123"));
+}
+
+#[tokio::test]
+async fn smtp_mailer_rejects_an_invalid_from_address() {
+    let result = SmtpMailer::new("127.0.0.1", &settings(2525, "not an address"));
+
+    let error = result.err().expect("invalid from address was accepted");
+    assert!(error.to_string().contains("smtp.from"));
+}
@@ -0,0 +1,442 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{spawn_app_with, TestApp, PASSWORD};
+use reqwest::{redirect, Url};
+use rust_web_app::config::{OAuthProviderSettings, Settings};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+
+/// Authorization codes the fake provider has handed out, with the PKCE challenge each
+/// was issued for and the profile it signs in as.
+type Grants = Arc<Mutex<HashMap<String, (String, Value)>>>;
+
+/// A stand-in for Google and GitHub: tokens are the codes they were exchanged for, and
+/// the profile endpoints serve whatever was granted for the code.
+struct FakeProvider {
+    url: String,
+    grants: Grants,
+}
+
+async fn token(
+    State(grants): State<Grants>,
+    Form(form): Form<HashMap<String, String>>,
+) -> (StatusCode, Json<Value>) {
+    let grants = grants.lock().unwrap();
+    let verified = match (grants.get(&form["code"]), form.get("code_verifier")) {
+        (Some((challenge, _)), Some(verifier)) => {
+            URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == *challenge
+        }
+        _ => false,
+    };
+    if verified {
+        (
+            StatusCode::OK,
+            Json(json!({ "access_token": form["code"] })),
+        )
+    } else {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    }
+}
+
+fn profile(grants: &Grants, headers: &HeaderMap) -> Value {
+    let token = headers["authorization"].to_str().unwrap();
+    let token = token.trim_start_matches("Bearer ");
+    grants.lock().unwrap()[token].1.clone()
+}
+
+async fn spawn_provider() -> FakeProvider {
+    let grants = Grants::default();
+    let app = Router::new()
+        .route("/token", post(token))
+        .route(
+            "/userinfo",
+            get(
+                |State(grants): State<Grants>, headers: HeaderMap| async move {
+                    Json(profile(&grants, &headers))
+                },
+            ),
+        )
+        .route(
+            "/user",
+            get(
+                |State(grants): State<Grants>, headers: HeaderMap| async move {
+                    Json(profile(&grants, &headers)["user"].clone())
+                },
+            ),
+        )
+        .route(
+            "/user/emails",
+            get(
+                |State(grants): State<Grants>, headers: HeaderMap| async move {
+                    Json(profile(&grants, &headers)["emails"].clone())
+                },
+            ),
+        )
+        .with_state(grants.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    FakeProvider { url, grants }
+}
+
+fn provider_settings(provider: &FakeProvider, name: &str) -> OAuthProviderSettings {
+    OAuthProviderSettings {
+        client_id: format!("{}-client", name),
+        client_secret: "client-secret".to_string(),
+        redirect_url: format!("http://localhost/api/auth/oauth/{}/callback", name),
+        authorize_url: Some(format!("{}/authorize", provider.url)),
+        token_url: Some(format!("{}/token", provider.url)),
+        api_url: Some(provider.url.clone()),
+    }
+}
+
+async fn spawn_app_with_google() -> (TestApp, FakeProvider) {
+    let provider = spawn_provider().await;
+    let google = provider_settings(&provider, "google");
+    let app = spawn_app_with(|settings| settings.oauth.google = Some(google)).await;
+    (app, provider)
+}
+
+/// Starts a login, returning the redirect to the provider.
+async fn start(app: &TestApp, provider: &str) -> reqwest::Response {
+    let client = reqwest::Client::builder()
+        .redirect(redirect::Policy::none())
+        .build()
+        .unwrap();
+    client
+        .get(format!("{}/api/auth/oauth/{}/start", app.address, provider))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The query parameters of the redirect to the provider.
+fn redirect_params(response: &reqwest::Response) -> HashMap<String, String> {
+    let location = response.headers()["location"].to_str().unwrap();
+    Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+async fn callback(app: &TestApp, provider: &str, state: &str, code: &str) -> reqwest::Response {
+    app.get(&format!("/api/auth/oauth/{}/callback", provider))
+        .query(&[("state", state), ("code", code)])
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Goes through a whole login as the user the provider describes with `profile`.
+async fn sign_in(
+    app: &TestApp,
+    fake: &FakeProvider,
+    provider: &str,
+    profile: Value,
+) -> reqwest::Response {
+    let params = redirect_params(&start(app, provider).await);
+    let code = format!("code-{}", fake.grants.lock().unwrap().len());
+    fake.grants
+        .lock()
+        .unwrap()
+        .insert(code.clone(), (params["code_challenge"].clone(), profile));
+    callback(app, provider, &params["state"], &code).await
+}
+
+fn google_profile(sub: &str, email: &str, verified: bool) -> Value {
+    json!({ "sub": sub, "email": email, "email_verified": verified, "name": "Jane Doe" })
+}
+
+#[tokio::test]
+async fn start_redirects_to_the_provider() {
+    let (app, fake) = spawn_app_with_google().await;
+
+    let response = start(&app, "google").await;
+    assert_eq!(response.status(), 303);
+    let location = response.headers()["location"].to_str().unwrap();
+    assert!(location.starts_with(&format!("{}/authorize?", fake.url)));
+
+    let params = redirect_params(&response);
+    assert_eq!(params["client_id"], "google-client");
+    assert_eq!(params["response_type"], "code");
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert!(!params["state"].is_empty());
+
+    // Only the hash of the state is stored
+    let stored: String = sqlx::query_scalar("SELECT state_hash FROM oauth_states")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_ne!(stored, params["state"]);
+}
+
+#[tokio::test]
+async fn first_login_creates_a_password_less_user() {
+    let (app, fake) = spawn_app_with_google().await;
+
+    let response = sign_in(
+        &app,
+        &fake,
+        "google",
+        google_profile("g-1", "jane@example.com", true),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["token"].is_string());
+    assert_eq!(body["data"]["user"]["email"], "jane@example.com");
+    assert_eq!(body["data"]["user"]["name"], "Jane Doe");
+    assert_eq!(body["data"]["user"]["email_verified"], true);
+
+    let token = body["data"]["token"].as_str().unwrap();
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let hash: Option<String> = sqlx::query_scalar("SELECT password_hash FROM users")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(hash, None);
+
+    // Without a password, password logins fail like a wrong password would
+    let response = app.login("jane@example.com", PASSWORD).await;
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Invalid credentials");
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": "newpassword123" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn later_logins_reuse_the_linked_account() {
+    let (app, fake) = spawn_app_with_google().await;
+
+    let first: Value = sign_in(
+        &app,
+        &fake,
+        "google",
+        google_profile("g-1", "jane@example.com", true),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    // Matched by the provider's id, even after the email changed there
+    let second: Value = sign_in(
+        &app,
+        &fake,
+        "google",
+        google_profile("g-1", "jane@work.example", true),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(first["data"]["user"]["id"], second["data"]["user"]["id"]);
+    assert_eq!(second["data"]["user"]["email"], "jane@example.com");
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+}
+
+#[tokio::test]
+async fn verified_emails_link_to_existing_accounts() {
+    let (app, fake) = spawn_app_with_google().await;
+    let registered: Value = app
+        .register("jane@example.com", "Jane")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Unverified provider emails are never trusted for linking
+    let response = sign_in(
+        &app,
+        &fake,
+        "google",
+        google_profile("g-1", "jane@example.com", false),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+
+    let response = sign_in(
+        &app,
+        &fake,
+        "google",
+        google_profile("g-1", "JANE@example.com", true),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["user"]["id"], registered["data"]["user"]["id"]);
+    assert_eq!(body["data"]["user"]["email_verified"], true);
+
+    // The password keeps working alongside the provider
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+}
+
+#[tokio::test]
+async fn tampered_and_reused_states_are_rejected() {
+    let (app, fake) = spawn_app_with_google().await;
+
+    let params = redirect_params(&start(&app, "google").await);
+    let code = "code-1";
+    fake.grants.lock().unwrap().insert(
+        code.to_string(),
+        (
+            params["code_challenge"].clone(),
+            google_profile("g-1", "jane@example.com", true),
+        ),
+    );
+
+    let tampered = format!("{}0", &params["state"][1..]);
+    for state in [tampered.as_str(), ""] {
+        let response = callback(&app, "google", state, code).await;
+        assert_eq!(response.status(), 400, "{}", state);
+    }
+
+    assert_eq!(
+        callback(&app, "google", &params["state"], code)
+            .await
+            .status(),
+        200
+    );
+    assert_eq!(
+        callback(&app, "google", &params["state"], code)
+            .await
+            .status(),
+        400
+    );
+
+    // Expired states are rejected too
+    let params = redirect_params(&start(&app, "google").await);
+    sqlx::query("UPDATE oauth_states SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(
+        callback(&app, "google", &params["state"], code)
+            .await
+            .status(),
+        400
+    );
+}
+
+#[tokio::test]
+async fn rejected_codes_and_declined_logins_are_bad_requests() {
+    let (app, _fake) = spawn_app_with_google().await;
+
+    // The fake provider never granted this code
+    let params = redirect_params(&start(&app, "google").await);
+    let response = callback(&app, "google", &params["state"], "unknown-code").await;
+    assert_eq!(response.status(), 400);
+
+    let params = redirect_params(&start(&app, "google").await);
+    let response = app
+        .get("/api/auth/oauth/google/callback")
+        .query(&[
+            ("state", params["state"].as_str()),
+            ("error", "access_denied"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["message"].as_str().unwrap().contains("access_denied"));
+}
+
+#[tokio::test]
+async fn unknown_and_unconfigured_providers_are_not_found() {
+    let (app, _fake) = spawn_app_with_google().await;
+
+    for provider in ["facebook", "github"] {
+        assert_eq!(start(&app, provider).await.status(), 404, "{}", provider);
+        let response = callback(&app, provider, "state", "code").await;
+        assert_eq!(response.status(), 404, "{}", provider);
+    }
+}
+
+#[tokio::test]
+async fn github_logins_use_the_primary_verified_email() {
+    let fake = spawn_provider().await;
+    let github = provider_settings(&fake, "github");
+    let app = spawn_app_with(|settings| settings.oauth.github = Some(github)).await;
+
+    let profile = json!({
+        "user": { "id": 42, "login": "jdoe", "name": null },
+        "emails": [
+            { "email": "old@example.com", "primary": false, "verified": true },
+            { "email": "jane@example.com", "primary": true, "verified": true },
+        ],
+    });
+    let response = sign_in(&app, &fake, "github", profile).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["user"]["email"], "jane@example.com");
+    assert_eq!(body["data"]["user"]["name"], "jdoe");
+
+    let linked: String = sqlx::query_scalar("SELECT provider_user_id FROM oauth_accounts")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(linked, "42");
+
+    let unverified = json!({
+        "user": { "id": 43, "login": "jsmith", "name": "John Smith" },
+        "emails": [{ "email": "john@example.com", "primary": true, "verified": false }],
+    });
+    assert_eq!(
+        sign_in(&app, &fake, "github", unverified).await.status(),
+        400
+    );
+}
+
+#[test]
+fn provider_urls_must_be_absolute() {
+    let mut settings = Settings::new().unwrap();
+    settings.oauth.google = Some(OAuthProviderSettings {
+        client_id: "client".to_string(),
+        client_secret: "secret".to_string(),
+        redirect_url: "/api/auth/oauth/google/callback".to_string(),
+        authorize_url: None,
+        token_url: None,
+        api_url: None,
+    });
+
+    let error = settings.validate().unwrap_err().to_string();
+    assert!(error.contains("oauth.google"), "{}", error);
+}
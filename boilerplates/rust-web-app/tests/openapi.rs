@@ -0,0 +1,27 @@
+//! Guards the generated OpenAPI spec against silent drift from the handlers.
+//! Regenerate the snapshot with `UPDATE_SNAPSHOTS=1 cargo test --test openapi` and review
+//! the diff.
+
+use rust_web_app::routes::ApiDoc;
+use utoipa::OpenApi;
+
+const SNAPSHOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/openapi.json");
+
+#[test]
+fn openapi_spec_matches_snapshot() {
+    let spec = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("OpenAPI spec should serialize");
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(SNAPSHOT, format!("{}\n", spec)).expect("failed to write snapshot");
+        return;
+    }
+
+    let expected = std::fs::read_to_string(SNAPSHOT).expect("failed to read snapshot");
+    assert_eq!(
+        expected.trim_end(),
+        spec,
+        "OpenAPI spec changed; rerun with UPDATE_SNAPSHOTS=1 and review the diff"
+    );
+}
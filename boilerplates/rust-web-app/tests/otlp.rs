@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{body::Bytes, extract::State, routing::post, Router};
+use opentelemetry::trace::TracerProvider;
+use rust_web_app::{
+    config::TelemetrySettings,
+    telemetry::{init_tracer, TracerGuard},
+};
+use tokio::net::TcpListener;
+use tracing_subscriber::layer::SubscriberExt;
+
+type Received = Arc<Mutex<Vec<Bytes>>>;
+
+/// Accepts OTLP/HTTP trace exports and keeps their bodies.
+async fn spawn_collector() -> (String, Received) {
+    let received = Received::default();
+    let app = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(received): State<Received>, body: Bytes| async move {
+                received.lock().unwrap().push(body);
+            }),
+        )
+        .with_state(received.clone());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (url, received)
+}
+
+fn emit_span(guard: &TracerGuard) {
+    let tracer = guard.provider().tracer("test");
+    let subscriber =
+        tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("checkout_order").in_scope(|| tracing::info!("inside"));
+    });
+}
+
+#[tokio::test]
+async fn nothing_is_exported_without_an_endpoint() {
+    let settings = TelemetrySettings {
+        otlp_endpoint: None,
+        service_name: "rust-web-app".to_string(),
+    };
+    assert!(init_tracer(&settings).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn spans_are_flushed_to_the_collector_when_the_guard_is_dropped() {
+    let (url, received) = spawn_collector().await;
+    let settings = TelemetrySettings {
+        otlp_endpoint: Some(format!("{}/", url)),
+        service_name: "checkout-service".to_string(),
+    };
+    let guard = init_tracer(&settings).unwrap().unwrap();
+
+    emit_span(&guard);
+    // Spans are batched, so nothing has necessarily been sent yet. Dropping the guard
+    // blocks until the batch is exported, which needs the collector on this runtime.
+    tokio::task::spawn_blocking(move || drop(guard))
+        .await
+        .unwrap();
+
+    let received = received.lock().unwrap();
+    let body: Vec<u8> = received.iter().flatten().copied().collect();
+    let contains = |needle: &str| body.windows(needle.len()).any(|w| w == needle.as_bytes());
+    assert!(contains("checkout_order"));
+    assert!(contains("checkout-service"));
+}
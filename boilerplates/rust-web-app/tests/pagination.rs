@@ -0,0 +1,129 @@
+mod common;
+
+use common::spawn_app;
+use serde_json::Value;
+
+#[tokio::test]
+async fn cursor_pagination_walks_ties_without_gaps_or_duplicates() {
+    let app = spawn_app().await;
+    let token = app.register_admin("admin@example.com").await;
+
+    // Five users sharing a created_at force the id tiebreaker to do the work
+    sqlx::query(
+        "INSERT INTO users (email, password_hash, name, created_at) \
+         SELECT 'tie' || g || '@example.com', 'x', 'Tie', '2020-01-01T00:00:00Z' \
+         FROM generate_series(1, 5) g",
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    let mut seen = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut path = "/api/admin/users/cursor?limit=2".to_string();
+        if let Some(cursor) = &cursor {
+            path.push_str(&format!("&cursor={}", cursor));
+        }
+        let body: Value = app
+            .get(&path)
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        for item in body["data"]["items"].as_array().unwrap() {
+            seen.push(item["email"].as_str().unwrap().to_string());
+        }
+        match body["data"]["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => {
+                assert_eq!(body["data"]["has_more"], false);
+                break;
+            }
+        }
+    }
+
+    let mut unique = seen.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(seen.len(), 6);
+    assert_eq!(unique.len(), 6);
+}
+
+#[tokio::test]
+async fn garbage_cursor_is_a_bad_request() {
+    let app = spawn_app().await;
+    let token = app.register_admin("admin@example.com").await;
+
+    for cursor in ["garbage!!", "Zm9v", ""] {
+        let response = app
+            .get(&format!("/api/admin/users/cursor?cursor={}", cursor))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "cursor {:?}", cursor);
+    }
+}
+
+#[tokio::test]
+async fn offset_listing_reports_totals() {
+    let app = spawn_app().await;
+    let token = app.register_admin("admin@example.com").await;
+    for i in 0..3 {
+        app.register_user(&format!("user{}@example.com", i)).await;
+    }
+
+    let body: Value = app
+        .get("/api/admin/users?per_page=2&sort=email&order=asc")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["data"]["total"], 4);
+    assert_eq!(body["data"]["total_pages"], 2);
+    assert_eq!(body["data"]["items"][0]["email"], "admin@example.com");
+}
+
+#[tokio::test]
+async fn offset_listing_includes_meta() {
+    let app = spawn_app().await;
+    let token = app.register_admin("admin@example.com").await;
+    app.register_user("user@example.com").await;
+
+    let body: Value = app
+        .get("/api/admin/users?page=2&per_page=1")
+        .bearer_auth(&token)
+        .header("X-Request-Id", "list-users-1")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["meta"]["total"], 2);
+    assert_eq!(body["meta"]["page"], 2);
+    assert_eq!(body["meta"]["per_page"], 1);
+    assert_eq!(body["meta"]["request_id"], "list-users-1");
+
+    // Single-item responses have no meta
+    let body: Value = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body.get("meta").is_none());
+}
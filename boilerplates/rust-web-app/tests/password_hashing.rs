@@ -0,0 +1,149 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use rust_web_app::{
+    config::{PasswordAlgorithm, Settings},
+    utils::auth::hash_password,
+};
+use serde_json::json;
+use tokio::task::JoinSet;
+
+async fn stored_hash(app: &TestApp, email: &str) -> String {
+    sqlx::query_scalar("SELECT password_hash FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+}
+
+async fn set_hash(app: &TestApp, email: &str, hash: &str) {
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE email = $2")
+        .bind(hash)
+        .bind(email)
+        .execute(&app.db)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn health_stays_responsive_while_registrations_hash() {
+    // Enough hashing work that running it on the runtime would stall it for seconds
+    let app = spawn_app_with(|settings| settings.application.argon2_memory_kib = 8192).await;
+
+    let started = Instant::now();
+    let mut registrations = JoinSet::new();
+    for i in 0..50 {
+        let email = format!("user{}@example.com", i);
+        registrations.spawn(
+            app.post("/api/auth/register")
+                .json(&json!({ "email": email, "password": PASSWORD, "name": "Load Test" }))
+                .send(),
+        );
+    }
+
+    let probes = async {
+        let mut slowest = Duration::ZERO;
+        for _ in 0..20 {
+            let started = Instant::now();
+            let response = app.get("/health/live").send().await.unwrap();
+            assert_eq!(response.status(), 200);
+            slowest = slowest.max(started.elapsed());
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        slowest
+    };
+
+    // The test runtime is single-threaded, so hashing on it would hold each probe up
+    // behind the queued registrations. Off the runtime, probes only share the CPU.
+    let slowest = probes.await;
+    while let Some(response) = registrations.join_next().await {
+        assert_eq!(response.unwrap().unwrap().status(), 200);
+    }
+    let burst = started.elapsed();
+    assert!(
+        slowest * 5 < burst,
+        "health check took {:?} during a {:?} burst",
+        slowest,
+        burst
+    );
+}
+
+#[tokio::test]
+async fn bcrypt_hashes_still_log_in_and_are_upgraded_to_argon2id() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let bcrypt_hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    set_hash(&app, "jane@example.com", &bcrypt_hash).await;
+
+    // A failed login leaves the hash alone
+    assert_eq!(
+        app.login("jane@example.com", "wrong-password")
+            .await
+            .status(),
+        401
+    );
+    assert_eq!(stored_hash(&app, "jane@example.com").await, bcrypt_hash);
+
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+    let upgraded = stored_hash(&app, "jane@example.com").await;
+    assert!(upgraded.starts_with("$argon2id$"), "{}", upgraded);
+
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+}
+
+#[tokio::test]
+async fn argon2id_hashes_with_old_costs_are_rehashed_on_login() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    // The test app hashes with one iteration; this hash predates a cost change
+    let mut old_settings = Settings::new().unwrap().application;
+    old_settings.argon2_memory_kib = 1024;
+    old_settings.argon2_iterations = 2;
+    let old_hash = hash_password(PASSWORD, &old_settings).await.unwrap();
+    set_hash(&app, "jane@example.com", &old_hash).await;
+
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+    let rehashed = stored_hash(&app, "jane@example.com").await;
+    assert_ne!(rehashed, old_hash);
+    assert!(rehashed.contains("m=1024,t=1,p=1"), "{}", rehashed);
+}
+
+#[tokio::test]
+async fn current_hashes_are_not_rewritten_on_login() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let hash = stored_hash(&app, "jane@example.com").await;
+
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+
+    assert_eq!(stored_hash(&app, "jane@example.com").await, hash);
+}
+
+#[tokio::test]
+async fn bcrypt_can_be_configured_and_other_hashes_are_converted_to_it() {
+    let app = spawn_app_with(|settings| {
+        settings.application.password_algorithm = PasswordAlgorithm::Bcrypt;
+        settings.application.bcrypt_cost = 4;
+    })
+    .await;
+    app.register_user("jane@example.com").await;
+    assert!(stored_hash(&app, "jane@example.com")
+        .await
+        .starts_with("$2b$04$"));
+
+    let mut argon2_settings = Settings::new().unwrap().application;
+    argon2_settings.argon2_memory_kib = 1024;
+    argon2_settings.argon2_iterations = 1;
+    let argon2_hash = hash_password(PASSWORD, &argon2_settings).await.unwrap();
+    let old_cost_hash = bcrypt::hash(PASSWORD, 5).unwrap();
+
+    for hash in [argon2_hash, old_cost_hash] {
+        set_hash(&app, "jane@example.com", &hash).await;
+        assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+        let converted = stored_hash(&app, "jane@example.com").await;
+        assert!(converted.starts_with("$2b$04$"), "{}", converted);
+    }
+}
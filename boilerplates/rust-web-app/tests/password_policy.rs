@@ -0,0 +1,214 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use serde_json::{json, Value};
+
+async fn register(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post("/api/auth/register")
+        .json(&json!({ "email": email, "password": password, "name": "Jane Doe" }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The 422 field errors for `field`.
+async fn field_errors(response: reqwest::Response, field: &str) -> Value {
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "VALIDATION_ERROR");
+    body["fields"][field].clone()
+}
+
+#[tokio::test]
+async fn passwords_need_a_letter_and_a_digit() {
+    let app = spawn_app().await;
+
+    for (password, message) in [
+        ("horse-battery", "Password must contain a digit"),
+        ("1234-5678-90", "Password must contain a letter"),
+    ] {
+        // Reported together with the request's other field errors
+        let response = app
+            .post("/api/auth/register")
+            .json(&json!({ "email": "not-an-email", "password": password, "name": "Jane" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(
+            body["fields"],
+            json!({ "email": ["Invalid email address"], "password": [message] })
+        );
+    }
+}
+
+#[tokio::test]
+async fn common_passwords_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = register(&app, "jane@example.com", "Password123").await;
+    assert_eq!(
+        field_errors(response, "password").await,
+        json!(["Password is too common"])
+    );
+}
+
+#[tokio::test]
+async fn policy_errors_are_reported_with_the_other_field_errors() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "jane@example.com", "password": "Password123", "name": "J" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["fields"],
+        json!({
+            "name": ["Name must be at least 2 characters"],
+            "password": ["Password is too common"],
+        })
+    );
+}
+
+#[tokio::test]
+async fn passwords_containing_the_email_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = register(&app, "jane.doe@example.com", "my-Jane.Doe-secret-9").await;
+    assert_eq!(
+        field_errors(response, "password").await,
+        json!(["Password must not contain your email address"])
+    );
+
+    // Short local parts would match too much to be useful
+    let response = register(&app, "jo@example.com", "jolly-roger-42").await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn required_character_classes_name_the_missing_one() {
+    let app = spawn_app_with(|settings| {
+        let policy = &mut settings.application.password_policy;
+        policy.min_length = 10;
+        policy.require_uppercase = true;
+        policy.require_symbol = true;
+    })
+    .await;
+
+    for (password, message) in [
+        ("Short-42", "Password must be at least 10 characters"),
+        (
+            "all-lowercase-42",
+            "Password must contain an uppercase letter",
+        ),
+        ("NoSymbolsHere42", "Password must contain a symbol"),
+    ] {
+        let response = register(&app, "jane@example.com", password).await;
+        assert_eq!(
+            field_errors(response, "password").await,
+            json!([message]),
+            "{}",
+            password
+        );
+    }
+
+    let response = register(&app, "jane@example.com", "Correct-Horse-42").await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn the_letter_and_digit_rules_can_be_turned_off() {
+    let app = spawn_app_with(|settings| {
+        let policy = &mut settings.application.password_policy;
+        policy.require_letter = false;
+        policy.require_digit = false;
+    })
+    .await;
+
+    let response = register(&app, "jane@example.com", "plum-tractor-nebula").await;
+    assert_eq!(response.status(), 200);
+    let response = register(&app, "john@example.com", "8302-5571-9046").await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn the_strength_estimate_is_applied_when_configured() {
+    let app = spawn_app_with(|settings| {
+        settings.application.password_policy.min_score = Some(3);
+    })
+    .await;
+
+    let response = register(&app, "jane@example.com", "abcabcabc1").await;
+    let errors = field_errors(response, "password").await;
+    assert!(
+        errors[0]
+            .as_str()
+            .unwrap()
+            .starts_with("Password is too easy to guess"),
+        "{}",
+        errors
+    );
+
+    let response = register(&app, "jane@example.com", "plum-tractor-nebula-17").await;
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn the_policy_applies_to_password_changes() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": "iloveyou1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        field_errors(response, "new_password").await,
+        json!(["Password is too common"])
+    );
+}
+
+#[tokio::test]
+async fn a_rejected_reset_leaves_the_token_usable() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app
+        .post("/api/auth/forgot-password")
+        .json(&json!({ "email": "jane@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let email = app.mailer.wait_for("jane@example.com").await;
+    let reset_token = email
+        .body
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit(' ').next())
+        .unwrap()
+        .to_string();
+
+    let reset = |password: &'static str| {
+        app.post("/api/auth/reset-password")
+            .json(&json!({ "token": reset_token, "new_password": password }))
+            .send()
+    };
+
+    let response = reset("jane-is-great-1").await.unwrap();
+    assert_eq!(
+        field_errors(response, "new_password").await,
+        json!(["Password must not contain your email address"])
+    );
+
+    let response = reset("plum-tractor-nebula-17").await.unwrap();
+    assert_eq!(response.status(), 200);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{spawn_app, TestApp};
+use serde_json::{json, Value};
+
+const NEW_PASSWORD: &str = "new-password-456";
+
+/// Requests a reset for `email` and reads the token out of the email that follows.
+async fn request_reset(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post("/api/auth/forgot-password")
+        .json(&json!({ "email": email }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let email = app.mailer.wait_for(email).await;
+    assert_eq!(email.subject, "Reset your password");
+    email
+        .body
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit(' ').next())
+        .unwrap()
+        .to_string()
+}
+
+async fn reset_password(app: &TestApp, token: &str) -> reqwest::Response {
+    app.post("/api/auth/reset-password")
+        .json(&json!({ "token": token, "new_password": NEW_PASSWORD }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn forgot_password_responds_identically_for_unknown_emails() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let known = app
+        .post("/api/auth/forgot-password")
+        .json(&json!({ "email": "jane@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    let unknown = app
+        .post("/api/auth/forgot-password")
+        .json(&json!({ "email": "nobody@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(known.status(), 200);
+    assert_eq!(unknown.status(), 200);
+    let known: Value = known.json().await.unwrap();
+    let unknown: Value = unknown.json().await.unwrap();
+    assert_eq!(known, unknown);
+}
+
+#[tokio::test]
+async fn forgot_password_matches_the_email_in_any_case() {
+    let app = spawn_app().await;
+    app.register_user("Jane.Doe@Example.com").await;
+
+    let response = app
+        .post("/api/auth/forgot-password")
+        .json(&json!({ "email": "jane.doe@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let email = app.mailer.wait_for("Jane.Doe@Example.com").await;
+    assert_eq!(email.subject, "Reset your password");
+}
+
+#[tokio::test]
+async fn reset_token_changes_the_password_and_can_only_be_used_once() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let token = request_reset(&app, "jane@example.com").await;
+
+    assert_eq!(reset_password(&app, &token).await.status(), 200);
+    assert_eq!(
+        app.login("jane@example.com", common::PASSWORD)
+            .await
+            .status(),
+        401
+    );
+    assert_eq!(
+        app.login("jane@example.com", NEW_PASSWORD).await.status(),
+        200
+    );
+
+    let response = reset_password(&app, &token).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Invalid or expired reset token");
+}
+
+#[tokio::test]
+async fn expired_reset_tokens_are_rejected() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let token = request_reset(&app, "jane@example.com").await;
+
+    sqlx::query("UPDATE password_reset_tokens SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let response = reset_password(&app, &token).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "BAD_REQUEST");
+    assert_eq!(
+        app.login("jane@example.com", common::PASSWORD)
+            .await
+            .status(),
+        200
+    );
+}
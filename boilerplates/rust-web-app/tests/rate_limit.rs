@@ -0,0 +1,213 @@
+mod common;
+
+use common::{spawn_app_with, PASSWORD};
+
+/// The test client connects from loopback, so trusting it lets tests pick their IP
+/// with `X-Forwarded-For`.
+const TEST_PROXY: &str = "127.0.0.1";
+
+#[tokio::test]
+async fn login_is_locked_after_too_many_failures() {
+    let app = spawn_app_with(|settings| settings.rate_limit.login_max_attempts = 3).await;
+    app.register_user("jane@example.com").await;
+
+    for _ in 0..3 {
+        let response = app.login("jane@example.com", "wrong-password").await;
+        assert_eq!(response.status(), 401);
+    }
+
+    // Even the correct password is refused during the cooldown
+    let response = app.login("jane@example.com", PASSWORD).await;
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn successful_login_resets_the_failure_count() {
+    let app = spawn_app_with(|settings| settings.rate_limit.login_max_attempts = 3).await;
+    app.register_user("jane@example.com").await;
+
+    for _ in 0..2 {
+        app.login("jane@example.com", "wrong-password").await;
+    }
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+
+    for _ in 0..2 {
+        app.login("jane@example.com", "wrong-password").await;
+    }
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+}
+
+#[tokio::test]
+async fn lockout_is_per_client_ip() {
+    let app = spawn_app_with(|settings| {
+        settings.rate_limit.login_max_attempts = 2;
+        settings.server.trusted_proxies = vec![TEST_PROXY.to_string()];
+    })
+    .await;
+    app.register_user("jane@example.com").await;
+
+    for _ in 0..2 {
+        app.post("/api/auth/login")
+            .header("X-Forwarded-For", "203.0.113.7")
+            .json(&serde_json::json!({ "email": "jane@example.com", "password": "nope-nope" }))
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let locked = app
+        .post("/api/auth/login")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .json(&serde_json::json!({ "email": "jane@example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(locked.status(), 429);
+
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 200);
+}
+
+#[tokio::test]
+async fn rotating_forwarded_for_does_not_reset_the_lockout() {
+    let app = spawn_app_with(|settings| settings.rate_limit.login_max_attempts = 2).await;
+    app.register_user("jane@example.com").await;
+
+    let login = |ip: String, password: &'static str| {
+        app.post("/api/auth/login")
+            .header("X-Forwarded-For", ip)
+            .json(&serde_json::json!({ "email": "jane@example.com", "password": password }))
+            .send()
+    };
+
+    for i in 0..2 {
+        let response = login(format!("203.0.113.{}", i), "nope-nope")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+
+    // The header is ignored from an untrusted peer, so this is the same client
+    let response = login("198.51.100.1".to_string(), PASSWORD).await.unwrap();
+    assert_eq!(response.status(), 429);
+}
+
+#[tokio::test]
+async fn account_is_locked_when_guesses_come_from_many_ips() {
+    let app = spawn_app_with(|settings| {
+        settings.rate_limit.login_max_attempts = 2;
+        settings.rate_limit.account_max_attempts = 4;
+        settings.server.trusted_proxies = vec![TEST_PROXY.to_string()];
+    })
+    .await;
+    app.register_user("jane@example.com").await;
+
+    for i in 0..4 {
+        let response = app
+            .post("/api/auth/login")
+            .header("X-Forwarded-For", format!("203.0.113.{}", i))
+            .json(&serde_json::json!({ "email": "jane@example.com", "password": "nope-nope" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+    }
+
+    // A fresh IP with the correct password is still refused
+    let response = app
+        .post("/api/auth/login")
+        .header("X-Forwarded-For", "198.51.100.1")
+        .json(&serde_json::json!({ "email": "Jane@Example.com", "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn auth_endpoints_are_throttled_per_ip() {
+    let app = spawn_app_with(|settings| {
+        settings.rate_limit.requests_per_minute = 1;
+        settings.rate_limit.burst = 2;
+        settings.server.trusted_proxies = vec![TEST_PROXY.to_string()];
+    })
+    .await;
+
+    let forgot = |ip: &'static str| {
+        app.post("/api/auth/forgot-password")
+            .header("X-Forwarded-For", ip)
+            .json(&serde_json::json!({ "email": "jane@example.com" }))
+            .send()
+    };
+
+    for _ in 0..2 {
+        assert_eq!(forgot("203.0.113.7").await.unwrap().status(), 200);
+    }
+    let response = forgot("203.0.113.7").await.unwrap();
+    assert_eq!(response.status(), 429);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    // Other clients and unthrottled routes are unaffected
+    assert_eq!(forgot("198.51.100.1").await.unwrap().status(), 200);
+    let response = app
+        .get("/api/auth/jwks.json")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn forwarded_for_from_an_untrusted_peer_is_ignored() {
+    let app = spawn_app_with(|settings| {
+        settings.rate_limit.requests_per_minute = 1;
+        settings.rate_limit.burst = 2;
+    })
+    .await;
+
+    // A new made-up address per request must not buy a new bucket
+    for i in 0..3 {
+        let response = app
+            .post("/api/auth/forgot-password")
+            .header("X-Forwarded-For", format!("203.0.113.{}", i))
+            .json(&serde_json::json!({ "email": "jane@example.com" }))
+            .send()
+            .await
+            .unwrap();
+        let expected = if i < 2 { 200 } else { 429 };
+        assert_eq!(response.status(), expected);
+    }
+}
+
+#[tokio::test]
+async fn only_the_hops_added_by_trusted_proxies_are_believed() {
+    let app = spawn_app_with(|settings| {
+        settings.rate_limit.requests_per_minute = 1;
+        settings.rate_limit.burst = 2;
+        settings.server.trusted_proxies = vec![TEST_PROXY.to_string(), "10.0.0.0/8".to_string()];
+    })
+    .await;
+
+    // The client controls everything left of the address our proxies appended
+    for i in 0..3 {
+        let response = app
+            .post("/api/auth/forgot-password")
+            .header(
+                "X-Forwarded-For",
+                format!("198.51.100.{}, 203.0.113.7, 10.1.2.3", i),
+            )
+            .json(&serde_json::json!({ "email": "jane@example.com" }))
+            .send()
+            .await
+            .unwrap();
+        let expected = if i < 2 { 200 } else { 429 };
+        assert_eq!(response.status(), expected);
+    }
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::spawn_app;
+use serde_json::Value;
+
+#[tokio::test]
+async fn incoming_request_id_is_echoed_and_included_in_errors() {
+    let app = spawn_app().await;
+
+    let response = app
+        .get("/api/users/me")
+        .header("x-request-id", "client-supplied-id")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["x-request-id"], "client-supplied-id");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "client-supplied-id");
+}
+
+#[tokio::test]
+async fn request_id_is_generated_when_missing_or_oversized() {
+    let app = spawn_app().await;
+
+    let missing = app.get("/health").send().await.unwrap();
+    let oversized = app
+        .get("/health")
+        .header("x-request-id", "x".repeat(129))
+        .send()
+        .await
+        .unwrap();
+
+    for response in [missing, oversized] {
+        let id = response.headers()["x-request-id"].to_str().unwrap();
+        assert!(uuid::Uuid::parse_str(id).is_ok(), "not a uuid: {}", id);
+    }
+}
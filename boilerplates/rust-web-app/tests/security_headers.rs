@@ -0,0 +1,31 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn responses_carry_security_headers_without_hsts_by_default() {
+    let app = spawn_app().await;
+
+    for path in ["/health", "/api/does-not-exist"] {
+        let response = app.get(path).send().await.unwrap();
+        let headers = response.headers();
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+        assert_eq!(headers["x-frame-options"], "DENY");
+        assert_eq!(headers["referrer-policy"], "no-referrer");
+        assert!(headers.get("strict-transport-security").is_none());
+    }
+}
+
+#[tokio::test]
+async fn hsts_is_sent_once_a_max_age_is_configured() {
+    let app = spawn_app_with(|settings| {
+        settings.security.hsts_max_age = Some(31_536_000);
+    })
+    .await;
+
+    let response = app.get("/health").send().await.unwrap();
+    assert_eq!(
+        response.headers()["strict-transport-security"],
+        "max-age=31536000"
+    );
+}
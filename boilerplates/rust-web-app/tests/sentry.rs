@@ -0,0 +1,75 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::spawn_app;
+use rust_web_app::telemetry;
+use sentry::{protocol::Event, test::TestTransport, Hub};
+use uuid::Uuid;
+
+/// Binds a client that records events instead of sending them. The request layers start
+/// from the main hub, so the whole test binary shares it.
+fn capture_events() -> Arc<TestTransport> {
+    let transport = TestTransport::new();
+    let mut options = telemetry::sentry_options(
+        Some("https://public@sentry.invalid/1".parse().unwrap()),
+        "test",
+    );
+    options.transport = Some(Arc::new(transport.clone()));
+    Hub::main().bind_client(Some(Arc::new(options.into())));
+    transport
+}
+
+fn events(transport: &TestTransport) -> Vec<Event<'static>> {
+    transport
+        .fetch_and_clear_envelopes()
+        .into_iter()
+        .filter_map(|envelope| envelope.event().cloned())
+        .collect()
+}
+
+#[tokio::test]
+async fn server_errors_are_reported_with_request_context_and_client_errors_are_not() {
+    let transport = capture_events();
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let user_id: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE email = $1")
+        .bind("jane@example.com")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+
+    let response = app.get("/api/does-not-exist").send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert!(events(&transport).is_empty());
+
+    sqlx::query("ALTER VIEW active_users RENAME TO active_users_renamed")
+        .execute(&app.db)
+        .await
+        .unwrap();
+    let response = app
+        .get("/api/users/me?token=secret")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+    let request_id = response.headers()["x-request-id"].to_str().unwrap();
+
+    let events = events(&transport);
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.level, sentry::Level::Error);
+    assert_eq!(event.tags["request_id"], request_id);
+    assert_eq!(event.tags["path"], "/api/users/me");
+    assert_eq!(event.user.as_ref().unwrap().id, Some(user_id.to_string()));
+
+    let request = event.request.as_ref().unwrap();
+    assert_eq!(request.method.as_deref(), Some("GET"));
+    assert!(!request
+        .headers
+        .keys()
+        .any(|name| name.eq_ignore_ascii_case("authorization")));
+    assert!(request.query_string.is_none());
+    assert!(request.url.as_ref().unwrap().query().is_none());
+}
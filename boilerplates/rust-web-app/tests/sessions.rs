@@ -0,0 +1,205 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use serde_json::{json, Value};
+
+/// Logs in from a client identifying as `user_agent`, returning the token.
+async fn login_from(app: &TestApp, email: &str, user_agent: &str) -> String {
+    let response = app
+        .post("/api/auth/login")
+        .header("User-Agent", user_agent)
+        .header("X-Forwarded-For", "203.0.113.7")
+        .json(&json!({ "email": email, "password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["data"]["token"].as_str().unwrap().to_string()
+}
+
+async fn list_sessions(app: &TestApp, token: &str) -> Vec<Value> {
+    let response = app
+        .get("/api/users/me/sessions")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["data"].as_array().unwrap().clone()
+}
+
+async fn me_status(app: &TestApp, token: &str) -> u16 {
+    app.get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn sessions_are_listed_with_the_current_one_flagged() {
+    // Trusts the test client so the forwarded address is recorded
+    let app = spawn_app_with(|settings| {
+        settings.server.trusted_proxies = vec!["127.0.0.1".to_string()];
+    })
+    .await;
+    app.register_user("jane@example.com").await;
+    let laptop = login_from(&app, "jane@example.com", "Laptop/1.0").await;
+    login_from(&app, "jane@example.com", "Phone/2.0").await;
+
+    let sessions = list_sessions(&app, &laptop).await;
+    // Registering started a session too
+    assert_eq!(sessions.len(), 3);
+
+    let current: Vec<&Value> = sessions.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["user_agent"], "Laptop/1.0");
+    assert_eq!(current[0]["ip"], "203.0.113.7");
+    assert!(current[0]["last_seen_at"].is_string());
+    assert!(sessions.iter().any(|s| s["user_agent"] == "Phone/2.0"));
+}
+
+#[tokio::test]
+async fn revoking_a_session_stops_its_token_from_working() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let laptop = login_from(&app, "jane@example.com", "Laptop/1.0").await;
+    let phone = login_from(&app, "jane@example.com", "Phone/2.0").await;
+    // Caches the phone's session as active
+    assert_eq!(me_status(&app, &phone).await, 200);
+
+    let sessions = list_sessions(&app, &laptop).await;
+    let phone_session = sessions
+        .iter()
+        .find(|s| s["user_agent"] == "Phone/2.0")
+        .unwrap();
+
+    let response = app
+        .delete(&format!(
+            "/api/users/me/sessions/{}",
+            phone_session["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&laptop)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    assert!(response.headers().get("set-cookie").is_none());
+
+    assert_eq!(me_status(&app, &phone).await, 401);
+    assert_eq!(me_status(&app, &laptop).await, 200);
+}
+
+#[tokio::test]
+async fn other_users_sessions_cannot_be_revoked() {
+    let app = spawn_app().await;
+    let jane = app.register_user("jane@example.com").await;
+    let john = app.register_user("john@example.com").await;
+    let johns_session = list_sessions(&app, &john).await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .delete(&format!("/api/users/me/sessions/{}", johns_session))
+        .bearer_auth(&jane)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(me_status(&app, &john).await, 200);
+}
+
+#[tokio::test]
+async fn revoking_all_other_sessions_keeps_the_current_one() {
+    let app = spawn_app().await;
+    let registered = app.register_user("jane@example.com").await;
+    let laptop = login_from(&app, "jane@example.com", "Laptop/1.0").await;
+    let phone = login_from(&app, "jane@example.com", "Phone/2.0").await;
+
+    let response = app
+        .delete("/api/users/me/sessions")
+        .bearer_auth(&laptop)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    assert_eq!(me_status(&app, &registered).await, 401);
+    assert_eq!(me_status(&app, &phone).await, 401);
+    assert_eq!(me_status(&app, &laptop).await, 200);
+    let sessions = list_sessions(&app, &laptop).await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["current"], true);
+}
+
+#[tokio::test]
+async fn revoking_the_current_session_logs_out() {
+    let app = spawn_app_with(|settings| settings.auth.cookie_enabled = true).await;
+    let token = app.register_user("jane@example.com").await;
+    let session = list_sessions(&app, &token).await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .delete(&format!("/api/users/me/sessions/{}", session))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    let cookies: Vec<&str> = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .collect();
+    assert!(cookies
+        .iter()
+        .any(|c| c.starts_with("access_token=;") && c.contains("Max-Age=0")));
+
+    assert_eq!(me_status(&app, &token).await, 401);
+}
+
+#[tokio::test]
+async fn logout_revokes_the_session() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+    let token = login_from(&app, "jane@example.com", "Laptop/1.0").await;
+
+    let response = app
+        .post("/api/auth/logout")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(me_status(&app, &token).await, 401);
+}
+
+#[tokio::test]
+async fn changing_the_password_replaces_every_session() {
+    let app = spawn_app().await;
+    let old = app.register_user("jane@example.com").await;
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&old)
+        .json(&json!({ "current_password": PASSWORD, "new_password": "new-password-456" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let new = body["data"]["token"].as_str().unwrap();
+
+    let sessions = list_sessions(&app, new).await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["current"], true);
+}
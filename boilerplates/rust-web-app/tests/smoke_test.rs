@@ -0,0 +1,154 @@
+mod common;
+
+use std::{process::Command, time::Duration};
+
+use common::spawn_app;
+use rust_web_app::smoke::{self, SmokeTestOptions, SmokeTestReport, StepStatus};
+use serde_json::Value;
+
+async fn run(address: &str, skip_destructive: bool) -> SmokeTestReport {
+    smoke::run(&SmokeTestOptions {
+        base_url: address.parse().unwrap(),
+        timeout: Duration::from_secs(5),
+        skip_destructive,
+    })
+    .await
+}
+
+fn statuses(report: &SmokeTestReport) -> Vec<(&str, StepStatus)> {
+    report
+        .steps
+        .iter()
+        .map(|step| (step.name, step.status))
+        .collect()
+}
+
+async fn account(app: &common::TestApp, email: &str) -> (String, bool) {
+    sqlx::query_as("SELECT name, deleted_at IS NOT NULL FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(&app.db)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn the_full_flow_passes_and_deletes_its_account() {
+    let app = spawn_app().await;
+
+    let report = run(&app.address, false).await;
+
+    assert!(report.passed, "{:?}", report);
+    assert_eq!(
+        statuses(&report),
+        [
+            ("health", StepStatus::Passed),
+            ("register", StepStatus::Passed),
+            ("login", StepStatus::Passed),
+            ("profile", StepStatus::Passed),
+            ("update_profile", StepStatus::Passed),
+            ("delete_account", StepStatus::Passed),
+        ]
+    );
+    let (name, deleted) = account(&app, &report.email).await;
+    assert_eq!(name, "Smoke Test Updated");
+    assert!(deleted);
+
+    // Each run registers its own account
+    let again = run(&app.address, false).await;
+    assert!(again.passed, "{:?}", again);
+    assert_ne!(again.email, report.email);
+}
+
+#[tokio::test]
+async fn destructive_steps_can_be_skipped() {
+    let app = spawn_app().await;
+
+    let report = run(&app.address, true).await;
+
+    assert!(report.passed, "{:?}", report);
+    assert_eq!(
+        statuses(&report)[4..],
+        [
+            ("update_profile", StepStatus::Skipped),
+            ("delete_account", StepStatus::Skipped),
+        ]
+    );
+    let (name, deleted) = account(&app, &report.email).await;
+    assert_eq!(name, "Smoke Test");
+    assert!(!deleted);
+}
+
+#[tokio::test]
+async fn a_failed_step_skips_the_rest_but_still_cleans_up() {
+    let app = spawn_app().await;
+    // Only the profile update is rejected
+    sqlx::query(
+        "CREATE FUNCTION reject_renames() RETURNS trigger AS $$ \
+         BEGIN RAISE EXCEPTION 'renames are broken'; END $$ LANGUAGE plpgsql",
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+    sqlx::query(
+        "CREATE TRIGGER reject_renames BEFORE UPDATE OF name ON users \
+         FOR EACH ROW EXECUTE FUNCTION reject_renames()",
+    )
+    .execute(&app.db)
+    .await
+    .unwrap();
+
+    let report = run(&app.address, false).await;
+
+    assert!(!report.passed);
+    assert_eq!(report.failed_step().unwrap().name, "update_profile");
+    assert!(report.steps[4]
+        .error
+        .as_deref()
+        .unwrap()
+        .starts_with("expected 200 OK, got 500 Internal Server Error"));
+    assert_eq!(report.steps[5].status, StepStatus::Passed);
+    let (_, deleted) = account(&app, &report.email).await;
+    assert!(deleted);
+}
+
+#[tokio::test]
+async fn an_unreachable_target_fails_the_health_check() {
+    let report = run("http://127.0.0.1:1", false).await;
+
+    assert!(!report.passed);
+    let body = serde_json::to_value(&report).unwrap();
+    assert_eq!(body["steps"][0]["name"], "health");
+    assert_eq!(body["steps"][0]["status"], "failed");
+    assert!(body["steps"][0]["error"].is_string());
+    for step in body["steps"].as_array().unwrap()[1..].iter() {
+        assert_eq!(step["status"], "skipped");
+        assert!(step.get("error").is_none());
+    }
+}
+
+#[test]
+fn production_needs_allow_production() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-web-app"))
+        .args(["smoke-test", "--base-url", "http://127.0.0.1:1"])
+        .env("APP__APPLICATION__ENVIRONMENT", "production")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(78));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-production"));
+
+    // Allowed, it runs and reports the unreachable target as a failure
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-web-app"))
+        .args([
+            "smoke-test",
+            "--base-url",
+            "http://127.0.0.1:1",
+            "--allow-production",
+        ])
+        .env("APP__APPLICATION__ENVIRONMENT", "production")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], false);
+}
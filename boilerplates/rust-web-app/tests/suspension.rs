@@ -0,0 +1,187 @@
+mod common;
+
+use chrono::{DateTime, Duration, Utc};
+use common::{spawn_app, TestApp};
+use serde_json::{json, Value};
+
+/// An admin's token, and a user's token and id.
+async fn setup(app: &TestApp) -> (String, String, String) {
+    let admin_token = app.register_admin("admin@example.com").await;
+    let token = app.register_user("jane@example.com").await;
+    let body: Value = me(app, &token).await.json().await.unwrap();
+    let user_id = body["data"]["id"].as_str().unwrap().to_string();
+    (admin_token, token, user_id)
+}
+
+async fn me(app: &TestApp, token: &str) -> reqwest::Response {
+    app.get("/api/users/me")
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn suspend(
+    app: &TestApp,
+    admin_token: &str,
+    user_id: &str,
+    body: Value,
+) -> reqwest::Response {
+    app.post(&format!("/api/admin/users/{}/suspend", user_id))
+        .bearer_auth(admin_token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+fn suspended_until(body: &Value) -> DateTime<Utc> {
+    body["data"]["suspended_until"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn a_duration_suspends_from_now() {
+    let app = spawn_app().await;
+    let (admin_token, token, user_id) = setup(&app).await;
+
+    let before = Utc::now();
+    let response = suspend(
+        &app,
+        &admin_token,
+        &user_id,
+        json!({ "duration_secs": 3600, "reason": "Spam" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let until = suspended_until(&body);
+    assert!(until >= before + Duration::seconds(3600));
+    assert!(until <= Utc::now() + Duration::seconds(3600));
+
+    let email = app
+        .mailer
+        .wait_for_subject("jane@example.com", "Your account has been suspended")
+        .await;
+    assert!(email.body.contains("Reason: Spam"), "{}", email.body);
+
+    // The 403 says until when, so clients can tell the user
+    let response = me(&app, &token).await;
+    assert_eq!(response.status(), 403);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"], "ACCOUNT_SUSPENDED");
+    let details: DateTime<Utc> = error["details"]["suspended_until"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(details, until);
+}
+
+#[tokio::test]
+async fn an_until_date_suspends_until_then() {
+    let app = spawn_app().await;
+    let (admin_token, token, user_id) = setup(&app).await;
+
+    let until = "2999-01-01T00:00:00Z";
+    let response = suspend(
+        &app,
+        &admin_token,
+        &user_id,
+        json!({ "until": until, "reason": "Spam" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        suspended_until(&body),
+        until.parse::<DateTime<Utc>>().unwrap()
+    );
+
+    let error: Value = me(&app, &token).await.json().await.unwrap();
+    assert_eq!(error["error"], "ACCOUNT_SUSPENDED");
+}
+
+#[tokio::test]
+async fn the_end_must_be_given_exactly_once_and_in_the_future() {
+    let app = spawn_app().await;
+    let (admin_token, token, user_id) = setup(&app).await;
+
+    for (body, message) in [
+        (
+            json!({ "duration_secs": 3600, "until": "2999-01-01T00:00:00Z", "reason": "Spam" }),
+            "Provide exactly one of duration_secs or until",
+        ),
+        (
+            json!({ "reason": "Spam" }),
+            "Provide exactly one of duration_secs or until",
+        ),
+        (
+            json!({ "until": "2000-01-01T00:00:00Z", "reason": "Spam" }),
+            "Suspension must end in the future",
+        ),
+    ] {
+        let response = suspend(&app, &admin_token, &user_id, body.clone()).await;
+        assert_eq!(response.status(), 400, "{}", body);
+        let error: Value = response.json().await.unwrap();
+        assert_eq!(error["message"], message);
+    }
+
+    assert_eq!(me(&app, &token).await.status(), 200);
+}
+
+#[tokio::test]
+async fn unsuspending_restores_access() {
+    let app = spawn_app().await;
+    let (admin_token, token, user_id) = setup(&app).await;
+    let response = suspend(
+        &app,
+        &admin_token,
+        &user_id,
+        json!({ "duration_secs": 3600, "reason": "Spam" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(me(&app, &token).await.status(), 403);
+
+    let response = app
+        .post(&format!("/api/admin/users/{}/unsuspend", user_id))
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["suspended_until"].is_null());
+
+    assert_eq!(me(&app, &token).await.status(), 200);
+    app.mailer
+        .wait_for_subject("jane@example.com", "Your account has been reinstated")
+        .await;
+}
+
+#[tokio::test]
+async fn a_lapsed_suspension_no_longer_blocks() {
+    let app = spawn_app().await;
+    let (admin_token, token, user_id) = setup(&app).await;
+    let response = suspend(
+        &app,
+        &admin_token,
+        &user_id,
+        json!({ "duration_secs": 3600, "reason": "Spam" }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    // Expiry is only ever compared against the clock, so an end in the past is a lapse
+    sqlx::query("UPDATE users SET suspended_until = NOW() - INTERVAL '1 second' WHERE email = $1")
+        .bind("jane@example.com")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    assert_eq!(me(&app, &token).await.status(), 200);
+}
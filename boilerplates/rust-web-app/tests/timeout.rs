@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use axum::{body::Body, http::Request, routing::get, Router};
+use http_body_util::BodyExt;
+use rust_web_app::middleware::fallback::json_errors;
+use serde_json::Value;
+use tower::ServiceExt;
+use tower_http::timeout::TimeoutLayer;
+
+async fn slow() -> &'static str {
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    "done"
+}
+
+#[tokio::test]
+async fn handlers_that_overrun_the_timeout_get_a_json_504() {
+    // Same layering as `build_app`
+    let app = Router::new()
+        .route("/slow", get(slow))
+        .layer(TimeoutLayer::new(Duration::from_millis(50)))
+        .layer(axum::middleware::from_fn(json_errors));
+
+    let response = app
+        .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 504);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["error"], "GATEWAY_TIMEOUT");
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::{
+    io,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use common::spawn_app;
+use serde_json::Value;
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Everything logged by the test binary, as JSON lines.
+fn captured_logs() -> Arc<Mutex<Vec<u8>>> {
+    static LOGS: OnceLock<Arc<Mutex<Vec<u8>>>> = OnceLock::new();
+    LOGS.get_or_init(|| {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let writer = logs.clone();
+        tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(move || Capture(writer.clone()))
+            .init();
+        logs
+    })
+    .clone()
+}
+
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fields of the request span for `request_id`, as logged when the span closes.
+async fn closed_request_span(request_id: &str) -> Value {
+    let logs = captured_logs();
+    for _ in 0..50 {
+        let lines = String::from_utf8(logs.lock().unwrap().clone()).unwrap();
+        let closed = lines
+            .lines()
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .find(|line| {
+                line["fields"]["message"] == "close" && line["span"]["request_id"] == request_id
+            });
+        if let Some(line) = closed {
+            return line["span"].clone();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("no closed span for request {}", request_id);
+}
+
+#[tokio::test]
+async fn request_spans_record_route_status_and_latency() {
+    captured_logs();
+    let app = spawn_app().await;
+
+    let response = app
+        .get("/api/users/6f1c9a52-0a4e-4d9b-9f7e-1d2c3b4a5e6f")
+        .header("X-Request-Id", "span-fields-test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let span = closed_request_span("span-fields-test").await;
+    assert_eq!(span["http.method"], "GET");
+    // The route template, not the raw path
+    assert_eq!(span["http.route"], "/api/users/:id");
+    assert_eq!(span["http.status_code"], 404);
+    assert!(span["latency_ms"].is_u64(), "{}", span);
+}
+
+#[tokio::test]
+async fn unmatched_requests_are_recorded_as_such() {
+    captured_logs();
+    let app = spawn_app().await;
+
+    let response = app
+        .post("/no/such/route")
+        .header("X-Request-Id", "span-unmatched-test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let span = closed_request_span("span-unmatched-test").await;
+    assert_eq!(span["http.method"], "POST");
+    assert_eq!(span["http.route"], "unmatched");
+    assert_eq!(span["http.status_code"], 404);
+}
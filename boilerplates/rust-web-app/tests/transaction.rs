@@ -0,0 +1,53 @@
+mod common;
+
+use common::spawn_app;
+use rust_web_app::utils::{error::AppError, transaction::with_transaction};
+
+async fn insert_user(tx: &mut sqlx::PgConnection, email: &str) -> Result<(), AppError> {
+    sqlx::query("INSERT INTO users (email, password_hash, name) VALUES ($1, 'hash', 'Jane')")
+        .bind(email)
+        .execute(tx)
+        .await?;
+    Ok(())
+}
+
+async fn user_exists(db: &sqlx::PgPool, email: &str) -> bool {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)")
+        .bind(email)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn with_transaction_commits_on_ok() {
+    let app = spawn_app().await;
+
+    let value = with_transaction(&app.db, |tx| {
+        Box::pin(async move {
+            insert_user(tx, "jane@example.com").await?;
+            Ok(42)
+        })
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(value, 42);
+    assert!(user_exists(&app.db, "jane@example.com").await);
+}
+
+#[tokio::test]
+async fn with_transaction_rolls_back_on_err() {
+    let app = spawn_app().await;
+
+    let result: Result<(), AppError> = with_transaction(&app.db, |tx| {
+        Box::pin(async move {
+            insert_user(tx, "jane@example.com").await?;
+            Err(AppError::BadRequest("abort".to_string()))
+        })
+    })
+    .await;
+
+    assert!(matches!(result, Err(AppError::BadRequest(msg)) if msg == "abort"));
+    assert!(!user_exists(&app.db, "jane@example.com").await);
+}
@@ -0,0 +1,381 @@
+mod common;
+
+use common::{spawn_app, spawn_app_with, TestApp, PASSWORD};
+use rust_web_app::utils::two_factor::{
+    base32_decode, base32_encode, current_step, otpauth_uri, totp_code, verify_totp, SecretCipher,
+    TOTP_STEP_SECS,
+};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// The RFC 6238 SHA-1 test secret.
+const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+#[test]
+fn totp_codes_match_the_rfc_6238_vectors() {
+    // The RFC lists 8-digit codes; 6-digit codes are their last six digits
+    for (time, code) in [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+    ] {
+        assert_eq!(
+            totp_code(RFC_SECRET, time / TOTP_STEP_SECS),
+            code,
+            "{}",
+            time
+        );
+    }
+}
+
+#[test]
+fn codes_are_accepted_within_one_step_of_clock_skew() {
+    let step = 1_000_000;
+    let code_at = |offset: i64| totp_code(RFC_SECRET, (step as i64 + offset) as u64);
+
+    for offset in [-1, 0, 1] {
+        let accepted = verify_totp(RFC_SECRET, &code_at(offset), step, None);
+        assert_eq!(accepted, Some((step as i64 + offset) as u64), "{}", offset);
+    }
+    for offset in [-2, 2] {
+        assert_eq!(verify_totp(RFC_SECRET, &code_at(offset), step, None), None);
+    }
+}
+
+#[test]
+fn codes_at_or_before_the_last_used_step_are_rejected() {
+    let step = 1_000_000;
+
+    assert_eq!(
+        verify_totp(RFC_SECRET, &totp_code(RFC_SECRET, step), step, Some(step)),
+        None
+    );
+    assert_eq!(
+        verify_totp(
+            RFC_SECRET,
+            &totp_code(RFC_SECRET, step - 1),
+            step,
+            Some(step - 1)
+        ),
+        None
+    );
+    assert_eq!(
+        verify_totp(
+            RFC_SECRET,
+            &totp_code(RFC_SECRET, step + 1),
+            step,
+            Some(step)
+        ),
+        Some(step + 1)
+    );
+}
+
+#[test]
+fn malformed_codes_are_rejected() {
+    let step = 1_000_000;
+    let code = totp_code(RFC_SECRET, step);
+
+    for guess in ["", "12345", "1234567", "abcdef", &format!("{}0", code)] {
+        assert_eq!(
+            verify_totp(RFC_SECRET, guess, step, None),
+            None,
+            "{}",
+            guess
+        );
+    }
+    assert_eq!(
+        verify_totp(RFC_SECRET, &format!(" {} ", code), step, None),
+        Some(step)
+    );
+}
+
+#[test]
+fn secrets_round_trip_through_base32_and_the_uri() {
+    assert_eq!(
+        base32_encode(RFC_SECRET),
+        "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+    );
+    assert_eq!(base32_encode(b"f"), "MY");
+    assert_eq!(base32_decode("my======").unwrap(), b"f");
+    assert_eq!(
+        base32_decode(&base32_encode(RFC_SECRET)).unwrap(),
+        RFC_SECRET
+    );
+
+    let uri = otpauth_uri("Acme Inc", "jane@example.com", RFC_SECRET);
+    assert_eq!(
+        uri,
+        "otpauth://totp/Acme%20Inc:jane%40example.com?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+         &issuer=Acme%20Inc&algorithm=SHA1&digits=6&period=30"
+    );
+}
+
+#[test]
+fn encrypted_secrets_only_decrypt_for_their_user() {
+    let cipher = SecretCipher::new(&"42".repeat(32)).unwrap();
+    let (jane, john) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let encrypted = cipher.encrypt(jane, RFC_SECRET).unwrap();
+    assert!(!encrypted.contains("1234567890"));
+    assert_ne!(encrypted, cipher.encrypt(jane, RFC_SECRET).unwrap());
+    assert_eq!(cipher.decrypt(jane, &encrypted).unwrap(), RFC_SECRET);
+    assert!(cipher.decrypt(john, &encrypted).is_err());
+
+    let other = SecretCipher::new(&"24".repeat(32)).unwrap();
+    assert!(other.decrypt(jane, &encrypted).is_err());
+
+    assert!(SecretCipher::new("not-hex").is_err());
+    assert!(SecretCipher::new(&"42".repeat(16)).is_err());
+}
+
+async fn post(app: &TestApp, path: &str, token: Option<&str>, body: Value) -> (u16, Value) {
+    let mut request = app.post(path).json(&body);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+/// Sets up and enables 2FA for the user, returning the secret and recovery codes.
+async fn enable_two_factor(app: &TestApp, token: &str) -> (Vec<u8>, Vec<String>) {
+    let (status, body) = post(app, "/api/users/me/2fa/setup", Some(token), json!({})).await;
+    assert_eq!(status, 200, "{}", body);
+    let secret = base32_decode(body["data"]["secret"].as_str().unwrap()).unwrap();
+    assert!(body["data"]["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/rust-web-app:"));
+
+    let code = totp_code(&secret, current_step());
+    let (status, body) = post(
+        app,
+        "/api/users/me/2fa/enable",
+        Some(token),
+        json!({ "code": code }),
+    )
+    .await;
+    assert_eq!(status, 200, "{}", body);
+    let recovery_codes = body["data"]["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+    (secret, recovery_codes)
+}
+
+/// Logs in with the password and returns the pending token of the 2FA challenge.
+async fn pending_token(app: &TestApp) -> String {
+    let response = app.login("jane@example.com", PASSWORD).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["two_factor_required"], true);
+    assert!(body["data"].get("token").is_none());
+    body["data"]["pending_token"].as_str().unwrap().to_string()
+}
+
+async fn verify(app: &TestApp, pending_token: &str, code: &str) -> (u16, Value) {
+    post(
+        app,
+        "/api/auth/2fa/verify",
+        None,
+        json!({ "pending_token": pending_token, "code": code }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn login_requires_a_code_once_two_factor_is_enabled() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+
+    let me: Value = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(me["data"]["two_factor_enabled"], true);
+
+    let pending = pending_token(&app).await;
+
+    // The code used to enable 2FA can't be replayed, the next one is within the window
+    let (status, _) = verify(&app, &pending, &totp_code(&secret, current_step())).await;
+    assert_eq!(status, 401);
+    let next_code = totp_code(&secret, current_step() + 1);
+    let (status, body) = verify(&app, &pending, &next_code).await;
+    assert_eq!(status, 200, "{}", body);
+    let session = body["data"]["token"].as_str().unwrap();
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(session)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Pending tokens are single-use, and an accepted code can't be used again
+    let (status, body) = verify(&app, &pending, &next_code).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["message"], "Invalid or expired two-factor challenge");
+    let (status, body) = verify(&app, &pending_token(&app).await, &next_code).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["message"], "Invalid two-factor code");
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let (_, recovery_codes) = enable_two_factor(&app, &token).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    // Typed loosely: uppercase and without the dashes
+    let typed = recovery_codes[0].replace('-', "").to_uppercase();
+    let (status, body) = verify(&app, &pending_token(&app).await, &typed).await;
+    assert_eq!(status, 200, "{}", body);
+
+    let (status, _) = verify(&app, &pending_token(&app).await, &recovery_codes[0]).await;
+    assert_eq!(status, 401);
+    let (status, _) = verify(&app, &pending_token(&app).await, &recovery_codes[1]).await;
+    assert_eq!(status, 200);
+
+    // Only hashes are stored
+    let stored: Vec<String> = sqlx::query_scalar("SELECT code_hash FROM two_factor_recovery_codes")
+        .fetch_all(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 10);
+    assert!(recovery_codes.iter().all(|code| !stored.contains(code)));
+}
+
+#[tokio::test]
+async fn challenges_are_discarded_after_too_many_wrong_codes() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+    let pending = pending_token(&app).await;
+
+    for _ in 0..5 {
+        let (status, _) = verify(&app, &pending, "000000").await;
+        assert_eq!(status, 401);
+    }
+
+    let code = totp_code(&secret, current_step() + 1);
+    let (status, body) = verify(&app, &pending, &code).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["message"], "Invalid or expired two-factor challenge");
+}
+
+#[tokio::test]
+async fn expired_challenges_are_rejected() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+    let pending = pending_token(&app).await;
+
+    sqlx::query("UPDATE two_factor_challenges SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db)
+        .await
+        .unwrap();
+
+    let (status, _) = verify(&app, &pending, &totp_code(&secret, current_step() + 1)).await;
+    assert_eq!(status, 401);
+}
+
+#[tokio::test]
+async fn enabling_requires_setup_and_a_valid_code() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let (status, _) = post(
+        &app,
+        "/api/users/me/2fa/enable",
+        Some(&token),
+        json!({ "code": "123456" }),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (status, body) = post(&app, "/api/users/me/2fa/setup", Some(&token), json!({})).await;
+    assert_eq!(status, 200);
+    let secret = base32_decode(body["data"]["secret"].as_str().unwrap()).unwrap();
+
+    // Only the encrypted secret is stored
+    let stored: String = sqlx::query_scalar("SELECT totp_secret FROM users")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert!(!stored.contains(body["data"]["secret"].as_str().unwrap()));
+
+    let wrong = totp_code(&secret, current_step() + 5);
+    let (status, body) = post(
+        &app,
+        "/api/users/me/2fa/enable",
+        Some(&token),
+        json!({ "code": wrong }),
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["message"], "Invalid two-factor code");
+
+    // Until enabled, login still hands out a token directly
+    let response = app.login("jane@example.com", PASSWORD).await;
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["token"].is_string());
+
+    enable_two_factor(&app, &token).await;
+    let (status, _) = post(&app, "/api/users/me/2fa/setup", Some(&token), json!({})).await;
+    assert_eq!(status, 409);
+}
+
+#[tokio::test]
+async fn disabling_requires_the_password() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+    let pending = pending_token(&app).await;
+
+    let (status, _) = post(
+        &app,
+        "/api/users/me/2fa/disable",
+        Some(&token),
+        json!({ "password": "wrong-password" }),
+    )
+    .await;
+    assert_eq!(status, 401);
+
+    let (status, _) = post(
+        &app,
+        "/api/users/me/2fa/disable",
+        Some(&token),
+        json!({ "password": PASSWORD }),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    // Outstanding challenges die with it and login is back to a single step
+    let (status, _) = verify(&app, &pending, &totp_code(&secret, current_step() + 1)).await;
+    assert_eq!(status, 401);
+    let response = app.login("jane@example.com", PASSWORD).await;
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["token"].is_string());
+    assert_eq!(body["data"]["user"]["two_factor_enabled"], false);
+}
+
+#[tokio::test]
+async fn setup_is_unavailable_without_an_encryption_key() {
+    let app = spawn_app_with(|settings| settings.two_factor.encryption_key = None).await;
+    let token = app.register_user("jane@example.com").await;
+
+    let (status, _) = post(&app, "/api/users/me/2fa/setup", Some(&token), json!({})).await;
+    assert_eq!(status, 503);
+}
@@ -0,0 +1,354 @@
+//! Register, login and authenticated requests against an in-memory [`UserRepository`],
+//! without a database.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use axum::{body::Body, http::Request, Router};
+use chrono::{DateTime, Utc};
+use http_body_util::BodyExt;
+use rust_web_app::{
+    build_app,
+    config::{HandleSettings, Settings},
+    db::Db,
+    models::{ListUsersQuery, Session, User, UserRole},
+    repositories::{
+        ApiKeyRepository, AuthState, NewUser, OAuthRepository, TwoFactorRepository, UserChanges,
+        UserRepository,
+    },
+    utils::{
+        auth::JwtKeys,
+        cache::MemoryCache,
+        error::{AppError, AppResult},
+        mailer::NoopMailer,
+        pagination::{Cursor, Pagination},
+        rate_limit::LoginRateLimiter,
+    },
+    AppState,
+};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+#[derive(Default)]
+struct InMemoryUserRepository {
+    users: Mutex<Vec<User>>,
+}
+
+impl InMemoryUserRepository {
+    fn modify(&self, id: Uuid, f: impl FnOnce(&mut User)) -> AppResult<User> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .iter_mut()
+            .find(|u| u.id == id && u.deleted_at.is_none())
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        f(user);
+        user.updated_at = Utc::now();
+        Ok(user.clone())
+    }
+
+    fn find(&self, predicate: impl Fn(&User) -> bool) -> Option<User> {
+        let users = self.users.lock().unwrap();
+        users
+            .iter()
+            .find(|u| u.deleted_at.is_none() && predicate(u))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn find_by_id(&self, id: Uuid) -> AppResult<Option<User>> {
+        Ok(self.find(|u| u.id == id))
+    }
+
+    async fn find_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        Ok(self.find(|u| u.email.eq_ignore_ascii_case(email)))
+    }
+
+    async fn find_by_handle(&self, handle: &str) -> AppResult<Option<User>> {
+        Ok(self.find(|u| u.handle.as_deref() == Some(handle)))
+    }
+
+    async fn auth_state(&self, id: Uuid) -> AppResult<Option<AuthState>> {
+        Ok(self.find(|u| u.id == id).map(|user| AuthState {
+            suspended_until: user.suspended_until,
+            password_changed_at: user.password_changed_at,
+            email_verified_at: user.email_verified_at,
+        }))
+    }
+
+    async fn role(&self, id: Uuid) -> AppResult<Option<UserRole>> {
+        Ok(self.find(|u| u.id == id).map(|user| user.role))
+    }
+
+    async fn find_unverified_by_email(&self, email: &str) -> AppResult<Option<User>> {
+        Ok(self.find(|u| u.email.eq_ignore_ascii_case(email) && u.email_verified_at.is_none()))
+    }
+
+    async fn list(&self, _: &ListUsersQuery, _: Pagination) -> AppResult<(Vec<User>, i64)> {
+        let users = self.users.lock().unwrap().clone();
+        let total = users.len() as i64;
+        Ok((users, total))
+    }
+
+    async fn list_before(&self, _: Option<Cursor>, _: i64) -> AppResult<Vec<User>> {
+        Ok(self.users.lock().unwrap().clone())
+    }
+
+    async fn email_in_use(&self, email: &str, except: Option<Uuid>) -> AppResult<bool> {
+        Ok(self
+            .find(|u| u.email.eq_ignore_ascii_case(email) && Some(u.id) != except)
+            .is_some())
+    }
+
+    async fn create(&self, user: NewUser, _: i64) -> AppResult<(User, String)> {
+        let now = Utc::now();
+        let user = User {
+            id: Uuid::new_v4(),
+            email: user.email,
+            password_hash: Some(user.password_hash),
+            name: user.name,
+            created_at: now,
+            updated_at: now,
+            email_verified_at: None,
+            role: UserRole::User,
+            suspended_until: None,
+            suspension_reason: None,
+            password_changed_at: None,
+            deleted_at: None,
+            handle: None,
+            handle_changed_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            totp_last_used_step: None,
+        };
+        self.users.lock().unwrap().push(user.clone());
+        Ok((user, Uuid::new_v4().to_string()))
+    }
+
+    async fn create_verification_token(&self, _: Uuid, _: i64) -> AppResult<String> {
+        Ok(Uuid::new_v4().to_string())
+    }
+
+    async fn mark_email_verified(&self, _: &str) -> AppResult<Option<Uuid>> {
+        Ok(None)
+    }
+
+    async fn update(&self, id: Uuid, changes: UserChanges) -> AppResult<User> {
+        self.modify(id, |user| {
+            if let Some(name) = changes.name {
+                user.name = name;
+            }
+            if let Some(email) = changes.email {
+                user.email = email;
+                user.email_verified_at = None;
+            }
+        })
+    }
+
+    async fn update_password(
+        &self,
+        id: Uuid,
+        password_hash: &str,
+        revoke_tokens: bool,
+    ) -> AppResult<User> {
+        self.modify(id, |user| {
+            user.password_hash = Some(password_hash.to_string());
+            if revoke_tokens {
+                user.password_changed_at = Some(Utc::now());
+            }
+        })
+    }
+
+    async fn create_password_reset_token(&self, _: Uuid, _: i64) -> AppResult<String> {
+        Ok(Uuid::new_v4().to_string())
+    }
+
+    async fn find_email_by_reset_token(&self, _: &str) -> AppResult<Option<String>> {
+        Ok(None)
+    }
+
+    async fn set_password(&self, _: &str, _: &str) -> AppResult<Option<Uuid>> {
+        Ok(None)
+    }
+
+    async fn suspend(
+        &self,
+        id: Uuid,
+        until: DateTime<Utc>,
+        reason: &str,
+    ) -> AppResult<Option<User>> {
+        Ok(self
+            .modify(id, |user| {
+                user.suspended_until = Some(until);
+                user.suspension_reason = Some(reason.to_string());
+            })
+            .ok())
+    }
+
+    async fn unsuspend(&self, id: Uuid) -> AppResult<Option<User>> {
+        Ok(self
+            .modify(id, |user| {
+                user.suspended_until = None;
+                user.suspension_reason = None;
+            })
+            .ok())
+    }
+
+    async fn set_role(&self, id: Uuid, role: UserRole) -> AppResult<Option<User>> {
+        Ok(self.modify(id, |user| user.role = role).ok())
+    }
+
+    async fn change_handle(&self, id: Uuid, handle: &str, _: &HandleSettings) -> AppResult<User> {
+        if self
+            .find(|u| u.id != id && u.handle.as_deref() == Some(handle))
+            .is_some()
+        {
+            return Err(AppError::already_in_use("handle"));
+        }
+        self.modify(id, |user| {
+            user.handle = Some(handle.to_string());
+            user.handle_changed_at = Some(Utc::now());
+        })
+    }
+
+    async fn soft_delete(&self, id: Uuid) -> AppResult<()> {
+        self.modify(id, |user| user.deleted_at = Some(Utc::now()))
+            .map(|_| ())
+    }
+
+    async fn create_session(
+        &self,
+        _: Uuid,
+        _: Option<&str>,
+        _: Option<&str>,
+        _: i64,
+    ) -> AppResult<Uuid> {
+        Ok(Uuid::new_v4())
+    }
+
+    async fn list_sessions(&self, _: Uuid) -> AppResult<Vec<Session>> {
+        Ok(Vec::new())
+    }
+
+    async fn touch_session(&self, _: Uuid, _: Uuid) -> AppResult<bool> {
+        Ok(true)
+    }
+
+    async fn delete_session(&self, _: Uuid, _: Uuid) -> AppResult<bool> {
+        Ok(false)
+    }
+
+    async fn delete_sessions(&self, _: Uuid, _: Option<Uuid>) -> AppResult<Vec<Uuid>> {
+        Ok(Vec::new())
+    }
+}
+
+fn app() -> Router {
+    let mut settings = Settings::new().expect("failed to load settings");
+    settings.application.argon2_memory_kib = 1024;
+    settings.application.argon2_iterations = 1;
+
+    // Nothing may touch this pool: it points at a port no database listens on
+    let pool = PgPoolOptions::new()
+        .connect_lazy("postgres://postgres@127.0.0.1:1/unused")
+        .unwrap();
+
+    let db = Db::new(pool, None);
+    build_app(AppState {
+        users: Arc::new(InMemoryUserRepository::default()),
+        two_factor: TwoFactorRepository::new(db.clone()),
+        api_keys: ApiKeyRepository::new(db.clone()),
+        oauth: OAuthRepository::new(db.clone()),
+        db,
+        login_limiter: Arc::new(LoginRateLimiter::new(&settings.rate_limit)),
+        cache: Arc::new(MemoryCache::new()),
+        mailer: Arc::new(NoopMailer),
+        jwt: Arc::new(JwtKeys::new(&settings.application).unwrap()),
+        config: settings,
+        started_at: Instant::now(),
+    })
+}
+
+async fn post(app: &Router, path: &str, body: Value) -> (u16, Value) {
+    let request = Request::post(path)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn get(app: &Router, path: &str, token: &str) -> (u16, Value) {
+    let request = Request::get(path)
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+async fn send(app: &Router, request: Request<Body>) -> (u16, Value) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn registration(email: &str) -> Value {
+    json!({ "email": email, "password": "correct-horse-42", "name": "Jane Doe" })
+}
+
+#[tokio::test]
+async fn register_then_login() {
+    let app = app();
+
+    let (status, body) = post(&app, "/api/auth/register", registration("jane@example.com")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["user"]["email"], "jane@example.com");
+
+    let credentials = json!({ "email": "Jane@Example.com", "password": "correct-horse-42" });
+    let (status, body) = post(&app, "/api/auth/login", credentials).await;
+    assert_eq!(status, 200);
+    assert!(body["data"]["token"].is_string());
+}
+
+#[tokio::test]
+async fn register_rejects_an_email_in_use_regardless_of_case() {
+    let app = app();
+    post(&app, "/api/auth/register", registration("jane@example.com")).await;
+
+    let (status, body) = post(&app, "/api/auth/register", registration("JANE@example.com")).await;
+    assert_eq!(status, 409);
+    assert_eq!(body["error"], "CONFLICT");
+}
+
+#[tokio::test]
+async fn login_rejects_a_wrong_password() {
+    let app = app();
+    post(&app, "/api/auth/register", registration("jane@example.com")).await;
+
+    let credentials = json!({ "email": "jane@example.com", "password": "wrong-password" });
+    let (status, body) = post(&app, "/api/auth/login", credentials).await;
+    assert_eq!(status, 401);
+    assert_eq!(body["message"], "Invalid credentials");
+}
+
+#[tokio::test]
+async fn authenticated_requests_only_use_the_repository() {
+    let app = app();
+    let (_, body) = post(&app, "/api/auth/register", registration("jane@example.com")).await;
+    let token = body["data"]["token"].as_str().unwrap();
+
+    let (status, body) = get(&app, "/api/users/me", token).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["email"], "jane@example.com");
+
+    // The role check goes through the repository too
+    let (status, body) = get(&app, "/api/admin/users", token).await;
+    assert_eq!(status, 403);
+    assert_eq!(body["message"], "Admin access required");
+}
@@ -0,0 +1,488 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{spawn_app, PASSWORD};
+use rust_web_app::{config::Settings, utils::auth::JwtKeys};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn register_returns_token_and_user() {
+    let app = spawn_app().await;
+
+    let response = app.register("jane@example.com", "Jane Doe").await;
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["token"].as_str().is_some());
+    assert_eq!(body["data"]["user"]["email"], "jane@example.com");
+    assert_eq!(body["data"]["user"]["role"], "user");
+    assert!(body["data"]["user"]["created_at"].as_str().is_some());
+    assert!(body["data"]["user"].get("password_hash").is_none());
+}
+
+#[tokio::test]
+async fn register_rejects_duplicate_email_regardless_of_case() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app.register("Jane@Example.com", "Jane Again").await;
+
+    assert_eq!(response.status(), 409);
+}
+
+#[tokio::test]
+async fn concurrent_registrations_for_one_email_conflict_cleanly() {
+    let app = spawn_app().await;
+
+    let (first, second) = tokio::join!(
+        app.register("jane@example.com", "Jane Doe"),
+        app.register("jane@example.com", "Jane Doe"),
+    );
+
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [200, 409]);
+}
+
+#[tokio::test]
+async fn register_reports_field_errors() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "not-an-email", "password": "short", "name": "J" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "VALIDATION_ERROR");
+    for field in ["email", "password", "name"] {
+        assert!(
+            body["fields"][field].is_array(),
+            "missing error for {}",
+            field
+        );
+    }
+}
+
+#[tokio::test]
+async fn register_reports_each_invalid_field_with_its_own_message() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "not-an-email", "password": "short", "name": "Jane" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["fields"],
+        json!({
+            "email": ["Invalid email address"],
+            "password": ["Password must be at least 8 characters"],
+        })
+    );
+}
+
+#[tokio::test]
+async fn malformed_bodies_use_the_error_envelope() {
+    let app = spawn_app().await;
+
+    let syntax = app
+        .post("/api/auth/register")
+        .header("content-type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+    let wrong_content_type = app
+        .post("/api/auth/register")
+        .header("content-type", "text/plain")
+        .body(r#"{"email": "jane@example.com"}"#)
+        .send()
+        .await
+        .unwrap();
+    let missing_body = app
+        .post("/api/auth/register")
+        .header("content-type", "application/json")
+        .send()
+        .await
+        .unwrap();
+    let wrong_shape = app
+        .post("/api/auth/register")
+        .json(&json!({ "email": "jane@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    for (response, status, error) in [
+        (syntax, 400, "BAD_REQUEST"),
+        (wrong_content_type, 400, "BAD_REQUEST"),
+        (missing_body, 400, "BAD_REQUEST"),
+        (wrong_shape, 422, "VALIDATION_ERROR"),
+    ] {
+        assert_eq!(response.status(), status);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"], error);
+        assert!(body["message"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn change_password_rejects_an_unchanged_password_on_the_field() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["fields"]["new_password"],
+        json!(["New password must differ from the current password"])
+    );
+    assert!(body["fields"]["__all__"].is_null());
+}
+
+#[tokio::test]
+async fn login_succeeds_with_valid_credentials() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let response = app.login("jane@example.com", PASSWORD).await;
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["data"]["token"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password_and_unknown_email() {
+    let app = spawn_app().await;
+    app.register_user("jane@example.com").await;
+
+    let wrong_password = app.login("jane@example.com", "wrong-password").await;
+    let unknown_email = app.login("nobody@example.com", PASSWORD).await;
+
+    assert_eq!(wrong_password.status(), 401);
+    assert_eq!(unknown_email.status(), 401);
+
+    // Nothing in the body tells the two apart either
+    let wrong_password: Value = wrong_password.json().await.unwrap();
+    let unknown_email: Value = unknown_email.json().await.unwrap();
+    assert_eq!(wrong_password["message"], "Invalid credentials");
+    assert_eq!(wrong_password["message"], unknown_email["message"]);
+    assert_eq!(wrong_password["error"], unknown_email["error"]);
+}
+
+#[tokio::test]
+async fn me_requires_a_valid_token() {
+    let app = spawn_app().await;
+
+    let missing = app.get("/api/users/me").send().await.unwrap();
+    let garbage = app
+        .get("/api/users/me")
+        .bearer_auth("not-a-token")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(missing.status(), 401);
+    assert_eq!(garbage.status(), 401);
+}
+
+#[tokio::test]
+async fn me_returns_the_current_user() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["email"], "jane@example.com");
+    // Owners also see when their account was last changed
+    assert!(body["data"]["updated_at"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn user_profile_by_id_exposes_only_public_fields() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let me: Value = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = me["data"]["id"].as_str().unwrap();
+
+    let response = app.get(&format!("/api/users/{}", id)).send().await.unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["id"], id);
+    assert_eq!(body["data"]["name"], me["data"]["name"]);
+    assert!(body["data"]["created_at"].is_string());
+    for private in ["email", "role", "email_verified", "updated_at", "is_self"] {
+        assert!(body["data"].get(private).is_none(), "{} exposed", private);
+    }
+}
+
+#[tokio::test]
+async fn user_profile_by_id_is_404_for_unknown_and_malformed_ids() {
+    let app = spawn_app().await;
+
+    for id in [uuid::Uuid::new_v4().to_string(), "not-a-uuid".to_string()] {
+        let response = app.get(&format!("/api/users/{}", id)).send().await.unwrap();
+        assert_eq!(response.status(), 404, "{}", id);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["message"], "User not found");
+    }
+}
+
+#[tokio::test]
+async fn update_profile_only_changes_provided_fields() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .patch("/api/users/me")
+        .bearer_auth(&token)
+        .json(&json!({ "name": "Jane Smith" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["name"], "Jane Smith");
+    assert_eq!(body["data"]["email"], "jane@example.com");
+}
+
+#[tokio::test]
+async fn update_profile_rejects_empty_body() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .patch("/api/users/me")
+        .bearer_auth(&token)
+        .json(&json!({}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn update_profile_rejects_email_taken_in_another_case() {
+    let app = spawn_app().await;
+    app.register_user("taken@example.com").await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .patch("/api/users/me")
+        .bearer_auth(&token)
+        .json(&json!({ "email": "TAKEN@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 409);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "CONFLICT");
+    assert_eq!(body["message"], "Email is already in use");
+    assert_eq!(body["fields"]["email"], json!(["is already in use"]));
+}
+
+#[tokio::test]
+async fn change_password_revokes_existing_tokens() {
+    let app = spawn_app().await;
+    let old_token = app.register_user("jane@example.com").await;
+
+    // Tokens are revoked by issue time, which only has second precision
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&old_token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": "new-password-123" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let new_token = body["data"]["token"].as_str().unwrap();
+
+    let with_old = app
+        .get("/api/users/me")
+        .bearer_auth(&old_token)
+        .send()
+        .await
+        .unwrap();
+    let with_new = app
+        .get("/api/users/me")
+        .bearer_auth(new_token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(with_old.status(), 401);
+    assert_eq!(with_new.status(), 200);
+    assert_eq!(
+        app.login("jane@example.com", "new-password-123")
+            .await
+            .status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn change_password_rejects_reusing_the_current_password() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .put("/api/users/me/password")
+        .bearer_auth(&token)
+        .json(&json!({ "current_password": PASSWORD, "new_password": PASSWORD }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn deleted_account_is_locked_out_and_email_can_be_reused() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+
+    let response = app
+        .delete("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let me = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(me.status(), 401);
+    assert_eq!(app.login("jane@example.com", PASSWORD).await.status(), 401);
+    assert_eq!(
+        app.register("jane@example.com", "Jane Again")
+            .await
+            .status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn reregistering_a_deleted_email_creates_a_separate_account() {
+    let app = spawn_app().await;
+    let old_token = app.register_user("jane@example.com").await;
+    let response = app
+        .delete("/api/users/me")
+        .bearer_auth(&old_token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let new_token = app.register_user("jane@example.com").await;
+
+    // The deleted row is kept for recovery, and its tokens don't carry over
+    let accounts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE email = $1")
+        .bind("jane@example.com")
+        .fetch_one(&app.db)
+        .await
+        .unwrap();
+    assert_eq!(accounts, 2);
+    for (token, status) in [(old_token, 401), (new_token, 200)] {
+        let response = app
+            .get("/api/users/me")
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[tokio::test]
+async fn active_users_view_has_every_users_column() {
+    let app = spawn_app().await;
+    let columns = |relation: &'static str| {
+        sqlx::query_scalar::<_, String>(
+            "SELECT column_name::text FROM information_schema.columns \
+             WHERE table_name = $1 ORDER BY ordinal_position",
+        )
+        .bind(relation)
+        .fetch_all(&app.db)
+    };
+
+    // Catches migrations that add a users column without recreating the view
+    assert_eq!(
+        columns("active_users").await.unwrap(),
+        columns("users").await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn tokens_signed_with_another_secret_are_rejected() {
+    let app = spawn_app().await;
+    let token = app.register_user("jane@example.com").await;
+    let body: Value = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let user_id = body["data"]["id"].as_str().unwrap();
+
+    // "secret" used to be the middleware's fallback when the env var was unset
+    let mut settings = Settings::new().unwrap().application;
+    settings.jwt_secret = "secret".to_string();
+    let forged = JwtKeys::new(&settings)
+        .unwrap()
+        .create_jwt(user_id)
+        .unwrap();
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&forged)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 401);
+}
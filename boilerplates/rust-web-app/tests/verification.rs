@@ -0,0 +1,113 @@
+mod common;
+
+use common::{spawn_app_with, TestApp};
+use serde_json::{json, Value};
+
+async fn spawn_app_requiring_verification() -> TestApp {
+    spawn_app_with(|settings| settings.application.require_email_verification = true).await
+}
+
+/// Reads the token from the most recent verification email to `to`.
+async fn verification_token(app: &TestApp, to: &str) -> String {
+    let email = app.mailer.wait_for(to).await;
+    email.body.rsplit(' ').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn unverified_tokens_are_rejected_until_the_email_is_verified() {
+    let app = spawn_app_requiring_verification().await;
+    let response = app.register("jane@example.com", "Jane Doe").await;
+    let body: Value = response.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap().to_string();
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "EMAIL_NOT_VERIFIED");
+
+    let verification = verification_token(&app, "jane@example.com").await;
+    let response = app
+        .get(&format!("/api/auth/verify?token={}", verification))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = app
+        .get("/api/users/me")
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["email_verified"], true);
+}
+
+#[tokio::test]
+async fn resent_verification_token_verifies_the_account() {
+    let app = spawn_app_requiring_verification().await;
+    app.register("jane@example.com", "Jane Doe").await;
+    let first = verification_token(&app, "jane@example.com").await;
+
+    let response = app
+        .post("/api/auth/resend-verification")
+        .json(&json!({ "email": "jane@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut resent = first.clone();
+    for _ in 0..50 {
+        resent = verification_token(&app, "jane@example.com").await;
+        if resent != first {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_ne!(resent, first);
+
+    let response = app
+        .get(&format!("/api/auth/verify?token={}", resent))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        app.login("jane@example.com", common::PASSWORD)
+            .await
+            .status(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn resend_verification_matches_the_email_in_any_case() {
+    let app = spawn_app_requiring_verification().await;
+    app.register("Jane.Doe@Example.com", "Jane Doe").await;
+    let first = verification_token(&app, "Jane.Doe@Example.com").await;
+
+    let response = app
+        .post("/api/auth/resend-verification")
+        .json(&json!({ "email": "jane.doe@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut resent = first.clone();
+    for _ in 0..50 {
+        resent = verification_token(&app, "Jane.Doe@Example.com").await;
+        if resent != first {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_ne!(resent, first);
+}